@@ -0,0 +1,199 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use warp::http::StatusCode;
+use warp::Reply;
+
+use crate::app::auth::handlers::{login_user, new_session_cookie, register_user};
+use crate::app::core::api_token_store::{
+    generate_api_token, hash_api_token, ApiToken, ApiTokenStoreError, ApiTokens,
+};
+use crate::app::core::authenticator::{
+    expired_session_cookie, remove_session, SessionIdT, UserSessions,
+};
+use crate::app::core::backend_client::{Backend, BackendError};
+use crate::app::core::csrf::{with_csrf_cookie, CsrfToken, CSRF_HEADER};
+use crate::app::core::error::AppError;
+use crate::app::core::models::{
+    ApiTokenInfo, CreatedApiToken, NewApiToken, PublicUserCred, PublicUserWithId, UserHistoryT,
+    UserIdT,
+};
+use crate::app::core::rate_limiter::LoginLimiter;
+use crate::app::core::session_store::unix_now;
+use crate::app::core::summary_store::Summaries;
+use crate::app::home::handlers::{analyze_news, load_history, with_stored_summary};
+
+/// Longest token name, in characters.
+const API_TOKEN_NAME_MAX_CHARS: usize = 64;
+
+/// Live tokens a user can have at once.
+const API_TOKENS_PER_USER: usize = 20;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AnalyzeQuery {
+    #[schemars(description = "Url artikel berita")]
+    pub url: String,
+}
+
+/// The user as JSON, with the cookie of their new session and a CSRF token,
+/// in its cookie and in `X-CSRF-Token` for the client to send back.
+fn logged_in_reply(
+    mut user: PublicUserWithId,
+    session_id: &SessionIdT,
+    status: StatusCode,
+) -> warp::reply::Response {
+    user.password = None;
+    let csrf_token = CsrfToken::mint();
+    let reply = warp::reply::with_header(
+        warp::reply::with_header(
+            warp::reply::with_status(warp::reply::json(&user), status),
+            "set-cookie",
+            new_session_cookie(session_id),
+        ),
+        CSRF_HEADER,
+        csrf_token.value.as_str(),
+    );
+    with_csrf_cookie(reply, &csrf_token)
+}
+
+pub async fn handle_register(
+    user_cred: PublicUserCred,
+    backend: Backend,
+    sessions: UserSessions,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (user, session_id) = register_user(user_cred, backend, sessions).await?;
+    Ok(logged_in_reply(user, &session_id, StatusCode::CREATED))
+}
+
+pub async fn handle_login(
+    user_cred: PublicUserCred,
+    backend: Backend,
+    sessions: UserSessions,
+    limiter: LoginLimiter,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let (user, session_id) = login_user(user_cred, backend, sessions, limiter).await?;
+    Ok(logged_in_reply(user, &session_id, StatusCode::OK))
+}
+
+pub async fn handle_logout(
+    session_id: Option<String>,
+    sessions: UserSessions,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    if let Some(session_id) = session_id {
+        remove_session(&session_id, sessions.clone()).await?;
+    }
+
+    Ok(warp::reply::with_header(
+        StatusCode::NO_CONTENT,
+        "set-cookie",
+        expired_session_cookie(),
+    )
+    .into_response())
+}
+
+pub async fn handle_analyze(
+    query: AnalyzeQuery,
+    user_id: UserIdT,
+    backend: Backend,
+    summaries: Summaries,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    match analyze_news(query.url, user_id, backend, summaries).await {
+        Ok(content) => Ok(warp::reply::json(&content).into_response()),
+        Err(BackendError::Unavailable { retry_after_secs }) => {
+            tracing::warn!(retry_after_secs, "analyzer unavailable, not calling it");
+            Err(AppError::BackendUnavailable { retry_after_secs }.into())
+        }
+        // The backend could not parse the url it was given.
+        Err(BackendError::Status(status)) if status.is_client_error() => {
+            tracing::debug!(%status, "analyzer refused the url");
+            Err(AppError::InvalidInput.into())
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "analyzing news failed");
+            Err(AppError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_history(
+    user_id: UserIdT,
+    backend: Backend,
+    summaries: Summaries,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let mut history = Vec::new();
+    for news in load_history(&user_id, &backend).await? {
+        history.push(with_stored_summary(news, &summaries).await);
+    }
+    Ok(warp::reply::json(&UserHistoryT(Some(history))).into_response())
+}
+
+// -- BLOCK: API_TOKENS
+fn token_store_rejection(store_error: ApiTokenStoreError) -> warp::Rejection {
+    AppError::from(store_error).into()
+}
+
+pub async fn handle_list_tokens(
+    user_id: UserIdT,
+    tokens: ApiTokens,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let listed = tokens.list(&user_id).await.map_err(token_store_rejection)?;
+    let listed = listed.iter().map(ApiTokenInfo::from).collect::<Vec<_>>();
+    Ok(warp::reply::json(&listed).into_response())
+}
+
+pub async fn handle_create_token(
+    user_id: UserIdT,
+    new_token: NewApiToken,
+    tokens: ApiTokens,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let name = new_token.name.trim();
+    if name.is_empty() || name.chars().count() > API_TOKEN_NAME_MAX_CHARS {
+        return Err(AppError::InvalidInput.into());
+    }
+    let owned = tokens.list(&user_id).await.map_err(token_store_rejection)?;
+    if owned.len() >= API_TOKENS_PER_USER {
+        tracing::warn!(%user_id, "api token limit reached");
+        return Err(AppError::InvalidInput.into());
+    }
+
+    let secret = generate_api_token();
+    let api_token = ApiToken {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        name: name.to_string(),
+        token_hash: hash_api_token(&secret),
+        created_at: unix_now(),
+        last_used: None,
+    };
+    let info = ApiTokenInfo::from(&api_token);
+    tokens
+        .insert(api_token)
+        .await
+        .map_err(token_store_rejection)?;
+    tracing::info!(token_id = %info.id, "api token created");
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&CreatedApiToken {
+            info,
+            token: secret,
+        }),
+        StatusCode::CREATED,
+    )
+    .into_response())
+}
+
+pub async fn handle_revoke_token(
+    token_id: String,
+    user_id: UserIdT,
+    tokens: ApiTokens,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let revoked = tokens
+        .revoke(&user_id, &token_id)
+        .await
+        .map_err(token_store_rejection)?;
+    if !revoked {
+        return Err(AppError::NotFound.into());
+    }
+    tracing::info!(%token_id, "api token revoked");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+// -- ENDBLOCK: API_TOKENS
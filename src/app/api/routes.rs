@@ -0,0 +1,537 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use percent_encoding::percent_decode_str;
+use warp::http::StatusCode;
+use warp::Filter;
+
+use crate::app::auth::routes::with_limited_credentials;
+use crate::app::core::api_token_store::{with_api_tokens, ApiTokens};
+use crate::app::core::authenticator::{
+    with_session_cookie_only, with_session_id, with_sessions, with_user_auth, UserSessions,
+};
+use crate::app::core::backend_client::{with_backend, Backend};
+use crate::app::core::csrf::csrf_protect;
+use crate::app::core::error::AppError;
+use crate::app::core::models::{
+    ApiTokenInfo, CreatedApiToken, NewApiToken, NewsContent, PublicUserCred, PublicUserWithId,
+    UserHistoryT, UserIdT,
+};
+use crate::app::core::openapi::{openapi_document, schema_of, ApiAuth, ApiOperation};
+use crate::app::core::rate_limiter::{with_login_limiter, LoginLimiter};
+use crate::app::core::summary_store::{with_summaries, Summaries};
+use crate::app::home::handlers::{edit_history, HistoryEdit, NoteForm, PinForm};
+
+use super::handlers::{
+    handle_analyze, handle_create_token, handle_history, handle_list_tokens, handle_login,
+    handle_logout, handle_register, handle_revoke_token, AnalyzeQuery,
+};
+
+/// Largest JSON body the API reads.
+const API_BODY_MAX_BYTES: u64 = 4 * 1024;
+
+/// The interactive docs, a static page reading `/v1/openapi.json`.
+const API_DOCS_PAGE: &str = include_str!("../../assets/api_docs.html");
+
+/// JSON routes, mounted under `/v1` by `main` and recovered with
+/// `json_on_reject`. They authenticate with an API token or the same session
+/// cookie as the pages. Bodies must say `application/json`, which a
+/// cross-site form cannot send, and every change made with the cookie also
+/// needs the `X-CSRF-Token` given by login and register, since a bodiless
+/// `DELETE` or `POST` can be sent cross-site.
+///
+/// Every route has an [`ApiOperation`] next to it, `/v1/openapi.json`
+/// documents the ones in [`API_OPERATIONS`].
+pub fn api_routes(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
+    login_limiter: LoginLimiter,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    register_route(backend.clone(), sessions.clone(), login_limiter.clone())
+        .or(login_route(
+            backend.clone(),
+            sessions.clone(),
+            login_limiter.clone(),
+        ))
+        .or(logout_route(sessions.clone()))
+        .or(analyze_route(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+            summaries.clone(),
+        ))
+        .or(history_route(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+            summaries.clone(),
+        ))
+        .or(delete_history_entry(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(clear_history(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(pin_history_entry(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(set_history_note(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(list_tokens(sessions.clone(), tokens.clone()))
+        .or(create_token(sessions.clone(), tokens.clone()))
+        .or(revoke_token(sessions.clone(), tokens.clone()))
+        .or(openapi_route())
+        .or(docs_route())
+}
+
+/// The operations of the routes in [`api_routes`], in the order they are tried.
+pub const API_OPERATIONS: &[ApiOperation] = &[
+    REGISTER,
+    LOGIN,
+    LOGOUT,
+    ANALYZE,
+    HISTORY,
+    DELETE_HISTORY_ENTRY,
+    CLEAR_HISTORY,
+    PIN_HISTORY_ENTRY,
+    SET_HISTORY_NOTE,
+    LIST_TOKENS,
+    CREATE_TOKEN,
+    REVOKE_TOKEN,
+    OPENAPI,
+];
+
+/// An `{id}` path segment, percent-decoded. A decoded `/` or dot segment
+/// would walk out of the backend path the id is put into, so it does not
+/// match.
+struct PathId(String);
+
+impl FromStr for PathId {
+    type Err = ();
+
+    fn from_str(segment: &str) -> Result<Self, Self::Err> {
+        let id = percent_decode_str(segment).decode_utf8().map_err(|_| ())?;
+        if id.is_empty() || id.contains(['/', '\\']) || id == "." || id == ".." {
+            return Err(());
+        }
+        Ok(PathId(id.into_owned()))
+    }
+}
+
+/// The JSON body, `warp::body::json` alone also reads a body without any
+/// `Content-Type`.
+fn with_json_body<T>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
+where
+    T: serde::de::DeserializeOwned + Send,
+{
+    warp::header::optional::<String>("content-type")
+        .and_then(|content_type: Option<String>| async move {
+            let is_json = content_type.is_some_and(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+            });
+            if is_json {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(AppError::UnsupportedMediaType))
+            }
+        })
+        .untuple_one()
+        .and(warp::body::content_length_limit(API_BODY_MAX_BYTES))
+        .and(warp::body::json())
+}
+
+// -- BLOCK: AUTH
+const REGISTER: ApiOperation = ApiOperation {
+    method: "POST",
+    path: "/v1/auth/register",
+    tag: "auth",
+    summary: "Daftar akun baru dan masuk",
+    auth: ApiAuth::Public,
+    query: None,
+    body: Some(schema_of::<PublicUserCred>),
+    status: 201,
+    reply: Some(schema_of::<PublicUserWithId>),
+};
+
+/// `POST /v1/auth/register`, `201 Created` with the user.
+fn register_route(
+    backend: Backend,
+    sessions: UserSessions,
+    limiter: LoginLimiter,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("auth" / "register")
+        .and(warp::post())
+        .and(with_limited_credentials(with_json_body(), limiter))
+        .and(with_backend(backend.clone()))
+        .and(with_sessions(sessions.clone()))
+        .and_then(handle_register)
+}
+
+const LOGIN: ApiOperation = ApiOperation {
+    method: "POST",
+    path: "/v1/auth/login",
+    tag: "auth",
+    summary: "Masuk, membalas cookie session_id",
+    auth: ApiAuth::Public,
+    query: None,
+    body: Some(schema_of::<PublicUserCred>),
+    status: 200,
+    reply: Some(schema_of::<PublicUserWithId>),
+};
+
+/// `POST /v1/auth/login`, the user and their session cookie.
+fn login_route(
+    backend: Backend,
+    sessions: UserSessions,
+    limiter: LoginLimiter,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("auth" / "login")
+        .and(warp::post())
+        .and(with_limited_credentials(with_json_body(), limiter.clone()))
+        .and(with_backend(backend.clone()))
+        .and(with_sessions(sessions.clone()))
+        .and(with_login_limiter(limiter.clone()))
+        .and_then(handle_login)
+}
+
+const LOGOUT: ApiOperation = ApiOperation {
+    method: "POST",
+    path: "/v1/auth/logout",
+    tag: "auth",
+    summary: "Keluar dari sesi cookie",
+    auth: ApiAuth::Public,
+    query: None,
+    body: None,
+    status: 204,
+    reply: None,
+};
+
+fn logout_route(
+    sessions: UserSessions,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("auth" / "logout")
+        .and(warp::post())
+        .and(csrf_protect())
+        .and(with_session_id())
+        .and(with_sessions(sessions.clone()))
+        .and_then(handle_logout)
+}
+// -- ENDBLOCK: AUTH
+
+const ANALYZE: ApiOperation = ApiOperation {
+    method: "GET",
+    path: "/v1/analyze",
+    tag: "berita",
+    summary: "Analisis berita dari url-nya",
+    auth: ApiAuth::TokenOrCookie,
+    query: Some(schema_of::<AnalyzeQuery>),
+    body: None,
+    status: 200,
+    reply: Some(schema_of::<NewsContent>),
+};
+
+/// `GET /v1/analyze?url=`, the parsed `NewsContent`.
+fn analyze_route(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("analyze")
+        .and(warp::get())
+        .and(warp::query())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .and(with_summaries(summaries.clone()))
+        .and_then(handle_analyze)
+}
+
+// -- BLOCK: HISTORY
+const HISTORY: ApiOperation = ApiOperation {
+    method: "GET",
+    path: "/v1/me/history",
+    tag: "riwayat",
+    summary: "Riwayat analisis, terlama lebih dulu",
+    auth: ApiAuth::TokenOrCookie,
+    query: None,
+    body: None,
+    status: 200,
+    reply: Some(schema_of::<UserHistoryT>),
+};
+
+/// `GET /v1/me/history`, the whole `UserHistoryT`, oldest first, with the
+/// stored summaries.
+fn history_route(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("me" / "history")
+        .and(warp::get())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .and(with_summaries(summaries.clone()))
+        .and_then(handle_history)
+}
+
+const DELETE_HISTORY_ENTRY: ApiOperation = ApiOperation {
+    method: "DELETE",
+    path: "/v1/me/history/{id}",
+    tag: "riwayat",
+    summary: "Hapus satu entri riwayat",
+    auth: ApiAuth::TokenOrCookie,
+    query: None,
+    body: None,
+    status: 204,
+    reply: None,
+};
+
+fn delete_history_entry(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path!("me" / "history" / PathId)
+        .and(warp::delete())
+        .map(|PathId(content_id)| content_id)
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(csrf_protect())
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |content_id: ContentID, user_id: UserIdT, backend: Backend| async move {
+                edit_history(user_id, HistoryEdit::Delete { content_id }, backend).await?;
+                Ok::<_, warp::Rejection>(StatusCode::NO_CONTENT)
+            },
+        )
+}
+
+const CLEAR_HISTORY: ApiOperation = ApiOperation {
+    method: "DELETE",
+    path: "/v1/me/history",
+    tag: "riwayat",
+    summary: "Hapus seluruh riwayat",
+    auth: ApiAuth::TokenOrCookie,
+    query: None,
+    body: None,
+    status: 204,
+    reply: None,
+};
+
+fn clear_history(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("me" / "history")
+        .and(warp::delete())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(csrf_protect())
+        .and(with_backend(backend.clone()))
+        .and_then(|user_id: UserIdT, backend: Backend| async move {
+            edit_history(user_id, HistoryEdit::Clear, backend).await?;
+            Ok::<_, warp::Rejection>(StatusCode::NO_CONTENT)
+        })
+}
+
+const PIN_HISTORY_ENTRY: ApiOperation = ApiOperation {
+    method: "PUT",
+    path: "/v1/me/history/{id}/pin",
+    tag: "riwayat",
+    summary: "Sematkan atau lepas entri riwayat",
+    auth: ApiAuth::TokenOrCookie,
+    query: None,
+    body: Some(schema_of::<PinForm>),
+    status: 204,
+    reply: None,
+};
+
+/// `PUT /v1/me/history/{id}/pin` with `{"pinned": bool}`.
+fn pin_history_entry(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path!("me" / "history" / PathId / "pin")
+        .and(warp::put())
+        .map(|PathId(content_id)| content_id)
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(csrf_protect())
+        .and(with_json_body::<PinForm>())
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |content_id: ContentID, user_id: UserIdT, form: PinForm, backend: Backend| async move {
+                let edit = HistoryEdit::Pin {
+                    content_id,
+                    pinned: form.pinned,
+                };
+                edit_history(user_id, edit, backend).await?;
+                Ok::<_, warp::Rejection>(StatusCode::NO_CONTENT)
+            },
+        )
+}
+
+const SET_HISTORY_NOTE: ApiOperation = ApiOperation {
+    method: "PUT",
+    path: "/v1/me/history/{id}/note",
+    tag: "riwayat",
+    summary: "Ubah catatan entri riwayat",
+    auth: ApiAuth::TokenOrCookie,
+    query: None,
+    body: Some(schema_of::<NoteForm>),
+    status: 204,
+    reply: None,
+};
+
+/// `PUT /v1/me/history/{id}/note` with `{"note": string | null}`.
+fn set_history_note(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path!("me" / "history" / PathId / "note")
+        .and(warp::put())
+        .map(|PathId(content_id)| content_id)
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(csrf_protect())
+        .and(with_json_body::<NoteForm>())
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |content_id: ContentID, user_id: UserIdT, note: NoteForm, backend: Backend| async move {
+                edit_history(user_id, HistoryEdit::Note { content_id, note }, backend).await?;
+                Ok::<_, warp::Rejection>(StatusCode::NO_CONTENT)
+            },
+        )
+}
+// -- ENDBLOCK: HISTORY
+
+// -- BLOCK: API_TOKENS
+const LIST_TOKENS: ApiOperation = ApiOperation {
+    method: "GET",
+    path: "/v1/me/tokens",
+    tag: "token",
+    summary: "Daftar token API",
+    auth: ApiAuth::Cookie,
+    query: None,
+    body: None,
+    status: 200,
+    reply: Some(schema_of::<Vec<ApiTokenInfo>>),
+};
+
+/// `GET /v1/me/tokens`, the tokens of the user with their last use.
+fn list_tokens(
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("me" / "tokens")
+        .and(warp::get())
+        .and(with_session_cookie_only(sessions.clone()))
+        .and(with_api_tokens(tokens.clone()))
+        .and_then(handle_list_tokens)
+}
+
+const CREATE_TOKEN: ApiOperation = ApiOperation {
+    method: "POST",
+    path: "/v1/me/tokens",
+    tag: "token",
+    summary: "Buat token API, token hanya ditampilkan sekali",
+    auth: ApiAuth::Cookie,
+    query: None,
+    body: Some(schema_of::<NewApiToken>),
+    status: 201,
+    reply: Some(schema_of::<CreatedApiToken>),
+};
+
+/// `POST /v1/me/tokens` with `{"name": string}`, `201 Created` with the
+/// token, shown only in this reply.
+fn create_token(
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("me" / "tokens")
+        .and(warp::post())
+        .and(with_session_cookie_only(sessions.clone()))
+        .and(csrf_protect())
+        .and(with_json_body())
+        .and(with_api_tokens(tokens.clone()))
+        .and_then(handle_create_token)
+}
+
+const REVOKE_TOKEN: ApiOperation = ApiOperation {
+    method: "DELETE",
+    path: "/v1/me/tokens/{id}",
+    tag: "token",
+    summary: "Cabut token API",
+    auth: ApiAuth::Cookie,
+    query: None,
+    body: None,
+    status: 204,
+    reply: None,
+};
+
+/// `DELETE /v1/me/tokens/{id}`.
+fn revoke_token(
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("me" / "tokens" / PathId)
+        .and(warp::delete())
+        .map(|PathId(token_id)| token_id)
+        .and(with_session_cookie_only(sessions.clone()))
+        .and(csrf_protect())
+        .and(with_api_tokens(tokens.clone()))
+        .and_then(handle_revoke_token)
+}
+// -- ENDBLOCK: API_TOKENS
+
+// -- BLOCK: DOCS
+const OPENAPI: ApiOperation = ApiOperation {
+    method: "GET",
+    path: "/v1/openapi.json",
+    tag: "dokumentasi",
+    summary: "Dokumen OpenAPI ini",
+    auth: ApiAuth::Public,
+    query: None,
+    body: None,
+    status: 200,
+    reply: None,
+};
+
+/// `GET /v1/openapi.json`, the document of [`API_OPERATIONS`], public like
+/// the docs page reading it.
+fn openapi_route() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let document = Arc::new(openapi_document(
+        "Sigekria API",
+        env!("CARGO_PKG_VERSION"),
+        API_OPERATIONS,
+    ));
+    warp::path!("openapi.json")
+        .and(warp::get())
+        .map(move || warp::reply::json(&*document))
+}
+
+/// `GET /v1/docs`, the interactive API docs, without any CDN.
+fn docs_route() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::html(API_DOCS_PAGE))
+}
+// -- ENDBLOCK: DOCS
@@ -1,121 +1,151 @@
-use std::future::Future;
-
-use crate::app::core::authenticator::{add_user_to_sessions, UserSessions};
-use crate::app::core::error::{
-    BuildResponseError, IncorrectPassword, RegisterExistingUser, UserNotExist,
+use crate::app::core::app_config::load_config;
+use crate::app::core::authenticator::{
+    add_user_to_sessions, expired_session_cookie, remove_session, remove_user_sessions,
+    session_cookie, SessionIdT, UserSessions,
 };
-use crate::app::core::models::PublicUserWithId;
+use crate::app::core::backend_client::{Backend, BackendError};
+use crate::app::core::error::AppError;
+use crate::app::core::models::{PublicUserCred, PublicUserWithId, UserIdT};
+use crate::app::core::rate_limiter::LoginLimiter;
+use crate::app::core::session_store::SessionPolicy;
+
+pub fn new_session_cookie(session_id: &str) -> String {
+    let policy = SessionPolicy::from_config(&load_config());
+    session_cookie(
+        session_id,
+        policy.idle_timeout_secs.min(policy.max_lifetime_secs),
+    )
+}
+
+/// Registers the user and opens their session, shared by the register page
+/// and the JSON API.
+pub async fn register_user(
+    user_cred: PublicUserCred,
+    backend: Backend,
+    sessions: UserSessions,
+) -> Result<(PublicUserWithId, SessionIdT), warp::Rejection> {
+    tracing::debug!(?user_cred, "registering user");
+
+    match backend.register(&user_cred).await {
+        Ok(new_user) => {
+            tracing::info!(user_id = %new_user.id, "user registered");
+
+            // -- BLOCK: ADD_USER_TO_SESSIONS
+            let new_session_id =
+                add_user_to_sessions(new_user.id.clone(), sessions.clone()).await?;
+            // -- ENDBLOCK: ADD_USER_TO_SESSIONS
+            Ok((new_user, new_session_id))
+        }
+        Err(BackendError::Status(reqwest::StatusCode::CONFLICT)) => {
+            tracing::warn!("register with an email already taken");
+            Err(AppError::EmailTaken.into())
+        }
+        Err(backend_error) => {
+            tracing::error!(error = %backend_error, "backend failed to register user");
+            Err(AppError::from(backend_error).into())
+        }
+    }
+}
+
+/// Checks the credentials and opens a session, shared by the login page and
+/// the JSON API.
+pub async fn login_user(
+    user_cred: PublicUserCred,
+    backend: Backend,
+    sessions: UserSessions,
+    limiter: LoginLimiter,
+) -> Result<(PublicUserWithId, SessionIdT), warp::Rejection> {
+    tracing::debug!(?user_cred, "logging user in");
+
+    match backend.login(&user_cred).await {
+        Ok(user) => {
+            tracing::info!(user_id = %user.id, "user logged in");
+
+            // -- BLOCK: ADD_USER_TO_SESSIONS
+            limiter.record_success(&user_cred.email);
+            let new_session_id = add_user_to_sessions(user.id.clone(), sessions.clone()).await?;
+            // -- ENDBLOCK: ADD_USER_TO_SESSIONS
+            Ok((user, new_session_id))
+        }
+        Err(BackendError::Status(reqwest::StatusCode::NOT_FOUND)) => {
+            tracing::warn!("login with an unknown email");
+            Err(AppError::UserNotExist.into())
+        }
+        Err(BackendError::Status(reqwest::StatusCode::UNAUTHORIZED)) => {
+            tracing::warn!("login with an incorrect password");
+            limiter.record_failure(&user_cred.email);
+            Err(AppError::IncorrectPassword.into())
+        }
+        Err(backend_error) => {
+            tracing::error!(error = %backend_error, "backend failed to log user in");
+            Err(AppError::from(backend_error).into())
+        }
+    }
+}
 
 pub async fn handle_register(
-    response: impl Future<Output = Result<reqwest::Response, reqwest::Error>>,
+    user_cred: PublicUserCred,
+    backend: Backend,
     sessions: UserSessions,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    match response.await {
-        Ok(result) => match result.status() {
-            reqwest::StatusCode::OK => match result.json::<PublicUserWithId>().await {
-                Ok(new_user) => {
-                    println!(
-                        "__handle_register__: When register success, returning user -> {:?}",
-                        new_user
-                    );
+    let (_, new_session_id) = register_user(user_cred, backend, sessions).await?;
+    logged_in_response(&new_session_id)
+}
 
-                    // -- BLOCK: ADD_USER_TO_SESSIONS
-                    let new_session_id =
-                        add_user_to_sessions(new_user.id, sessions.clone()).await?;
-                    // -- ENDBLOCK: ADD_USER_TO_SESSIONS
-                    match warp::http::Response::builder()
-                        .status(warp::http::StatusCode::MOVED_PERMANENTLY.as_u16())
-                        .header("Location", "/home")
-                        .header("HX-Location", "/home")
-                        .header(
-                            "set-cookie",
-                            format!("session_id={new_session_id}; path=/; max-age=34560"),
-                        )
-                        .body("")
-                    {
-                        Ok(r) => Ok(r),
-                        Err(_somehow_error_when_building_response) => {
-                            eprintln!("__handle_login__: Build Response Fail!");
-                            Err(warp::reject::custom(BuildResponseError))
-                        }
-                    }
-                }
-                Err(_deserialize_response_error) => {
-                    println!(
-                        "__register__: deserialize user fail-> {:?}",
-                        _deserialize_response_error
-                    );
-                    Err(warp::reject())
-                }
-            },
-            reqwest::StatusCode::CONFLICT => {
-                eprintln!("__handle_register__: conflict!");
-                Err(warp::reject::custom(RegisterExistingUser))
-            }
-            _other_error => {
-                eprintln!("__handle_register__: unknown!");
-                Err(warp::reject())
-            }
-        },
-        Err(_error_cause_by_invalid_request) => Err(warp::reject()),
+pub async fn handle_login(
+    user_cred: PublicUserCred,
+    backend: Backend,
+    sessions: UserSessions,
+    limiter: LoginLimiter,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (_, new_session_id) = login_user(user_cred, backend, sessions, limiter).await?;
+    logged_in_response(&new_session_id)
+}
+
+fn logged_in_response(
+    session_id: &str,
+) -> Result<warp::http::Response<&'static str>, warp::Rejection> {
+    match warp::http::Response::builder()
+        .status(warp::http::StatusCode::MOVED_PERMANENTLY.as_u16())
+        .header("Location", "/home")
+        .header("HX-Location", "/home")
+        .header("set-cookie", new_session_cookie(session_id))
+        .body("")
+    {
+        Ok(r) => Ok(r),
+        Err(build_error) => Err(AppError::from(build_error).into()),
     }
 }
 
-pub async fn handle_login(
-    response: impl Future<Output = Result<reqwest::Response, reqwest::Error>>,
+pub async fn handle_logout(
+    session_id: Option<String>,
     sessions: UserSessions,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    match response.await {
-        Ok(res) => match res.status() {
-            reqwest::StatusCode::OK => match res.json::<PublicUserWithId>().await {
-                Ok(user) => {
-                    println!(
-                        "__handle_login__: When Login Success get user -> {:?}",
-                        user
-                    );
+    if let Some(session_id) = session_id {
+        remove_session(&session_id, sessions.clone()).await?;
+    }
+
+    logged_out_response()
+}
 
-                    // -- BLOCK: ADD_USER_TO_SESSIONS
-                    let new_session_id = add_user_to_sessions(user.id, sessions.clone()).await?;
-                    // -- ENDBLOCK: ADD_USER_TO_SESSIONS
+pub async fn handle_logout_everywhere(
+    user_id: UserIdT,
+    sessions: UserSessions,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    remove_user_sessions(&user_id, sessions.clone()).await?;
+
+    logged_out_response()
+}
 
-                    match warp::http::Response::builder()
-                        .status(warp::http::StatusCode::MOVED_PERMANENTLY.as_u16())
-                        .header("Location", "/home")
-                        .header("HX-Location", "/home")
-                        .header(
-                            "set-cookie",
-                            format!("session_id={new_session_id}; path=/; max-age=34560"),
-                        )
-                        .body("")
-                    {
-                        Ok(r) => Ok(r),
-                        Err(_somehow_error_when_building_response) => {
-                            eprintln!("__handle_login__: Build Response Fail!");
-                            Err(warp::reject::custom(BuildResponseError))
-                        }
-                    }
-                }
-                Err(_deserialize_response_error) => {
-                    println!(
-                        "__handle_login__: deserialize user fail-> {:?}",
-                        _deserialize_response_error
-                    );
-                    Err(warp::reject())
-                }
-            },
-            reqwest::StatusCode::NOT_FOUND => {
-                eprintln!("__handle_login__: Not Found!");
-                Err(warp::reject::custom(UserNotExist))
-            }
-            reqwest::StatusCode::UNAUTHORIZED => {
-                eprintln!("__handle_login__: Unauthorized!");
-                Err(warp::reject::custom(IncorrectPassword))
-            }
-            _other_error => {
-                eprintln!("__handle_login__: another response error!");
-                Err(warp::reject())
-            }
-        },
-        Err(_error_cause_by_invalid_request) => Err(warp::reject()),
+fn logged_out_response() -> Result<warp::http::Response<&'static str>, warp::Rejection> {
+    match warp::http::Response::builder()
+        .status(warp::http::StatusCode::MOVED_PERMANENTLY.as_u16())
+        .header("Location", "/auth/login")
+        .header("HX-Location", "/auth/login")
+        .header("set-cookie", expired_session_cookie())
+        .body("")
+    {
+        Ok(r) => Ok(r),
+        Err(build_error) => Err(AppError::from(build_error).into()),
     }
 }
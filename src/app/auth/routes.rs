@@ -1,18 +1,28 @@
 use serde_json::json;
+use std::net::IpAddr;
 use warp::Filter;
 
-use crate::app::core::app_config::{load_config, with_app_config, AppConfig};
-use crate::app::core::authenticator::{with_sessions, UserSessions};
-use crate::app::core::http_client::{with_http_client, HttpClient};
+use crate::app::core::api_token_store::ApiTokens;
+use crate::app::core::authenticator::{
+    with_session_id, with_sessions, with_user_auth, UserSessions,
+};
+use crate::app::core::backend_client::{with_backend, Backend};
+use crate::app::core::csrf::{
+    csrf_protect, csrf_protected_form, with_csrf_cookie, with_csrf_token, CsrfToken,
+};
 use crate::app::core::models::PublicUserCred;
+use crate::app::core::rate_limiter::{with_login_limiter, LoginLimiter};
 use crate::app::core::renderer::{render, with_renderer, Renderer, WithTemplate};
+use crate::app::core::request_context::with_client_ip;
 
-use super::handlers::{handle_login, handle_register};
+use super::handlers::{handle_login, handle_logout, handle_logout_everywhere, handle_register};
 
 pub fn auth_routes(
     renderer: Renderer,
-    http_client: HttpClient,
+    backend: Backend,
     sessions: UserSessions,
+    tokens: ApiTokens,
+    login_limiter: LoginLimiter,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let default_redirect = warp::path("auth")
         .and(warp::path::end())
@@ -21,8 +31,18 @@ pub fn auth_routes(
     default_redirect
         .or(register_page(renderer.clone()))
         .or(login_page(renderer.clone()))
-        .or(register_route(http_client.clone(), sessions.clone()))
-        .or(login_route(http_client.clone(), sessions.clone()))
+        .or(register_route(
+            backend.clone(),
+            sessions.clone(),
+            login_limiter.clone(),
+        ))
+        .or(login_route(
+            backend.clone(),
+            sessions.clone(),
+            login_limiter.clone(),
+        ))
+        .or(logout_route(sessions.clone()))
+        .or(logout_everywhere_route(sessions.clone(), tokens.clone()))
 }
 
 fn register_page(
@@ -32,15 +52,22 @@ fn register_page(
         .and(warp::path("register"))
         .and(warp::path::end())
         .and(warp::get())
-        .map(|| WithTemplate {
-            name: "auth_page",
-            value: json!({
-                "title": "AUTH Register",
-                "child_component": "register_page"
-            }),
-        })
+        .and(with_csrf_token())
         .and(with_renderer(renderer.clone()))
-        .map(render)
+        .map(|csrf_token: CsrfToken, renderer: Renderer| {
+            let page = render(
+                WithTemplate {
+                    name: "auth_page",
+                    value: json!({
+                        "title": "AUTH Register",
+                        "child_component": "register_page",
+                        "csrf_token": csrf_token.value
+                    }),
+                },
+                renderer,
+            );
+            with_csrf_cookie(page, &csrf_token)
+        })
 }
 
 fn login_page(
@@ -50,67 +77,103 @@ fn login_page(
         .and(warp::path("login"))
         .and(warp::path::end())
         .and(warp::get())
-        .map(|| WithTemplate {
-            name: "auth_page",
-            value: json!({
-                "title": "Auth Login",
-                "child_component": "login_page"
-            }),
-        })
+        .and(with_csrf_token())
         .and(with_renderer(renderer.clone()))
-        .map(render)
+        .map(|csrf_token: CsrfToken, renderer: Renderer| {
+            let page = render(
+                WithTemplate {
+                    name: "auth_page",
+                    value: json!({
+                        "title": "Auth Login",
+                        "child_component": "login_page",
+                        "csrf_token": csrf_token.value
+                    }),
+                },
+                renderer,
+            );
+            with_csrf_cookie(page, &csrf_token)
+        })
+}
+
+/// `credentials` read from the body, rejected with `AppError::TooManyAttempts` when the
+/// client ip or the email is over its rate limit or locked out.
+pub fn with_limited_credentials<F>(
+    credentials: F,
+    limiter: LoginLimiter,
+) -> impl Filter<Extract = (PublicUserCred,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (PublicUserCred,), Error = warp::Rejection> + Clone,
+{
+    credentials
+        .and(with_client_ip(limiter.trusted_proxies()))
+        .and(with_login_limiter(limiter))
+        .and_then(
+            |user_cred: PublicUserCred, ip: Option<IpAddr>, limiter: LoginLimiter| async move {
+                limiter
+                    .check(&user_cred.email, ip)
+                    .map(|()| user_cred)
+                    .map_err(warp::Rejection::from)
+            },
+        )
 }
 
 fn register_route(
-    http_client: HttpClient,
+    backend: Backend,
     sessions: UserSessions,
+    limiter: LoginLimiter,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("auth")
         .and(warp::path("register"))
-        .and(warp::body::form::<PublicUserCred>())
         .and(warp::path::end())
         .and(warp::post())
-        .and(with_http_client(http_client.clone()))
-        .and(with_app_config(load_config().clone()))
-        .map(
-            |user_cred: PublicUserCred, client: HttpClient, app_config: AppConfig| async move {
-                println!("__register_route__: UserCredentials {:?}", user_cred);
-                let res = client
-                    .post(format!("{}/users", &app_config.local_backend_api))
-                    .json(&user_cred)
-                    .send()
-                    .await?;
-
-                Ok(res)
-            },
-        )
+        .and(with_limited_credentials(csrf_protected_form(), limiter))
+        .and(with_backend(backend.clone()))
         .and(with_sessions(sessions.clone()))
         .and_then(handle_register)
 }
 
 fn login_route(
-    http_client: HttpClient,
+    backend: Backend,
     sessions: UserSessions,
+    limiter: LoginLimiter,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("auth")
         .and(warp::path("login"))
-        .and(warp::body::form::<PublicUserCred>())
         .and(warp::path::end())
         .and(warp::post())
-        .and(with_http_client(http_client.clone()))
-        .and(with_app_config(load_config().clone()))
-        .map(
-            |user_cred: PublicUserCred, client: HttpClient, app_config: AppConfig| async move {
-                println!("__login_route__: UserCredentials {:?}", user_cred);
-                Ok::<reqwest::Response, reqwest::Error>(
-                    client
-                        .post(format!("{}/users/login", &app_config.local_backend_api))
-                        .json(&user_cred)
-                        .send()
-                        .await?,
-                )
-            },
-        )
+        .and(with_limited_credentials(
+            csrf_protected_form(),
+            limiter.clone(),
+        ))
+        .and(with_backend(backend.clone()))
         .and(with_sessions(sessions.clone()))
+        .and(with_login_limiter(limiter.clone()))
         .and_then(handle_login)
 }
+
+fn logout_route(
+    sessions: UserSessions,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("auth")
+        .and(warp::path("logout"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(csrf_protect())
+        .and(with_session_id())
+        .and(with_sessions(sessions.clone()))
+        .and_then(handle_logout)
+}
+
+fn logout_everywhere_route(
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("auth")
+        .and(warp::path("logout-everywhere"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(csrf_protect())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_sessions(sessions.clone()))
+        .and_then(handle_logout_everywhere)
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use warp::Filter;
+
+use super::app_config::{ApiTokenStoreKind, AppConfigT};
+use super::append_log::{AppendLog, StoreError};
+use super::models::{ApiTokenInfo, UserIdT};
+
+/// Marks our tokens, so they are recognizable in a leaked config or log.
+pub const API_TOKEN_PREFIX: &str = "sgk_";
+
+pub type ApiTokenStoreError = StoreError;
+
+/// A new random token, only ever shown to the user once.
+pub fn generate_api_token() -> String {
+    let random = [Uuid::new_v4(), Uuid::new_v4()]
+        .iter()
+        .flat_map(|u| *u.as_bytes())
+        .collect::<Vec<_>>();
+    format!("{API_TOKEN_PREFIX}{}", URL_SAFE_NO_PAD.encode(random))
+}
+
+/// What the store keeps of a token. Tokens are random, so a plain sha-256
+/// is enough, there is nothing to brute force.
+pub fn hash_api_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: UserIdT,
+    /// The user's label, e.g. "cron laporan harian".
+    pub name: String,
+    pub token_hash: String,
+    pub created_at: u64,
+    pub last_used: Option<u64>,
+}
+
+impl From<&ApiToken> for ApiTokenInfo {
+    fn from(token: &ApiToken) -> Self {
+        ApiTokenInfo {
+            id: token.id.clone(),
+            name: token.name.clone(),
+            created_at: token.created_at,
+            last_used: token.last_used,
+        }
+    }
+}
+
+/// API tokens by their hash.
+#[async_trait]
+pub trait ApiTokenStore: Send + Sync {
+    async fn find(&self, token_hash: &str) -> Result<Option<ApiToken>, ApiTokenStoreError>;
+
+    async fn insert(&self, token: ApiToken) -> Result<(), ApiTokenStoreError>;
+
+    /// Tokens of `user_id`, oldest first.
+    async fn list(&self, user_id: &str) -> Result<Vec<ApiToken>, ApiTokenStoreError>;
+
+    async fn touch(&self, token_hash: &str, last_used: u64) -> Result<(), ApiTokenStoreError>;
+
+    /// Revokes the token `id` of `user_id`, returns whether it existed.
+    async fn revoke(&self, user_id: &str, id: &str) -> Result<bool, ApiTokenStoreError>;
+}
+
+pub type ApiTokens = Arc<dyn ApiTokenStore>;
+
+pub fn with_api_tokens(
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (ApiTokens,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || tokens.clone())
+}
+
+/// Builds the store selected by `api_token_store` in the config.
+pub async fn new_api_token_store(config: &AppConfigT) -> Result<ApiTokens, ApiTokenStoreError> {
+    Ok(match config.api_token_store {
+        ApiTokenStoreKind::Memory => Arc::new(MemoryApiTokenStore::default()),
+        ApiTokenStoreKind::File => Arc::new(FileApiTokenStore::open(&config.api_token_file).await?),
+    })
+}
+
+fn tokens_of(tokens: &HashMap<String, ApiToken>, user_id: &str) -> Vec<ApiToken> {
+    let mut listed = tokens
+        .values()
+        .filter(|token| token.user_id == user_id)
+        .cloned()
+        .collect::<Vec<_>>();
+    listed.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+    listed
+}
+
+fn hash_of(tokens: &HashMap<String, ApiToken>, user_id: &str, id: &str) -> Option<String> {
+    tokens
+        .values()
+        .find(|token| token.user_id == user_id && token.id == id)
+        .map(|token| token.token_hash.clone())
+}
+
+// -- BLOCK: MEMORY_API_TOKEN_STORE
+/// Tokens kept in process memory, a restart revokes every token.
+#[derive(Default)]
+pub struct MemoryApiTokenStore {
+    tokens: RwLock<HashMap<String, ApiToken>>,
+}
+
+#[async_trait]
+impl ApiTokenStore for MemoryApiTokenStore {
+    async fn find(&self, token_hash: &str) -> Result<Option<ApiToken>, ApiTokenStoreError> {
+        Ok(self.tokens.read().await.get(token_hash).cloned())
+    }
+
+    async fn insert(&self, token: ApiToken) -> Result<(), ApiTokenStoreError> {
+        self.tokens
+            .write()
+            .await
+            .insert(token.token_hash.clone(), token);
+        Ok(())
+    }
+
+    async fn list(&self, user_id: &str) -> Result<Vec<ApiToken>, ApiTokenStoreError> {
+        Ok(tokens_of(&*self.tokens.read().await, user_id))
+    }
+
+    async fn touch(&self, token_hash: &str, last_used: u64) -> Result<(), ApiTokenStoreError> {
+        if let Some(token) = self.tokens.write().await.get_mut(token_hash) {
+            token.last_used = Some(last_used);
+        }
+        Ok(())
+    }
+
+    async fn revoke(&self, user_id: &str, id: &str) -> Result<bool, ApiTokenStoreError> {
+        let mut tokens = self.tokens.write().await;
+        Ok(match hash_of(&tokens, user_id, id) {
+            Some(token_hash) => tokens.remove(&token_hash).is_some(),
+            None => false,
+        })
+    }
+}
+// -- ENDBLOCK: MEMORY_API_TOKEN_STORE
+
+// -- BLOCK: FILE_API_TOKEN_STORE
+/// One line of the append-only token log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ApiTokenRecord {
+    Insert {
+        #[serde(flatten)]
+        token: ApiToken,
+    },
+    Touch {
+        token_hash: String,
+        last_used: u64,
+    },
+    Revoke {
+        token_hash: String,
+    },
+}
+
+/// Tokens kept in memory and mirrored to an append-only JSON lines file,
+/// replayed and compacted on open.
+pub struct FileApiTokenStore {
+    inner: RwLock<FileApiTokenStoreInner>,
+}
+
+struct FileApiTokenStoreInner {
+    tokens: HashMap<String, ApiToken>,
+    log: AppendLog<ApiTokenRecord>,
+}
+
+impl FileApiTokenStore {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, ApiTokenStoreError> {
+        let (mut log, records) = AppendLog::open(path).await?;
+        let tokens = replay_api_tokens(records);
+        let live = tokens
+            .values()
+            .map(|token| ApiTokenRecord::Insert {
+                token: token.clone(),
+            })
+            .collect();
+        log.compact(live).await?;
+
+        tracing::info!(
+            tokens = tokens.len(),
+            path = %log.path().display(),
+            "loaded api token log"
+        );
+        Ok(FileApiTokenStore {
+            inner: RwLock::new(FileApiTokenStoreInner { tokens, log }),
+        })
+    }
+}
+
+fn replay_api_tokens(records: Vec<ApiTokenRecord>) -> HashMap<String, ApiToken> {
+    let mut tokens = HashMap::new();
+    for record in records {
+        match record {
+            ApiTokenRecord::Insert { token } => {
+                tokens.insert(token.token_hash.clone(), token);
+            }
+            ApiTokenRecord::Touch {
+                token_hash,
+                last_used,
+            } => {
+                if let Some(token) = tokens.get_mut(&token_hash) {
+                    token.last_used = Some(last_used);
+                }
+            }
+            ApiTokenRecord::Revoke { token_hash } => {
+                tokens.remove(&token_hash);
+            }
+        }
+    }
+    tokens
+}
+
+#[async_trait]
+impl ApiTokenStore for FileApiTokenStore {
+    async fn find(&self, token_hash: &str) -> Result<Option<ApiToken>, ApiTokenStoreError> {
+        Ok(self.inner.read().await.tokens.get(token_hash).cloned())
+    }
+
+    async fn insert(&self, token: ApiToken) -> Result<(), ApiTokenStoreError> {
+        let mut inner = self.inner.write().await;
+        let record = ApiTokenRecord::Insert {
+            token: token.clone(),
+        };
+        inner.log.append(&record).await?;
+        inner.tokens.insert(token.token_hash.clone(), token);
+        Ok(())
+    }
+
+    async fn list(&self, user_id: &str) -> Result<Vec<ApiToken>, ApiTokenStoreError> {
+        Ok(tokens_of(&self.inner.read().await.tokens, user_id))
+    }
+
+    async fn touch(&self, token_hash: &str, last_used: u64) -> Result<(), ApiTokenStoreError> {
+        let mut inner = self.inner.write().await;
+        if !inner.tokens.contains_key(token_hash) {
+            return Ok(());
+        }
+        let record = ApiTokenRecord::Touch {
+            token_hash: token_hash.to_string(),
+            last_used,
+        };
+        inner.log.append(&record).await?;
+        if let Some(token) = inner.tokens.get_mut(token_hash) {
+            token.last_used = Some(last_used);
+        }
+        Ok(())
+    }
+
+    async fn revoke(&self, user_id: &str, id: &str) -> Result<bool, ApiTokenStoreError> {
+        let mut inner = self.inner.write().await;
+        let Some(token_hash) = hash_of(&inner.tokens, user_id, id) else {
+            return Ok(false);
+        };
+        let record = ApiTokenRecord::Revoke {
+            token_hash: token_hash.clone(),
+        };
+        inner.log.append(&record).await?;
+        Ok(inner.tokens.remove(&token_hash).is_some())
+    }
+}
+// -- ENDBLOCK: FILE_API_TOKEN_STORE
@@ -1,34 +1,298 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
 
+use serde::{Deserialize, Serialize};
 use warp::Filter;
 
+use super::logging::LogFormat;
 use super::models::UserIdT;
 
-#[derive(Debug, Default, Clone)]
+/// Prefix of every environment variable read by the config loader,
+/// e.g. `SIGEKRIA_LOCAL_BACKEND_API`.
+pub const ENV_PREFIX: &str = "SIGEKRIA_";
+
+/// Config file looked up in the working directory when neither
+/// `--config` nor `SIGEKRIA_CONFIG` is given.
+pub const DEFAULT_CONFIG_FILE: &str = "sigekria.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfigT {
+    pub address: SocketAddr,
+
+    /// `pretty` or `json`.
+    pub log_format: LogFormat,
+    /// `RUST_LOG` style directives.
+    pub log_filter: String,
+
     pub local_backend_api: String,
     pub local_default_user: UserIdT,
 
     pub remote_backend_api: String,
     pub remote_default_user: UserIdT,
+
+    pub backend_connect_timeout_ms: u64,
+    /// Longest wait for the next bytes of a backend response.
+    pub backend_read_timeout_ms: u64,
+    /// [`Self::backend_read_timeout_ms`] of the summary stream, the backend
+    /// may think a while between chunks.
+    pub backend_stream_read_timeout_ms: u64,
+    /// Extra attempts for idempotent backend calls, e.g. history.
+    pub backend_retries: u32,
+    /// Retry `n` waits a random time up to `backend_retry_base_delay_ms * 2^n`.
+    pub backend_retry_base_delay_ms: u64,
+    /// Failed backend calls in a row before the circuit breaker opens.
+    pub backend_breaker_failures: u32,
+    /// How long an open breaker fails calls before letting one through.
+    pub backend_breaker_open_secs: u64,
+
+    pub session_store: SessionStoreKind,
+    /// Append-only log used by the `file` session store.
+    pub session_file: PathBuf,
+    pub session_idle_timeout_secs: u64,
+    pub session_max_lifetime_secs: u64,
+    /// How often expired sessions are evicted from the store.
+    pub session_sweep_interval_secs: u64,
+    /// HMAC key signing the session cookie, at least 32 bytes. When empty a
+    /// random key is generated on startup and restarts invalidate every cookie.
+    pub session_secret: String,
+
+    /// Only send the session cookie over https, enable when served over TLS.
+    pub cookie_secure: bool,
+    pub cookie_same_site: SameSite,
+
+    /// Where finished summaries are kept, so a content is only summarized once.
+    pub summary_store: SummaryStoreKind,
+    /// Append-only log used by the `file` summary store.
+    pub summary_file: PathBuf,
+    /// Summaries kept, the first stored goes first once it is reached.
+    pub summary_store_capacity: usize,
+
+    /// Where personal API tokens are kept, only their hashes are stored.
+    pub api_token_store: ApiTokenStoreKind,
+    /// Append-only log used by the `file` api token store.
+    pub api_token_file: PathBuf,
+
+    /// Parsed articles kept by canonical url, 0 turns the cache off.
+    pub news_cache_capacity: usize,
+    pub news_cache_ttl_secs: u64,
+    pub news_cache_store: NewsCacheStoreKind,
+    /// Log used by the `file` news cache store.
+    pub news_cache_file: PathBuf,
+
+    /// Login and register attempts per client ip, see `rate_limiter`.
+    pub login_ip_burst: u32,
+    pub login_ip_per_minute: u32,
+    /// Login and register attempts per email.
+    pub login_email_burst: u32,
+    pub login_email_per_minute: u32,
+    /// Wrong passwords in a row before the email is locked out.
+    pub login_max_failures: u32,
+    pub login_lockout_secs: u64,
+    /// Peer addresses of reverse proxies whose `X-Forwarded-For` names the
+    /// client, e.g. `["127.0.0.1"]`. Empty trusts none and limits by peer.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    Lax,
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Memory,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryStoreKind {
+    Memory,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiTokenStoreKind {
+    Memory,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewsCacheStoreKind {
+    Memory,
+    File,
+}
+
+impl Default for AppConfigT {
+    fn default() -> Self {
+        AppConfigT {
+            address: SocketAddr::from(([127, 0, 0, 1], 6969)),
+            log_format: LogFormat::Pretty,
+            log_filter: "info,warp::filters=warn,warp::server=warn".into(),
+            local_backend_api: "http://localhost:8000/api/v0".into(),
+            local_default_user: "".into(),
+            remote_backend_api: "http://localhost:8000/api/v0".into(),
+            remote_default_user: "".into(),
+            backend_connect_timeout_ms: 5_000,
+            backend_read_timeout_ms: 30_000,
+            backend_stream_read_timeout_ms: 5 * 60 * 1000,
+            backend_retries: 2,
+            backend_retry_base_delay_ms: 200,
+            backend_breaker_failures: 5,
+            backend_breaker_open_secs: 30,
+            session_store: SessionStoreKind::Memory,
+            session_file: PathBuf::from("sessions.jsonl"),
+            session_idle_timeout_secs: 34560,
+            session_max_lifetime_secs: 7 * 24 * 60 * 60,
+            session_sweep_interval_secs: 5 * 60,
+            session_secret: "".into(),
+            cookie_secure: false,
+            cookie_same_site: SameSite::Lax,
+            summary_store: SummaryStoreKind::Memory,
+            summary_file: PathBuf::from("summaries.jsonl"),
+            summary_store_capacity: 10_000,
+            api_token_store: ApiTokenStoreKind::Memory,
+            api_token_file: PathBuf::from("api_tokens.jsonl"),
+            news_cache_capacity: 1_000,
+            news_cache_ttl_secs: 6 * 60 * 60,
+            news_cache_store: NewsCacheStoreKind::Memory,
+            news_cache_file: PathBuf::from("news_cache.jsonl"),
+            login_ip_burst: 20,
+            login_ip_per_minute: 10,
+            login_email_burst: 5,
+            login_email_per_minute: 5,
+            login_max_failures: 5,
+            login_lockout_secs: 15 * 60,
+            trusted_proxies: Vec::new(),
+        }
+    }
 }
 
 pub type AppConfig = Arc<AppConfigT>;
 
+pub const MIN_SESSION_SECRET_LEN: usize = 32;
+
 static CONFIG: OnceLock<AppConfig> = OnceLock::new();
 
+#[derive(Debug)]
+pub enum ConfigError {
+    ReadFile(PathBuf, std::io::Error),
+    ParseFile(PathBuf, toml::de::Error),
+    UnknownKey(String),
+    InvalidValue { key: String, value: String },
+    MissingFlagValue(String),
+    Deserialize(toml::de::Error),
+    InvalidUrl { key: &'static str, value: String },
+    AlreadyInitialized,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::ReadFile(path, err) => {
+                write!(f, "cannot read config file '{}': {err}", path.display())
+            }
+            ConfigError::ParseFile(path, err) => {
+                write!(f, "invalid config file '{}': {err}", path.display())
+            }
+            ConfigError::UnknownKey(key) => write!(f, "unknown config key '{key}'"),
+            ConfigError::InvalidValue { key, value } => {
+                write!(f, "invalid value '{value}' for config key '{key}'")
+            }
+            ConfigError::MissingFlagValue(flag) => write!(f, "missing value for flag '{flag}'"),
+            ConfigError::Deserialize(err) => write!(f, "invalid config: {err}"),
+            ConfigError::InvalidUrl { key, value } => write!(
+                f,
+                "config key '{key}' must be an absolute http(s) url, got '{value}'"
+            ),
+            ConfigError::AlreadyInitialized => write!(f, "config was already initialized"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Builds the config from its layers, lowest priority first:
+/// defaults -> config file -> `SIGEKRIA_*` env vars -> `--key value` flags.
+///
+/// `args` must not contain the program name.
+///
+/// An unknown key fails in every layer, a `SIGEKRIA_*` env var naming no key
+/// included: the logger is not set up yet to warn about it, and a typo would
+/// otherwise leave the default silently in place.
+pub fn build_config(
+    env: impl IntoIterator<Item = (String, String)>,
+    args: impl IntoIterator<Item = String>,
+) -> Result<AppConfigT, ConfigError> {
+    let env = env
+        .into_iter()
+        .filter_map(|(k, v)| {
+            k.strip_prefix(ENV_PREFIX)
+                .map(|key| (key.to_lowercase(), v))
+        })
+        .collect::<Vec<_>>();
+    let flags = parse_flags(args)?;
+
+    // -- BLOCK: RESOLVE_CONFIG_FILE
+    let explicit_file = flags
+        .iter()
+        .chain(env.iter())
+        .find(|(k, _)| k == "config")
+        .map(|(_, v)| PathBuf::from(v));
+    let config_file = match explicit_file {
+        Some(path) => Some(path),
+        None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()),
+    };
+    // -- ENDBLOCK: RESOLVE_CONFIG_FILE
+
+    let mut table =
+        toml::Table::try_from(AppConfigT::default()).expect("default config is serializable");
+
+    if let Some(path) = config_file {
+        for (key, value) in read_config_file(&path)? {
+            if !table.contains_key(&key) {
+                return Err(ConfigError::UnknownKey(key));
+            }
+            table.insert(key, value);
+        }
+    }
+
+    for (key, value) in env.into_iter().chain(flags) {
+        if key == "config" {
+            continue;
+        }
+        override_key(&mut table, key, value)?;
+    }
+
+    let config: AppConfigT = toml::Value::Table(table)
+        .try_into()
+        .map_err(ConfigError::Deserialize)?;
+
+    config.validated()
+}
+
+/// Builds the config from the process environment and command line and
+/// stores it as the global config returned by [`load_config`].
+pub fn init_config() -> Result<AppConfig, ConfigError> {
+    let config = Arc::new(build_config(std::env::vars(), std::env::args().skip(1))?);
+    CONFIG
+        .set(config.clone())
+        .map_err(|_| ConfigError::AlreadyInitialized)?;
+    Ok(config)
+}
+
+/// Global config, falls back to the defaults when [`init_config`] was never called.
 pub fn load_config() -> AppConfig {
     CONFIG
-        .get_or_init(|| {
-            Arc::new(AppConfigT {
-                remote_backend_api: "http://localhost:8000/api/v0".into(), // BEING SWITCHED
-                local_default_user: "fa160d0b-2922-496e-a7b0-abc133c48ca7".into(),
-                // local_backend_api: "https://main-bvxea6i-k67tm7kfclutw.au.platformsh.site/api/v0"
-                //     .into(),
-                local_backend_api: "http://localhost:8000/api/v0".into(),
-                remote_default_user: "remoteuser".into(),
-            })
-        })
+        .get_or_init(|| Arc::new(AppConfigT::default()))
         .clone()
 }
 
@@ -37,3 +301,310 @@ pub fn with_app_config(
 ) -> impl Filter<Extract = (AppConfig,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || config.clone())
 }
+
+impl AppConfigT {
+    fn validated(mut self) -> Result<Self, ConfigError> {
+        self.local_backend_api = validate_url("local_backend_api", &self.local_backend_api)?;
+        self.remote_backend_api = validate_url("remote_backend_api", &self.remote_backend_api)?;
+        if !self.session_secret.is_empty() && self.session_secret.len() < MIN_SESSION_SECRET_LEN {
+            return Err(ConfigError::InvalidValue {
+                key: "session_secret".into(),
+                value: format!(
+                    "<{} bytes, need {MIN_SESSION_SECRET_LEN}>",
+                    self.session_secret.len()
+                ),
+            });
+        }
+        for (key, value) in [
+            (
+                "session_sweep_interval_secs",
+                self.session_sweep_interval_secs,
+            ),
+            (
+                "backend_connect_timeout_ms",
+                self.backend_connect_timeout_ms,
+            ),
+            ("backend_read_timeout_ms", self.backend_read_timeout_ms),
+            (
+                "backend_stream_read_timeout_ms",
+                self.backend_stream_read_timeout_ms,
+            ),
+            ("news_cache_ttl_secs", self.news_cache_ttl_secs),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::InvalidValue {
+                    key: key.into(),
+                    value: "0".into(),
+                });
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Checks the url is absolute http(s) and strips the trailing slash, since
+/// routes append paths with `format!("{}/...")`.
+fn validate_url(key: &'static str, value: &str) -> Result<String, ConfigError> {
+    match reqwest::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {
+            Ok(value.trim_end_matches('/').to_string())
+        }
+        _ => Err(ConfigError::InvalidUrl {
+            key,
+            value: value.to_string(),
+        }),
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<toml::Table, ConfigError> {
+    let raw =
+        std::fs::read_to_string(path).map_err(|e| ConfigError::ReadFile(path.to_path_buf(), e))?;
+    raw.parse::<toml::Table>()
+        .map_err(|e| ConfigError::ParseFile(path.to_path_buf(), e))
+}
+
+/// Turns `--local-backend-api <url>` or `--local-backend-api=<url>` into
+/// `("local_backend_api", "<url>")`.
+fn parse_flags(
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(ConfigError::UnknownKey(arg));
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => match args.next() {
+                Some(value) => (flag.to_string(), value),
+                None => return Err(ConfigError::MissingFlagValue(arg)),
+            },
+        };
+        flags.push((key.replace('-', "_"), value));
+    }
+
+    Ok(flags)
+}
+
+/// Env vars and flags are plain strings, so they are converted to the type
+/// of the value they replace.
+fn override_key(table: &mut toml::Table, key: String, raw: String) -> Result<(), ConfigError> {
+    let invalid = |key: &str, raw: &str| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: raw.to_string(),
+    };
+
+    let value = match table.get(&key) {
+        None => return Err(ConfigError::UnknownKey(key)),
+        Some(toml::Value::Integer(_)) => {
+            toml::Value::Integer(raw.parse().map_err(|_| invalid(&key, &raw))?)
+        }
+        Some(toml::Value::Float(_)) => {
+            toml::Value::Float(raw.parse().map_err(|_| invalid(&key, &raw))?)
+        }
+        Some(toml::Value::Boolean(_)) => {
+            toml::Value::Boolean(raw.parse().map_err(|_| invalid(&key, &raw))?)
+        }
+        // Comma separated, `a,b`.
+        Some(toml::Value::Array(_)) => toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        ),
+        Some(_) => toml::Value::String(raw),
+    };
+
+    table.insert(key, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn config_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sigekria-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn defaults_without_any_layer() {
+        let config = build_config(env(&[]), args(&[])).unwrap();
+
+        assert_eq!(config.address, AppConfigT::default().address);
+        assert_eq!(config.local_backend_api, "http://localhost:8000/api/v0");
+        assert_eq!(config.backend_retries, 2);
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let path =
+            config_file("backend_retries = 3\nbackend_breaker_failures = 7\nlogin_ip_burst = 8\n");
+        let path = path.to_str().unwrap();
+
+        let config = build_config(
+            env(&[
+                ("SIGEKRIA_CONFIG", path),
+                ("SIGEKRIA_BACKEND_BREAKER_FAILURES", "9"),
+                ("SIGEKRIA_LOGIN_IP_BURST", "11"),
+                ("OTHER_LOGIN_IP_BURST", "not ours"),
+            ]),
+            args(&["--login-ip-burst", "12"]),
+        )
+        .unwrap();
+
+        assert_eq!(config.backend_retries, 3);
+        assert_eq!(config.backend_breaker_failures, 9);
+        assert_eq!(config.login_ip_burst, 12);
+        assert_eq!(
+            config.login_email_burst,
+            AppConfigT::default().login_email_burst
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn config_flag_picks_the_file() {
+        let path = config_file("backend_retries = 4\n");
+
+        let config = build_config(env(&[]), args(&["--config", path.to_str().unwrap()])).unwrap();
+
+        assert_eq!(config.backend_retries, 4);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_refused_in_every_layer() {
+        let path = config_file("no_such_key = 1\n");
+        let from_file = build_config(
+            env(&[("SIGEKRIA_CONFIG", path.to_str().unwrap())]),
+            args(&[]),
+        );
+        assert!(matches!(from_file, Err(ConfigError::UnknownKey(key)) if key == "no_such_key"));
+        std::fs::remove_file(path).unwrap();
+
+        let from_env = build_config(env(&[("SIGEKRIA_NO_SUCH_KEY", "1")]), args(&[]));
+        assert!(matches!(from_env, Err(ConfigError::UnknownKey(key)) if key == "no_such_key"));
+
+        let from_flag = build_config(env(&[]), args(&["--no-such-key", "1"]));
+        assert!(matches!(from_flag, Err(ConfigError::UnknownKey(key)) if key == "no_such_key"));
+    }
+
+    #[test]
+    fn flags_take_both_forms_and_need_a_value() {
+        assert_eq!(
+            parse_flags(args(&["--log-filter=debug", "--backend-retries", "1"])).unwrap(),
+            env(&[("log_filter", "debug"), ("backend_retries", "1")])
+        );
+        assert!(matches!(
+            parse_flags(args(&["--backend-retries"])),
+            Err(ConfigError::MissingFlagValue(flag)) if flag == "--backend-retries"
+        ));
+        assert!(matches!(
+            parse_flags(args(&["backend-retries"])),
+            Err(ConfigError::UnknownKey(arg)) if arg == "backend-retries"
+        ));
+    }
+
+    #[test]
+    fn strings_are_coerced_to_the_type_they_replace() {
+        let mut table = toml::Table::try_from(AppConfigT::default()).unwrap();
+
+        override_key(&mut table, "backend_retries".into(), "5".into()).unwrap();
+        override_key(&mut table, "cookie_secure".into(), "true".into()).unwrap();
+        override_key(
+            &mut table,
+            "trusted_proxies".into(),
+            "127.0.0.1, ,10.0.0.2".into(),
+        )
+        .unwrap();
+        override_key(&mut table, "log_filter".into(), "debug".into()).unwrap();
+
+        assert_eq!(table["backend_retries"], toml::Value::Integer(5));
+        assert_eq!(table["cookie_secure"], toml::Value::Boolean(true));
+        assert_eq!(
+            table["trusted_proxies"],
+            toml::Value::Array(vec!["127.0.0.1".into(), "10.0.0.2".into()])
+        );
+        assert_eq!(table["log_filter"], toml::Value::String("debug".into()));
+    }
+
+    #[test]
+    fn uncoercible_strings_are_invalid_values() {
+        let mut table = toml::Table::try_from(AppConfigT::default()).unwrap();
+
+        for (key, raw) in [("backend_retries", "many"), ("cookie_secure", "yes")] {
+            assert!(matches!(
+                override_key(&mut table, key.into(), raw.into()),
+                Err(ConfigError::InvalidValue { key: invalid, value }) if invalid == key && value == raw
+            ));
+        }
+        assert!(matches!(
+            build_config(env(&[("SIGEKRIA_BACKEND_RETRIES", "-1")]), args(&[])),
+            Err(ConfigError::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn urls_must_be_absolute_http() {
+        assert_eq!(
+            validate_url("local_backend_api", "https://analyzer:8000/api/v0/").unwrap(),
+            "https://analyzer:8000/api/v0"
+        );
+        for invalid in [
+            "analyzer:8000/api/v0",
+            "/api/v0",
+            "ftp://analyzer/api",
+            "http://",
+        ] {
+            assert!(matches!(
+                validate_url("local_backend_api", invalid),
+                Err(ConfigError::InvalidUrl { key: "local_backend_api", value }) if value == invalid
+            ));
+        }
+        assert!(matches!(
+            build_config(env(&[]), args(&["--remote-backend-api", "not a url"])),
+            Err(ConfigError::InvalidUrl {
+                key: "remote_backend_api",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn short_session_secret_is_refused() {
+        let short = "a".repeat(MIN_SESSION_SECRET_LEN - 1);
+        assert!(matches!(
+            build_config(env(&[("SIGEKRIA_SESSION_SECRET", &short)]), args(&[])),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "session_secret"
+        ));
+
+        let long = "a".repeat(MIN_SESSION_SECRET_LEN);
+        let config = build_config(env(&[("SIGEKRIA_SESSION_SECRET", &long)]), args(&[])).unwrap();
+        assert_eq!(config.session_secret, long);
+    }
+
+    #[test]
+    fn zero_timeouts_are_refused() {
+        assert!(matches!(
+            build_config(env(&[]), args(&["--backend-read-timeout-ms", "0"])),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "backend_read_timeout_ms"
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+/// Error of the stores persisted in an [`AppendLog`].
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(err) => write!(f, "store io error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+impl From<tokio::task::JoinError> for StoreError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        StoreError::Io(std::io::Error::other(err))
+    }
+}
+
+/// JSON lines file a store kept in memory appends its changes to, one record
+/// per line, and replays on open. Blocking file work runs on tokio's blocking
+/// pool, appends go through `tokio::fs`.
+pub struct AppendLog<R> {
+    path: PathBuf,
+    file: tokio::fs::File,
+    /// Lines written since the last compaction.
+    lines: usize,
+    records: PhantomData<fn(R) -> R>,
+}
+
+impl<R> AppendLog<R>
+where
+    R: Serialize + DeserializeOwned + Send + 'static,
+{
+    /// Opens the log at `path` for appending, creating it when missing, with
+    /// the records already in it.
+    pub async fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<R>), StoreError> {
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let records = read_records(&path)?;
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?;
+            let log = AppendLog {
+                lines: records.len(),
+                path,
+                file: tokio::fs::File::from_std(file),
+                records: PhantomData,
+            };
+            Ok((log, records))
+        })
+        .await?
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub async fn append(&mut self, record: &R) -> Result<(), StoreError> {
+        self.file.write_all(&record_line(record)?).await?;
+        self.file.flush().await?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// Replaces the log with `records`, through a synced temporary file so a
+    /// crash leaves either the old or the new log.
+    ///
+    /// Not cancel safe, a caller dropped midway may keep appending to the
+    /// replaced file. Run it from a task that finishes it.
+    pub async fn compact(&mut self, records: Vec<R>) -> Result<(), StoreError> {
+        let path = self.path.clone();
+        let lines = records.len();
+        let file = tokio::task::spawn_blocking(move || {
+            let tmp_path = path.with_extension("compact");
+            {
+                let mut tmp = std::fs::File::create(&tmp_path)?;
+                for record in &records {
+                    tmp.write_all(&record_line(record)?)?;
+                }
+                tmp.sync_all()?;
+            }
+            std::fs::rename(&tmp_path, &path)?;
+            std::fs::OpenOptions::new().append(true).open(&path)
+        })
+        .await??;
+
+        self.file = tokio::fs::File::from_std(file);
+        self.lines = lines;
+        Ok(())
+    }
+}
+
+fn record_line(record: &impl Serialize) -> std::io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn read_records<R: DeserializeOwned>(path: &Path) -> Result<Vec<R>, StoreError> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = Vec::new();
+    for (line_no, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<R>(&line) {
+            Ok(record) => records.push(record),
+            // A crash mid-write leaves a truncated last line, skip it.
+            Err(e) => tracing::warn!(
+                line = line_no + 1,
+                path = %path.display(),
+                error = %e,
+                "skipping corrupt log line"
+            ),
+        }
+    }
+    Ok(records)
+}
@@ -1,16 +1,31 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use tokio::sync::Mutex;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use uuid::Uuid;
 use warp::Filter;
 
-use super::error::{InternalServerProblem, UnauthorizeRequest};
+use super::api_token_store::{hash_api_token, with_api_tokens, ApiTokens};
+use super::app_config::{load_config, AppConfigT, SameSite};
+use super::error::AppError;
 use super::models::UserIdT;
+use super::session_store::{unix_now, Session, SessionPolicy, SessionStore};
 
 pub type SessionIdT = String;
 
-pub type UserSessions = Arc<Mutex<HashMap<SessionIdT, UserIdT>>>;
+pub type UserSessions = Arc<dyn SessionStore>;
+
+/// `last_seen` (and an API token's `last_used`) is only written back when it
+/// moved by at least this much, so a burst of requests does not hit the store
+/// on every call.
+const TOUCH_GRANULARITY_SECS: u64 = 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+static COOKIE_KEY: OnceLock<Vec<u8>> = OnceLock::new();
 
 pub fn with_sessions(
     sessions: UserSessions,
@@ -26,40 +41,238 @@ pub fn generate_session_id() -> SessionIdT {
     Uuid::new_v4().to_string()
 }
 
+fn cookie_key() -> &'static [u8] {
+    COOKIE_KEY.get_or_init(|| {
+        let secret = &load_config().session_secret;
+        if secret.is_empty() {
+            tracing::warn!("session_secret not set, signing cookies with a random key");
+            [Uuid::new_v4(), Uuid::new_v4()]
+                .iter()
+                .flat_map(|u| *u.as_bytes())
+                .collect()
+        } else {
+            secret.as_bytes().to_vec()
+        }
+    })
+}
+
+/// `purpose` keeps a value signed for one cookie from being accepted by another.
+fn value_mac(purpose: &str, value: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(cookie_key()).expect("hmac takes keys of any size");
+    mac.update(purpose.as_bytes());
+    mac.update(b":");
+    mac.update(value.as_bytes());
+    mac
+}
+
+/// `<value>.<base64url hmac-sha256 of purpose:value>`
+pub fn sign_value(purpose: &str, value: &str) -> String {
+    let signature = value_mac(purpose, value).finalize().into_bytes();
+    format!("{value}.{}", URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// Returns the value of a signed string, `None` when it was not signed with
+/// our key for `purpose`.
+pub fn verify_value(purpose: &str, signed: &str) -> Option<String> {
+    let (value, signature) = signed.rsplit_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    value_mac(purpose, value).verify_slice(&signature).ok()?;
+    Some(value.to_string())
+}
+
+pub fn sign_session_id(session_id: &str) -> String {
+    sign_value("session", session_id)
+}
+
+pub fn verify_session_id(cookie_value: &str) -> Option<SessionIdT> {
+    verify_value("session", cookie_value)
+}
+
+/// Builds a `Set-Cookie` value with the attributes shared by all our cookies.
+pub fn cookie_with_attributes(name: &str, value: &str, max_age_secs: u64) -> String {
+    cookie_with_attributes_of(&load_config(), name, value, max_age_secs)
+}
+
+/// [`cookie_with_attributes`] as `config` sets them.
+pub fn cookie_with_attributes_of(
+    config: &AppConfigT,
+    name: &str,
+    value: &str,
+    max_age_secs: u64,
+) -> String {
+    let same_site = match config.cookie_same_site {
+        SameSite::Lax => "Lax",
+        SameSite::Strict => "Strict",
+    };
+    let secure = if config.cookie_secure { "; Secure" } else { "" };
+
+    format!(
+        "{name}={value}; Path=/; Max-Age={max_age_secs}; HttpOnly; SameSite={same_site}{secure}"
+    )
+}
+
+/// The `Set-Cookie` value for a session, every session cookie is built here.
+pub fn session_cookie(session_id: &str, max_age_secs: u64) -> String {
+    cookie_with_attributes("session_id", &sign_session_id(session_id), max_age_secs)
+}
+
+/// Tells the browser to drop the session cookie.
+pub fn expired_session_cookie() -> String {
+    cookie_with_attributes("session_id", "", 0)
+}
+
+/// Session id from the signed `session_id` cookie, tampered cookies count as missing.
+pub fn with_session_id(
+) -> impl Filter<Extract = (Option<SessionIdT>,), Error = std::convert::Infallible> + Clone {
+    warp::cookie::optional::<String>("session_id").map(|cookie: Option<String>| {
+        let cookie = cookie.filter(|value| !value.is_empty())?;
+        let session_id = verify_session_id(&cookie);
+        if session_id.is_none() {
+            tracing::warn!("rejecting tampered session cookie");
+        }
+        session_id
+    })
+}
+
 pub fn with_cookies_session_auth(
     sessions: UserSessions,
 ) -> impl Filter<Extract = (UserIdT,), Error = warp::Rejection> + Clone {
     warp::any()
-        .and(warp::cookie::optional::<String>("session_id"))
+        .and(with_session_id())
         .and(with_sessions(sessions.clone()))
         .and_then(auth_cookie_session)
 }
 
+/// User of the session cookie alone, for routes an API token must not reach
+/// such as managing the tokens themselves. A bearer request is refused
+/// rather than authenticated by a cookie it happens to carry.
+pub fn with_session_cookie_only(
+    sessions: UserSessions,
+) -> impl Filter<Extract = (UserIdT,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|authorization: Option<String>| async move {
+            if authorization.is_some_and(|value| value.starts_with("Bearer ")) {
+                tracing::debug!("api token on a session cookie only route");
+                return Err(warp::reject::custom(AppError::LoginRequired));
+            }
+            Ok(())
+        })
+        .untuple_one()
+        .and(with_cookies_session_auth(sessions))
+}
+
+/// User of the `Authorization: Bearer` API token, for clients without a
+/// browser.
+pub fn with_bearer_auth(
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (UserIdT,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(with_api_tokens(tokens))
+        .and_then(
+            |authorization: Option<String>, tokens: ApiTokens| async move {
+                match authorization {
+                    Some(authorization) => auth_bearer(&authorization, tokens).await,
+                    None => {
+                        tracing::debug!("no authorization header");
+                        Err(AppError::LoginRequired.into())
+                    }
+                }
+            },
+        )
+}
+
+/// The user of an authenticated route: the bearer token when the request
+/// has an `Authorization: Bearer` header, the session cookie otherwise, so a
+/// `Basic` header added by a proxy does not lock the user out. A bad bearer
+/// token never falls back to the cookie, `csrf_protect` relies on that.
+pub fn with_user_auth(
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (UserIdT,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(with_session_id())
+        .and(with_sessions(sessions.clone()))
+        .and(with_api_tokens(tokens))
+        .and_then(
+            |authorization: Option<String>,
+             session_id: Option<SessionIdT>,
+             sessions: UserSessions,
+             tokens: ApiTokens| async move {
+                match authorization.filter(|value| value.starts_with("Bearer ")) {
+                    Some(authorization) => auth_bearer(&authorization, tokens).await,
+                    None => auth_cookie_session(session_id, sessions).await,
+                }
+            },
+        )
+}
+
+async fn auth_bearer(authorization: &str, tokens: ApiTokens) -> Result<UserIdT, warp::Rejection> {
+    let Some(token) = authorization
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+    else {
+        tracing::debug!("authorization header is not a bearer token");
+        return Err(AppError::LoginRequired.into());
+    };
+
+    let token_hash = hash_api_token(token);
+    match tokens.find(&token_hash).await {
+        Ok(Some(api_token)) => {
+            tracing::debug!(user_id = %api_token.user_id, token_id = %api_token.id, "api token authenticated");
+            let now = unix_now();
+            let stale = api_token
+                .last_used
+                .is_none_or(|last_used| now.saturating_sub(last_used) >= TOUCH_GRANULARITY_SECS);
+            if stale {
+                if let Err(store_error) = tokens.touch(&token_hash, now).await {
+                    tracing::error!(error = %store_error, "api token store failed");
+                }
+            }
+            Ok(api_token.user_id)
+        }
+        Ok(None) => {
+            tracing::debug!("unknown or revoked api token");
+            Err(AppError::LoginRequired.into())
+        }
+        Err(store_error) => Err(AppError::from(store_error).into()),
+    }
+}
+
 async fn auth_cookie_session(
     cookie_session_id: Option<String>,
     sessions: UserSessions,
 ) -> Result<UserIdT, warp::Rejection> {
+    let policy = SessionPolicy::from_config(&load_config());
+    let now = unix_now();
+
     match cookie_session_id {
-        Some(session_id) => match sessions.try_lock() {
-            Ok(locked_session) => match locked_session.get(&session_id) {
-                Some(user_id) => {
-                    println!("__with_cookies_session_auth__: UserID -> {user_id}");
-                    Ok(user_id.to_string())
+        Some(session_id) => match sessions.get(&session_id).await {
+            Ok(Some(session)) if session.is_expired(&policy, now) => {
+                tracing::debug!(user_id = %session.user_id, "session expired");
+                if let Err(store_error) = sessions.remove(&session_id).await {
+                    tracing::error!(error = %store_error, "session store failed");
                 }
-                None => {
-                    eprintln!("__with_cookies_session_auth__: User session not found");
-                    Err(warp::reject::custom(UnauthorizeRequest))
+                Err(AppError::LoginRequired.into())
+            }
+            Ok(Some(session)) => {
+                tracing::debug!(user_id = %session.user_id, "session authenticated");
+                if now.saturating_sub(session.last_seen) >= TOUCH_GRANULARITY_SECS {
+                    if let Err(store_error) = sessions.touch(&session_id, now).await {
+                        tracing::error!(error = %store_error, "session store failed");
+                    }
                 }
-            },
-
-            Err(_locking_session_fail) => {
-                eprintln!("__with_cookies_session_auth__: Something wrong during the mutex lock");
-                Err(warp::reject::custom(InternalServerProblem))
+                Ok(session.user_id)
             }
+            Ok(None) => {
+                tracing::debug!("session not found");
+                Err(AppError::LoginRequired.into())
+            }
+            Err(store_error) => Err(AppError::from(store_error).into()),
         },
         None => {
-            eprintln!("__with_cookies_session_auth__: User not login yet");
-            Err(warp::reject::custom(UnauthorizeRequest))
+            tracing::debug!("no session cookie");
+            Err(AppError::LoginRequired.into())
         }
     }
 }
@@ -70,15 +283,123 @@ pub async fn add_user_to_sessions(
 ) -> Result<SessionIdT, warp::Rejection> {
     let new_session_id = generate_session_id();
 
-    match sessions.try_lock() {
-        Ok(mut sessions_lock) => {
-            sessions_lock.insert(new_session_id.to_string(), id.to_string());
-            println!("__add_user_to_sessions__: adding user {id}, into session {new_session_id}");
+    match sessions
+        .insert(
+            new_session_id.to_string(),
+            Session::new(id.to_string(), unix_now()),
+        )
+        .await
+    {
+        Ok(()) => {
+            tracing::debug!(user_id = %id, "session created");
             Ok(new_session_id)
         }
-        Err(_) => {
-            println!("__add_user_to_sessions__: Something wrong with the mutex lock");
-            Err(warp::reject::custom(InternalServerProblem))
+        Err(store_error) => Err(AppError::from(store_error).into()),
+    }
+}
+
+/// Ends the session `session_id`, returns whether it existed.
+pub async fn remove_session(
+    session_id: &str,
+    sessions: UserSessions,
+) -> Result<bool, warp::Rejection> {
+    match sessions.remove(session_id).await {
+        Ok(removed) => {
+            tracing::debug!(existed = removed.is_some(), "session removed");
+            Ok(removed.is_some())
         }
+        Err(store_error) => Err(AppError::from(store_error).into()),
     }
 }
+
+/// Ends every session of `user_id`, returns how many were ended.
+pub async fn remove_user_sessions(
+    user_id: &str,
+    sessions: UserSessions,
+) -> Result<usize, warp::Rejection> {
+    match sessions.remove_user(user_id).await {
+        Ok(removed) => {
+            tracing::info!(%user_id, removed, "removed every session of user");
+            Ok(removed)
+        }
+        Err(store_error) => Err(AppError::from(store_error).into()),
+    }
+}
+
+/// Sliding renewal: re-sends the session cookie with a fresh `max-age` on every
+/// reply to a request carrying a live session. Replies that already set the
+/// session cookie (login, logout) are left alone.
+pub fn renew_session_cookie<F, R>(
+    filter: F,
+    sessions: UserSessions,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    R: warp::Reply + Send,
+{
+    with_session_id()
+        .and(with_sessions(sessions))
+        .and(filter)
+        .then(
+            |session_id: Option<String>, sessions: UserSessions, reply: R| async move {
+                let mut response = reply.into_response();
+                let Some(session_id) = session_id else {
+                    return response;
+                };
+                let sets_session_cookie = response
+                    .headers()
+                    .get_all(warp::http::header::SET_COOKIE)
+                    .iter()
+                    .any(|value| value.as_bytes().starts_with(b"session_id="));
+                if sets_session_cookie {
+                    return response;
+                }
+
+                let policy = SessionPolicy::from_config(&load_config());
+                let now = unix_now();
+                if let Ok(Some(session)) = sessions.get(&session_id).await {
+                    if !session.is_expired(&policy, now) {
+                        let cookie =
+                            session_cookie(&session_id, session.remaining_secs(&policy, now));
+                        if let Ok(value) = warp::http::HeaderValue::from_str(&cookie) {
+                            response
+                                .headers_mut()
+                                .append(warp::http::header::SET_COOKIE, value);
+                        }
+                    }
+                }
+                response
+            },
+        )
+}
+
+/// Every `period`, evicts the sessions expired under `policy` and compacts the
+/// store's log, so neither grows unbounded.
+pub fn spawn_session_sweeper(
+    sessions: UserSessions,
+    policy: SessionPolicy,
+    period: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            match sessions.remove_expired(&policy, unix_now()).await {
+                Ok(0) => (),
+                Ok(evicted) => tracing::info!(evicted, "evicted expired sessions"),
+                Err(store_error) => {
+                    tracing::error!(error = %store_error, "session sweep failed")
+                }
+            }
+            // Every request touches its session, the log only grows between
+            // restarts otherwise.
+            match sessions.compact().await {
+                Ok(false) => (),
+                Ok(true) => tracing::debug!("compacted session log"),
+                Err(store_error) => {
+                    tracing::error!(error = %store_error, "session log compaction failed")
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,482 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use hyper::body::Bytes;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tracing::Instrument;
+use uuid::Uuid;
+use warp::Filter;
+
+use super::app_config::AppConfigT;
+use super::circuit_breaker::CircuitBreaker;
+use super::http_client::{ForwardRequestId, HttpClient};
+use super::json_array::JsonArrayDecoder;
+use super::models::{NewsContent, PublicUserCred, PublicUserWithId, UserHistoryT};
+use super::request_context::in_request_scope;
+
+#[derive(Debug)]
+pub enum BackendError {
+    /// The backend could not be reached or sent a body we could not read.
+    Request(reqwest::Error),
+    /// The backend answered with a non-success status.
+    Status(reqwest::StatusCode),
+    /// A streamed body was not the JSON we expected.
+    Decode(serde_json::Error),
+    /// The backend kept failing, the circuit breaker is not letting calls
+    /// through for another `retry_after_secs`.
+    Unavailable { retry_after_secs: u64 },
+}
+
+impl BackendError {
+    /// Whether the error says the backend is down or overloaded, as opposed
+    /// to answering a bad request. Only those count towards the breaker and
+    /// are retried.
+    pub fn is_backend_failure(&self) -> bool {
+        match self {
+            BackendError::Request(err) => !err.is_decode(),
+            BackendError::Status(status) => status.is_server_error(),
+            BackendError::Decode(_) | BackendError::Unavailable { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Request(err) => write!(f, "backend request failed: {err}"),
+            BackendError::Status(status) => write!(f, "backend answered {status}"),
+            BackendError::Decode(err) => write!(f, "backend sent malformed json: {err}"),
+            BackendError::Unavailable { retry_after_secs } => {
+                write!(f, "backend unavailable, retrying in {retry_after_secs}s")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BackendError::Request(err) => Some(err),
+            BackendError::Decode(err) => Some(err),
+            BackendError::Status(_) | BackendError::Unavailable { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(err: reqwest::Error) -> Self {
+        BackendError::Request(err)
+    }
+}
+
+/// Raw `text/event-stream` body of the summarizer.
+pub type SummaryStream = Pin<Box<dyn Stream<Item = Result<Bytes, BackendError>> + Send>>;
+
+/// History entries decoded one by one as the backend sends them.
+pub type HistoryStream = Pin<Box<dyn Stream<Item = Result<NewsContent, BackendError>> + Send>>;
+
+/// The analyzer backend, routes only talk to it through this trait.
+#[async_trait]
+pub trait BackendClient: Send + Sync {
+    async fn register(&self, cred: &PublicUserCred) -> Result<PublicUserWithId, BackendError>;
+
+    async fn login(&self, cred: &PublicUserCred) -> Result<PublicUserWithId, BackendError>;
+
+    async fn parse_news_url(
+        &self,
+        user_id: &str,
+        news_url: &str,
+    ) -> Result<NewsContent, BackendError>;
+
+    async fn user_history(&self, user_id: &str) -> Result<UserHistoryT, BackendError>;
+
+    /// [`Self::user_history`] without holding it whole, for exports. Fails
+    /// only on the status, a later failure ends the stream with an error.
+    async fn user_history_stream(&self, user_id: &str) -> Result<HistoryStream, BackendError>;
+
+    async fn delete_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+    ) -> Result<(), BackendError>;
+
+    async fn clear_history(&self, user_id: &str) -> Result<(), BackendError>;
+
+    async fn pin_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        pinned: bool,
+    ) -> Result<(), BackendError>;
+
+    /// `None` removes the note.
+    async fn set_history_note(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        note: Option<&str>,
+    ) -> Result<(), BackendError>;
+
+    async fn summarize_stream(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+    ) -> Result<SummaryStream, BackendError>;
+}
+
+pub type Backend = Arc<dyn BackendClient>;
+
+pub fn with_backend(
+    backend: Backend,
+) -> impl Filter<Extract = (Backend,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || backend.clone())
+}
+
+// -- BLOCK: REQWEST_BACKEND_CLIENT
+/// History entries decoded ahead of a slow download.
+const HISTORY_STREAM_BUFFER: usize = 32;
+
+/// Talks to the backend at `local_backend_api` over http, behind a circuit
+/// breaker.
+pub struct ReqwestBackendClient {
+    http_client: HttpClient,
+    /// For the summary stream, which may stall longer than a reply.
+    stream_client: HttpClient,
+    base_url: String,
+    breaker: CircuitBreaker,
+    retries: u32,
+    retry_base_delay: Duration,
+}
+
+impl ReqwestBackendClient {
+    pub fn new(config: &AppConfigT) -> Result<Self, reqwest::Error> {
+        let client_with_read_timeout = |read_timeout_ms| {
+            reqwest::Client::builder()
+                .connect_timeout(Duration::from_millis(config.backend_connect_timeout_ms))
+                .read_timeout(Duration::from_millis(read_timeout_ms))
+                .build()
+        };
+
+        Ok(ReqwestBackendClient {
+            http_client: Arc::new(client_with_read_timeout(config.backend_read_timeout_ms)?),
+            stream_client: Arc::new(client_with_read_timeout(
+                config.backend_stream_read_timeout_ms,
+            )?),
+            base_url: config.local_backend_api.clone(),
+            breaker: CircuitBreaker::new(
+                config.backend_breaker_failures,
+                Duration::from_secs(config.backend_breaker_open_secs),
+            ),
+            retries: config.backend_retries,
+            retry_base_delay: Duration::from_millis(config.backend_retry_base_delay_ms),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// The content id is pushed as one escaped path segment, so a `/` or
+    /// `..` in it cannot reach another entry.
+    fn history_entry_url(&self, user_id: &str, news_content_id: &str) -> String {
+        let history_url = self.url(&format!("/users/{user_id}/history"));
+        // Not an http(s) url then, reqwest reports it on send.
+        let Ok(mut url) = reqwest::Url::parse(&history_url) else {
+            return history_url;
+        };
+        match url.path_segments_mut() {
+            Ok(mut segments) => {
+                segments.pop_if_empty().push(news_content_id);
+            }
+            Err(()) => return history_url,
+        }
+        url.into()
+    }
+
+    /// Sets fields of a history entry, repeating it is harmless.
+    async fn patch_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        fields: serde_json::Value,
+    ) -> Result<(), BackendError> {
+        self.guarded_with_retries(|| async {
+            let res = self
+                .http_client
+                .patch(self.history_entry_url(user_id, news_content_id))
+                .json(&fields)
+                .forward_request_id()
+                .send()
+                .await?;
+            success_of(res).await
+        })
+        .await
+    }
+
+    fn summarize_stream_url(&self, user_id: &str, news_content_id: &str) -> String {
+        let query =
+            serde_urlencoded::to_string([("news_content_id", news_content_id)]).unwrap_or_default();
+        self.url(&format!(
+            "/{user_id}/news_contents/summarize-news-content-stream?{query}"
+        ))
+    }
+
+    /// Runs `call` unless the breaker is open, and reports its outcome.
+    async fn guarded<T>(
+        &self,
+        call: impl Future<Output = Result<T, BackendError>>,
+    ) -> Result<T, BackendError> {
+        if let Err(retry_after_secs) = self.breaker.check(Instant::now()) {
+            return Err(BackendError::Unavailable { retry_after_secs });
+        }
+
+        let result = call.await;
+        match &result {
+            Err(err) if err.is_backend_failure() => self.breaker.record_failure(Instant::now()),
+            _ => self.breaker.record_success(),
+        }
+        result
+    }
+
+    /// [`Self::guarded`] with up to `retries` extra attempts, only for calls
+    /// that are safe to repeat.
+    async fn guarded_with_retries<T, F, Fut>(&self, call: F) -> Result<T, BackendError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, BackendError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.guarded(call()).await {
+                Err(err) if err.is_backend_failure() && attempt < self.retries => {
+                    let delay = jittered_backoff(self.retry_base_delay, attempt);
+                    tracing::warn!(error = %err, attempt, delay_ms = delay.as_millis() as u64, "retrying backend call");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// "Full jitter": a random delay up to `base * 2^attempt`, so clients
+/// retrying together do not hit the backend in lockstep.
+fn jittered_backoff(base: Duration, attempt: u32) -> Duration {
+    let cap = base.saturating_mul(2u32.saturating_pow(attempt));
+    let fraction = (Uuid::new_v4().as_u128() % 1_000) as f64 / 1_000.0;
+    cap.mul_f64(fraction)
+}
+
+async fn success_of(res: reqwest::Response) -> Result<(), BackendError> {
+    match res.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(BackendError::Status(status)),
+    }
+}
+
+/// Reads the history array of `res` into `tx` item by item, until the
+/// receiver goes away.
+async fn decode_history_items(
+    res: reqwest::Response,
+    tx: mpsc::Sender<Result<NewsContent, BackendError>>,
+) {
+    let mut body = res.bytes_stream();
+    let mut decoder = JsonArrayDecoder::default();
+    while let Some(chunk) = body.next().await {
+        let items = match chunk {
+            Ok(chunk) => decoder.push::<NewsContent>(&chunk),
+            Err(err) => {
+                let _ = tx.send(Err(err.into())).await;
+                return;
+            }
+        };
+        match items {
+            Ok(items) => {
+                for item in items {
+                    if tx.send(Ok(item)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                let _ = tx.send(Err(BackendError::Decode(err))).await;
+                return;
+            }
+        }
+    }
+    if let Err(err) = decoder.finish() {
+        let _ = tx.send(Err(BackendError::Decode(err))).await;
+    }
+}
+
+async fn json_of<T: DeserializeOwned>(res: reqwest::Response) -> Result<T, BackendError> {
+    if !res.status().is_success() {
+        return Err(BackendError::Status(res.status()));
+    }
+    Ok(res.json().await?)
+}
+
+#[async_trait]
+impl BackendClient for ReqwestBackendClient {
+    async fn register(&self, cred: &PublicUserCred) -> Result<PublicUserWithId, BackendError> {
+        self.guarded(async {
+            let res = self
+                .http_client
+                .post(self.url("/users"))
+                .json(cred)
+                .forward_request_id()
+                .send()
+                .await?;
+            json_of(res).await
+        })
+        .await
+    }
+
+    async fn login(&self, cred: &PublicUserCred) -> Result<PublicUserWithId, BackendError> {
+        self.guarded(async {
+            let res = self
+                .http_client
+                .post(self.url("/users/login"))
+                .json(cred)
+                .forward_request_id()
+                .send()
+                .await?;
+            json_of(res).await
+        })
+        .await
+    }
+
+    async fn parse_news_url(
+        &self,
+        user_id: &str,
+        news_url: &str,
+    ) -> Result<NewsContent, BackendError> {
+        self.guarded(async {
+            let res = self
+                .http_client
+                .post(self.url(&format!("/{user_id}/news-contents/parse-news-url")))
+                .query(&[("news_url", news_url)])
+                .forward_request_id()
+                .send()
+                .await?;
+            json_of(res).await
+        })
+        .await
+    }
+
+    async fn user_history(&self, user_id: &str) -> Result<UserHistoryT, BackendError> {
+        self.guarded_with_retries(|| async {
+            let res = self
+                .http_client
+                .get(self.url(&format!("/users/{user_id}/history")))
+                .forward_request_id()
+                .send()
+                .await?;
+            json_of(res).await
+        })
+        .await
+    }
+
+    async fn user_history_stream(&self, user_id: &str) -> Result<HistoryStream, BackendError> {
+        let res = self
+            .guarded_with_retries(|| async {
+                let res = self
+                    .http_client
+                    .get(self.url(&format!("/users/{user_id}/history")))
+                    .forward_request_id()
+                    .send()
+                    .await?;
+                match res.status() {
+                    status if status.is_success() => Ok(res),
+                    status => Err(BackendError::Status(status)),
+                }
+            })
+            .await?;
+
+        let (tx, rx) = mpsc::channel(HISTORY_STREAM_BUFFER);
+        tokio::spawn(in_request_scope(decode_history_items(res, tx)).in_current_span());
+        Ok(Box::pin(ReceiverStream::new(rx)))
+    }
+
+    async fn delete_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+    ) -> Result<(), BackendError> {
+        self.guarded_with_retries(|| async {
+            let res = self
+                .http_client
+                .delete(self.history_entry_url(user_id, news_content_id))
+                .forward_request_id()
+                .send()
+                .await?;
+            success_of(res).await
+        })
+        .await
+    }
+
+    async fn clear_history(&self, user_id: &str) -> Result<(), BackendError> {
+        self.guarded_with_retries(|| async {
+            let res = self
+                .http_client
+                .delete(self.url(&format!("/users/{user_id}/history")))
+                .forward_request_id()
+                .send()
+                .await?;
+            success_of(res).await
+        })
+        .await
+    }
+
+    async fn pin_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        pinned: bool,
+    ) -> Result<(), BackendError> {
+        self.patch_history_entry(user_id, news_content_id, json!({ "pinned": pinned }))
+            .await
+    }
+
+    async fn set_history_note(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        note: Option<&str>,
+    ) -> Result<(), BackendError> {
+        self.patch_history_entry(user_id, news_content_id, json!({ "note": note }))
+            .await
+    }
+
+    async fn summarize_stream(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+    ) -> Result<SummaryStream, BackendError> {
+        let res = self
+            .guarded(async {
+                let res = self
+                    .stream_client
+                    .get(self.summarize_stream_url(user_id, news_content_id))
+                    .forward_request_id()
+                    .send()
+                    .await?;
+                match res.status() {
+                    status if status.is_success() => Ok(res),
+                    status => Err(BackendError::Status(status)),
+                }
+            })
+            .await?;
+        Ok(Box::pin(res.bytes_stream().map(|chunk| Ok(chunk?))))
+    }
+}
+// -- ENDBLOCK: REQWEST_BACKEND_CLIENT
@@ -0,0 +1,93 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+enum BreakerState {
+    Closed {
+        consecutive_failures: u32,
+    },
+    /// Every call fails fast until `until`.
+    Open {
+        until: Instant,
+    },
+    /// One trial call went through at `since`, the others fail fast until it
+    /// reports back (or `open_for` passes, in case it never does).
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+/// Stops calling a dependency that keeps failing, so requests fail fast
+/// instead of each waiting for their own timeout.
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+    failure_threshold: u32,
+    open_for: Duration,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        CircuitBreaker {
+            state: Mutex::new(BreakerState::Closed {
+                consecutive_failures: 0,
+            }),
+            failure_threshold: failure_threshold.max(1),
+            open_for,
+        }
+    }
+
+    /// Lets a call through, or returns the seconds until one will be.
+    pub fn check(&self, now: Instant) -> Result<(), u64> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let blocked_until = match *state {
+            BreakerState::Closed { .. } => return Ok(()),
+            BreakerState::Open { until } => until,
+            BreakerState::HalfOpen { since } => since + self.open_for,
+        };
+        if now < blocked_until {
+            return Err(blocked_until.duration_since(now).as_secs().max(1));
+        }
+
+        tracing::info!("circuit breaker half open, letting a trial call through");
+        *state = BreakerState::HalfOpen { since: now };
+        Ok(())
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if !matches!(*state, BreakerState::Closed { .. }) {
+            tracing::info!("circuit breaker closed");
+        }
+        *state = BreakerState::Closed {
+            consecutive_failures: 0,
+        };
+    }
+
+    pub fn record_failure(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let consecutive_failures = match *state {
+            BreakerState::Closed {
+                consecutive_failures,
+            } => consecutive_failures + 1,
+            // A failed trial reopens right away.
+            BreakerState::HalfOpen { .. } => self.failure_threshold,
+            BreakerState::Open { .. } => return,
+        };
+
+        if consecutive_failures >= self.failure_threshold {
+            tracing::warn!(
+                open_secs = self.open_for.as_secs(),
+                "circuit breaker opened"
+            );
+            *state = BreakerState::Open {
+                until: now + self.open_for,
+            };
+        } else {
+            *state = BreakerState::Closed {
+                consecutive_failures,
+            };
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use uuid::Uuid;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+
+use super::app_config::load_config;
+use super::authenticator::{cookie_with_attributes, sign_value, verify_value};
+use super::error::AppError;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+/// Header htmx sends the token in, set through `hx-headers` on `<body>`.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const CSRF_PURPOSE: &str = "csrf";
+
+/// Largest url-encoded form [`csrf_protected_form`] reads.
+const FORM_BODY_MAX_BYTES: u64 = 16 * 1024;
+
+/// Double-submit token: the same signed value lives in the `csrf_token`
+/// cookie and in the rendered page, a cross-site form cannot know it.
+#[derive(Debug, Clone)]
+pub struct CsrfToken {
+    pub value: String,
+    /// The browser has no valid token yet, the reply must set the cookie.
+    pub is_new: bool,
+}
+
+/// Reuses the token from the cookie, or mints a new one.
+pub fn with_csrf_token(
+) -> impl Filter<Extract = (CsrfToken,), Error = std::convert::Infallible> + Clone {
+    warp::cookie::optional::<String>(CSRF_COOKIE).map(|cookie: Option<String>| {
+        match cookie.filter(|value| verify_value(CSRF_PURPOSE, value).is_some()) {
+            Some(value) => CsrfToken {
+                value,
+                is_new: false,
+            },
+            None => CsrfToken::mint(),
+        }
+    })
+}
+
+impl CsrfToken {
+    /// A fresh token, its cookie still has to be set.
+    pub fn mint() -> Self {
+        CsrfToken {
+            value: sign_value(CSRF_PURPOSE, &Uuid::new_v4().to_string()),
+            is_new: true,
+        }
+    }
+}
+
+/// Adds the `csrf_token` cookie to a page rendered with `token`.
+pub fn with_csrf_cookie(reply: impl warp::Reply, token: &CsrfToken) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    if token.is_new {
+        let cookie = cookie_with_attributes(
+            CSRF_COOKIE,
+            &token.value,
+            load_config().session_max_lifetime_secs,
+        );
+        if let Ok(value) = warp::http::HeaderValue::from_str(&cookie) {
+            response
+                .headers_mut()
+                .append(warp::http::header::SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+/// Guards state-changing routes, rejects with [`AppError::CsrfMismatch`] unless the
+/// `X-CSRF-Token` header matches the signed `csrf_token` cookie. Bearer token
+/// requests pass: a browser never adds that header on its own, a cross-site
+/// page cannot set it without a CORS preflight we refuse, and
+/// `with_user_auth` ignores the cookies of such requests.
+pub fn csrf_protect() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::cookie::optional::<String>(CSRF_COOKIE)
+        .and(warp::header::optional::<String>(CSRF_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(
+            |cookie: Option<String>, header: Option<String>, authorization: Option<String>| async move {
+                check_submitted_token(cookie, header, authorization)
+            },
+        )
+        .untuple_one()
+}
+
+#[derive(Deserialize)]
+struct CsrfField {
+    csrf_token: Option<String>,
+}
+
+/// [`csrf_protect`] for an url-encoded form read into `T`, the token may
+/// also come in the hidden `csrf_token` field, for a form posted before htmx has
+/// loaded or without it.
+pub fn csrf_protected_form<T>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
+where
+    T: DeserializeOwned + Send,
+{
+    warp::cookie::optional::<String>(CSRF_COOKIE)
+        .and(warp::header::optional::<String>(CSRF_HEADER))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(FORM_BODY_MAX_BYTES))
+        .and(warp::body::bytes())
+        .and_then(
+            |cookie: Option<String>,
+             header: Option<String>,
+             authorization: Option<String>,
+             body: Bytes| async move {
+                let field = serde_urlencoded::from_bytes::<CsrfField>(&body)
+                    .ok()
+                    .and_then(|field| field.csrf_token);
+                check_submitted_token(cookie, header.or(field), authorization)?;
+                serde_urlencoded::from_bytes::<T>(&body).map_err(|e| {
+                    tracing::debug!(error = %e, "invalid form body");
+                    warp::reject::custom(AppError::InvalidInput)
+                })
+            },
+        )
+}
+
+fn check_submitted_token(
+    cookie: Option<String>,
+    submitted: Option<String>,
+    authorization: Option<String>,
+) -> Result<(), warp::Rejection> {
+    if authorization.is_some_and(|value| value.starts_with("Bearer ")) {
+        return Ok(());
+    }
+    match (cookie, submitted) {
+        (Some(cookie), Some(submitted))
+            if cookie == submitted && verify_value(CSRF_PURPOSE, &cookie).is_some() =>
+        {
+            Ok(())
+        }
+        _ => {
+            tracing::warn!("csrf token missing or mismatching");
+            Err(warp::reject::custom(AppError::CsrfMismatch))
+        }
+    }
+}
@@ -1,108 +1,528 @@
 use std::convert::Infallible;
+use std::fmt;
 
+use schemars::JsonSchema;
 use serde::Serialize;
-use warp::http::StatusCode;
-use warp::{reject, Rejection, Reply};
+use warp::http::{header, HeaderMap, StatusCode};
+use warp::reply::Response;
+use warp::{reject, Filter, Rejection, Reply};
 
-#[derive(Debug)]
-pub struct RegisterExistingUser;
-impl reject::Reject for RegisterExistingUser {}
+use super::append_log::StoreError;
+use super::backend_client::BackendError;
+use super::renderer::{with_renderer, Renderer};
+use super::request_context::{current_request_id, RequestIdT};
 
+/// Why a request failed. The variant decides the status, the `/error/*`
+/// page and what the user reads; internal ones carry their cause, which is
+/// logged and never shown. Reject with `Err(AppError::X.into())` or `?`.
 #[derive(Debug)]
-pub struct IncorrectPassword;
-impl reject::Reject for IncorrectPassword {}
+pub enum AppError {
+    NotFound,
+    /// No session or API token, or an expired one.
+    LoginRequired,
+    UserNotExist,
+    IncorrectPassword,
+    EmailTaken,
+    /// Logged in, but asking for something of another user.
+    Forbidden,
+    CsrfMismatch,
+    /// A query, form or body value that makes no sense, e.g. a bad date.
+    InvalidInput,
+    UnsupportedMediaType,
+    PayloadTooLarge,
+    LengthRequired,
+    MethodNotAllowed,
+    TooManyAttempts {
+        retry_after_secs: u64,
+    },
+    /// The backend circuit breaker is open, see `BackendError::Unavailable`.
+    BackendUnavailable {
+        retry_after_secs: u64,
+    },
+    /// The backend answered, but not with what we asked for.
+    BackendFailure(BackendError),
+    /// A bug or a broken store, `None` for a rejection we do not know.
+    Internal(Option<ErrorCause>),
+}
 
+/// What broke behind an [`AppError::Internal`].
 #[derive(Debug)]
-pub struct UserNotExist;
-impl reject::Reject for UserNotExist {}
+pub enum ErrorCause {
+    Request(reqwest::Error),
+    Json(serde_json::Error),
+    Template(handlebars::RenderError),
+    Response(warp::http::Error),
+    Store(Box<dyn std::error::Error + Send + Sync>),
+}
 
-#[derive(Debug)]
-pub struct BuildResponseError;
-impl reject::Reject for BuildResponseError {}
+impl fmt::Display for ErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCause::Request(err) => write!(f, "request failed: {err}"),
+            ErrorCause::Json(err) => write!(f, "json failed: {err}"),
+            ErrorCause::Template(err) => write!(f, "rendering failed: {err}"),
+            ErrorCause::Response(err) => write!(f, "building response failed: {err}"),
+            ErrorCause::Store(err) => write!(f, "store failed: {err}"),
+        }
+    }
+}
 
-#[derive(Debug)]
-pub struct UnauthorizeRequest;
-impl reject::Reject for UnauthorizeRequest {}
+impl reject::Reject for AppError {}
 
-#[derive(Debug)]
-pub struct InternalServerProblem;
-impl reject::Reject for InternalServerProblem {}
+// -- BLOCK: FROM_CAUSE
+impl From<BackendError> for AppError {
+    fn from(err: BackendError) -> Self {
+        match err {
+            BackendError::Unavailable { retry_after_secs } => {
+                AppError::BackendUnavailable { retry_after_secs }
+            }
+            err => AppError::BackendFailure(err),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(err: reqwest::Error) -> Self {
+        AppError::Internal(Some(ErrorCause::Request(err)))
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Internal(Some(ErrorCause::Json(err)))
+    }
+}
+
+impl From<handlebars::RenderError> for AppError {
+    fn from(err: handlebars::RenderError) -> Self {
+        AppError::Internal(Some(ErrorCause::Template(err)))
+    }
+}
+
+impl From<warp::http::Error> for AppError {
+    fn from(err: warp::http::Error) -> Self {
+        AppError::Internal(Some(ErrorCause::Response(err)))
+    }
+}
+
+impl From<StoreError> for AppError {
+    fn from(err: StoreError) -> Self {
+        AppError::Internal(Some(ErrorCause::Store(Box::new(err))))
+    }
+}
+// -- ENDBLOCK: FROM_CAUSE
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound | AppError::UserNotExist => StatusCode::NOT_FOUND,
+            AppError::LoginRequired | AppError::IncorrectPassword => StatusCode::UNAUTHORIZED,
+            AppError::EmailTaken => StatusCode::CONFLICT,
+            AppError::Forbidden | AppError::CsrfMismatch => StatusCode::FORBIDDEN,
+            AppError::InvalidInput => StatusCode::BAD_REQUEST,
+            AppError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::LengthRequired => StatusCode::LENGTH_REQUIRED,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::TooManyAttempts { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::BackendUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::BackendFailure(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The status line, stable for clients to match on.
+    pub fn title(&self) -> &'static str {
+        match self {
+            AppError::TooManyAttempts { .. } => "Too Many Attempts",
+            _ => self.status().canonical_reason().unwrap_or("Error"),
+        }
+    }
+
+    /// What went wrong, in the words of the user.
+    pub fn detail(&self) -> &'static str {
+        match self {
+            AppError::NotFound => "Halaman atau data yang dicari tidak ditemukan.",
+            AppError::LoginRequired => "Sesi tidak ditemukan atau sudah berakhir.",
+            AppError::UserNotExist => "Email belum terdaftar.",
+            AppError::IncorrectPassword => "Email atau password salah.",
+            AppError::EmailTaken => "Email sudah terdaftar.",
+            AppError::Forbidden => "Data ini milik pengguna lain.",
+            AppError::CsrfMismatch => "Formulir sudah kedaluwarsa.",
+            AppError::InvalidInput => "Input tidak valid.",
+            AppError::UnsupportedMediaType => "Format body tidak didukung.",
+            AppError::PayloadTooLarge => "Body terlalu besar.",
+            AppError::LengthRequired => "Panjang body tidak diketahui.",
+            AppError::MethodNotAllowed => "Method http tidak didukung.",
+            AppError::TooManyAttempts { .. } => "Terlalu banyak percobaan login.",
+            AppError::BackendUnavailable { .. } => "Layanan analisis sedang tidak tersedia.",
+            AppError::BackendFailure(_) => "Layanan analisis gagal menjawab.",
+            AppError::Internal(_) => "Terjadi kesalahan pada server.",
+        }
+    }
+
+    pub fn instructions(&self) -> Vec<&'static str> {
+        match self {
+            AppError::NotFound | AppError::InvalidInput => {
+                vec!["Pastikan input sudah benar", "Hubungi pihak pengembang"]
+            }
+            AppError::LoginRequired => {
+                vec!["Login kembali, atau kirim token API sebagai Authorization: Bearer"]
+            }
+            AppError::UserNotExist => {
+                vec!["Pastikan email sudah terdaftar", "Hubungi pihak pengembang"]
+            }
+            AppError::IncorrectPassword => vec![
+                "Pastikan password dan email sesuai",
+                "Hubungi pihak pengembang untuk mengganti password",
+            ],
+            AppError::EmailTaken => vec!["Coba gunakan email lain", "Hubungi pihak pengembang"],
+            AppError::Forbidden => vec!["Hanya data milik sendiri yang dapat diakses"],
+            AppError::CsrfMismatch => vec![
+                "Muat ulang halaman lalu coba kembali",
+                "Hubungi pihak pengembang",
+            ],
+            AppError::UnsupportedMediaType => {
+                vec!["Kirim body dengan Content-Type: application/json"]
+            }
+            AppError::PayloadTooLarge => vec!["Perkecil isi body"],
+            AppError::LengthRequired => vec!["Kirim header Content-Length"],
+            AppError::MethodNotAllowed => vec!["Periksa method http yang digunakan"],
+            AppError::TooManyAttempts { .. } => vec![
+                "Tunggu beberapa menit lalu coba kembali",
+                "Hubungi pihak pengembang untuk mengganti password",
+            ],
+            AppError::BackendUnavailable { .. } => vec!["Tunggu sebentar lalu coba kembali"],
+            AppError::BackendFailure(_) | AppError::Internal(_) => vec![
+                "Coba kembali dan refresh halaman",
+                "Hubungi pihak pengembang",
+            ],
+        }
+    }
+
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            AppError::TooManyAttempts { retry_after_secs }
+            | AppError::BackendUnavailable { retry_after_secs } => Some(*retry_after_secs),
+            _ => None,
+        }
+    }
+
+    /// Where `redirect_on_reject` sends a browser, one of [`ERROR_PAGES`] or
+    /// the login.
+    pub fn page(&self) -> &'static str {
+        match self {
+            AppError::LoginRequired => "/auth",
+            AppError::NotFound | AppError::UserNotExist => "/error/not-found",
+            AppError::IncorrectPassword => "/error/incorrect-password",
+            AppError::EmailTaken => "/error/email-taken",
+            AppError::Forbidden | AppError::CsrfMismatch => "/error/forbidden",
+            AppError::TooManyAttempts { .. } => "/error/too-many-attempts",
+            AppError::InvalidInput
+            | AppError::UnsupportedMediaType
+            | AppError::PayloadTooLarge
+            | AppError::LengthRequired
+            | AppError::MethodNotAllowed => "/error/bad-request",
+            AppError::BackendUnavailable { .. }
+            | AppError::BackendFailure(_)
+            | AppError::Internal(_) => "/error/server-error",
+        }
+    }
+
+    pub fn error_message(&self) -> ErrorMessage {
+        ErrorMessage {
+            code: self.status().as_u16(),
+            message: self.title(),
+            detail: self.detail(),
+            instructions: self.instructions(),
+        }
+    }
+
+    fn log(&self) {
+        match self {
+            AppError::Internal(Some(cause)) => {
+                tracing::error!(error = %cause, "rejected: internal server problem")
+            }
+            AppError::Internal(None) => tracing::error!("rejected: internal server problem"),
+            AppError::BackendFailure(err) => {
+                tracing::warn!(error = %err, "rejected: backend failure")
+            }
+            AppError::BackendUnavailable { retry_after_secs } => {
+                tracing::warn!(retry_after_secs, "rejected: backend unavailable")
+            }
+            AppError::Forbidden => tracing::warn!("rejected: resource of another user"),
+            err => tracing::debug!(status = err.status().as_u16(), "rejected: {err:?}"),
+        }
+    }
+}
+
+/// The page shown for each `/error/*` path, one per [`AppError::page`].
+pub const ERROR_PAGES: [fn() -> AppError; 7] = [
+    || AppError::NotFound,
+    || AppError::InvalidInput,
+    || AppError::Forbidden,
+    || AppError::Internal(None),
+    || AppError::EmailTaken,
+    || AppError::IncorrectPassword,
+    || AppError::TooManyAttempts {
+        retry_after_secs: 0,
+    },
+];
+
+/// The [`AppError`] of a rejection, mapping warp's own rejections.
+fn with_app_error<T>(err: &Rejection, f: impl FnOnce(&AppError) -> T) -> T {
+    if let Some(app_error) = err.find::<AppError>() {
+        return f(app_error);
+    }
+
+    let app_error = if err.is_not_found() {
+        AppError::NotFound
+    } else if err
+        .find::<warp::filters::body::BodyDeserializeError>()
+        .is_some()
+        || err.find::<warp::reject::InvalidQuery>().is_some()
+        || err.find::<warp::reject::MissingHeader>().is_some()
+        || err.find::<warp::reject::InvalidHeader>().is_some()
+    {
+        AppError::InvalidInput
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        AppError::UnsupportedMediaType
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        AppError::PayloadTooLarge
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        AppError::LengthRequired
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        AppError::MethodNotAllowed
+    } else {
+        tracing::error!(rejection = ?err, "rejected: unhandled rejection");
+        AppError::Internal(None)
+    };
+    f(&app_error)
+}
 
 /// An API error serializable to JSON.
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, JsonSchema)]
 pub struct ErrorMessage {
+    #[schemars(description = "Status http")]
     pub code: u16,
+    #[schemars(description = "Alasan status http")]
     pub message: &'static str,
+    #[schemars(description = "Penjelasan untuk pengguna")]
+    pub detail: &'static str,
     pub instructions: Vec<&'static str>,
 }
 
-pub async fn redirect_on_reject(err: Rejection) -> Result<impl Reply, Infallible> {
-    let redirect_path;
-
-    if err.is_not_found() {
-        eprintln!("__redirect_on_reject__: NOT FOUND");
-        redirect_path = "/error/not-found";
-    } else if let Some(UnauthorizeRequest) = err.find() {
-        eprintln!("__redirect_on_reject__: UNAUTHORIZE user not login yet");
-        redirect_path = "/auth";
-    } else if let Some(RegisterExistingUser) = err.find() {
-        eprintln!("__redirect_on_reject__: EMAIL CONFLICT");
-        redirect_path = "/error/email-taken";
-    } else if let Some(UserNotExist) = err.find() {
-        eprintln!("__redirect_on_reject__: User Not Exist");
-        redirect_path = "/error/not-found";
-    } else if let Some(IncorrectPassword) = err.find() {
-        eprintln!("__redirect_on_reject__: User failed to login",);
-        redirect_path = "/error/incorrect-password"
-    } else if let Some(InternalServerProblem) = err.find() {
-        eprintln!("__redirect_on_reject__: Something bad happen to server",);
-        redirect_path = "/error/server-error"
-    } else if let Some(BuildResponseError) = err.find() {
-        eprintln!("__redirect_on_reject__: Building response error",);
-        redirect_path = "/error/server-error"
-    } else if let Some(_error_on_broken_req_body) =
-        err.find::<warp::filters::body::BodyDeserializeError>()
-    {
-        eprintln!("__redirect_on_reject__: BAD REQUEST on input body deserialize");
-        redirect_path = "/error/bad-request"
-    } else if let Some(_error_on_wrong_req_http_method) =
-        err.find::<warp::reject::MethodNotAllowed>()
-    {
-        eprintln!("__redirect_on_reject__: BAD REQUEST on wrong req http method");
-        redirect_path = "/error/bad-request";
-    } else {
-        eprintln!("__redirect_on_reject__: INTERNAL SERVER ERROR of unknown");
-        redirect_path = "/error/server-error";
+/// What the `error_page` template renders, the request id lets a user report
+/// which request failed.
+#[derive(Serialize, Clone)]
+pub struct ErrorPage {
+    #[serde(flatten)]
+    pub error: ErrorMessage,
+    pub request_id: Option<RequestIdT>,
+}
+
+fn with_retry_after(mut response: Response, retry_after: Option<u64>) -> Response {
+    if let Some(secs) = retry_after {
+        response
+            .headers_mut()
+            .insert(warp::http::header::RETRY_AFTER, secs.into());
     }
+    response
+}
+
+/// Sends a browser to the `/error/*` page of the rejection, or to the login.
+pub async fn redirect_on_reject(err: Rejection) -> Result<impl Reply, Infallible> {
+    Ok(with_app_error(&err, redirect_response))
+}
+
+fn redirect_response(err: &AppError) -> Response {
+    err.log();
+    let redirect_path = err.page();
 
-    Ok(warp::reply::with_header(
-        warp::redirect::redirect(warp::http::Uri::from_static(redirect_path)),
-        "HX-Location",
-        redirect_path,
-    ))
+    // Error pages show the id of the failed request, not of their own.
+    let location = match current_request_id() {
+        Some(request_id) if redirect_path.starts_with("/error/") => {
+            format!("{redirect_path}?request_id={request_id}")
+        }
+        _ => redirect_path.to_string(),
+    };
+    let uri = warp::http::Uri::try_from(location.as_str())
+        .unwrap_or_else(|_| warp::http::Uri::from_static(redirect_path));
+
+    // A forbidden page too, htmx and `/v1` callers get the bare 403 from
+    // `fragment_response` and `json_on_reject`.
+    let response = warp::reply::with_header(warp::redirect::redirect(uri), "HX-Location", location)
+        .into_response();
+
+    with_retry_after(response, err.retry_after_secs())
+}
+
+/// [`redirect_on_reject`] for the `/v1` JSON API: the status of the
+/// rejection with an [`ErrorMessage`] body instead of a redirect.
+pub async fn json_on_reject(err: Rejection) -> Result<impl Reply, Infallible> {
+    Ok(with_app_error(&err, |err| {
+        err.log();
+        let response =
+            warp::reply::with_status(warp::reply::json(&err.error_message()), err.status())
+                .into_response();
+        with_retry_after(response, err.retry_after_secs())
+    }))
+}
+
+// -- BLOCK: NEGOTIATION
+/// Where the htmx error fragment is swapped, see `index.html`.
+pub const ERROR_TOAST_TARGET: &str = "#error-toast";
+
+/// How a client wants its errors, from `HX-Request` and `Accept`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// RFC 7807 `application/problem+json`, for fetch and API clients.
+    Problem,
+    /// An html fragment for htmx to swap in.
+    Fragment,
+    /// A redirect to the full `/error/*` page.
+    Page,
 }
 
-// UNUSE
-pub async fn _handle_rejection(err: Rejection) -> impl Reply {
-    let code;
-    let message;
+impl ErrorFormat {
+    pub fn of(headers: &HeaderMap) -> Self {
+        if headers.get("hx-request").is_some_and(|v| v == "true") {
+            return ErrorFormat::Fragment;
+        }
 
-    match err.find() {
-        Some(RegisterExistingUser) => {
-            eprintln!("__handle_rejection__: conflict rejection -> {:?}", err);
-            code = StatusCode::CONFLICT;
-            message = "Email already taken";
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let mut json_q = 0.0_f32;
+        let mut html_q = 0.0_f32;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media = params
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if media == "application/json"
+                || (media.starts_with("application/") && media.ends_with("+json"))
+            {
+                json_q = json_q.max(q);
+            } else if media == "text/html" || media == "application/xhtml+xml" {
+                html_q = html_q.max(q);
+            }
         }
-        _ => {
-            eprintln!("__handle_rejection__: unhandle rejection -> {:?}", err);
-            code = StatusCode::INTERNAL_SERVER_ERROR;
-            message = "UNHANDLED_REJECTION";
+
+        // A browser lists text/html and `*/*` is no preference, both get the
+        // page like before.
+        if json_q > 0.0 && json_q > html_q {
+            ErrorFormat::Problem
+        } else {
+            ErrorFormat::Page
         }
     }
+}
+
+pub fn with_error_format() -> impl Filter<Extract = (ErrorFormat,), Error = Infallible> + Clone {
+    warp::header::headers_cloned().map(|headers: HeaderMap| ErrorFormat::of(&headers))
+}
+
+/// RFC 7807 problem details, an [`ErrorMessage`] under the standard names.
+#[derive(Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: &'static str,
+    pub instructions: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestIdT>,
+}
+
+fn problem_response(err: &AppError) -> Response {
+    err.log();
+    let problem = ProblemDetails {
+        problem_type: "about:blank",
+        title: err.title(),
+        status: err.status().as_u16(),
+        detail: err.detail(),
+        instructions: err.instructions(),
+        request_id: current_request_id(),
+    };
+    let mut response =
+        warp::reply::with_status(warp::reply::json(&problem), err.status()).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/problem+json"),
+    );
+    with_retry_after(response, err.retry_after_secs())
+}
+
+fn fragment_response(err: &AppError, renderer: &Renderer) -> Response {
+    err.log();
+    // An expired session cannot be fixed in place, htmx loads the login.
+    if matches!(err, AppError::LoginRequired) {
+        let response = warp::reply::with_header(StatusCode::UNAUTHORIZED, "HX-Redirect", "/auth");
+        return response.into_response();
+    }
+
+    let page = ErrorPage {
+        error: err.error_message(),
+        request_id: current_request_id(),
+    };
+    let html = renderer
+        .render("error_fragment", &page)
+        .unwrap_or_else(|render_error| {
+            AppError::from(render_error).log();
+            page.error.detail.to_string()
+        });
+
+    let mut response =
+        warp::reply::with_status(warp::reply::html(html), err.status()).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        "HX-Retarget",
+        header::HeaderValue::from_static(ERROR_TOAST_TARGET),
+    );
+    headers.insert("HX-Reswap", header::HeaderValue::from_static("innerHTML"));
+    with_retry_after(response, err.retry_after_secs())
+}
+
+/// Recovers the rejections of `filter` in the format the client asked for,
+/// see [`ErrorFormat`]. Use instead of `.recover(redirect_on_reject)` for
+/// the pages.
+pub fn recover_negotiated<F, R>(
+    filter: F,
+    renderer: Renderer,
+) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let replied = filter
+        .map(|reply: R| Ok::<_, Rejection>(reply.into_response()))
+        .or_else(|err: Rejection| async move { Ok::<_, Infallible>((Err(err),)) });
 
-    warp::reply::json(&ErrorMessage {
-        code: code.as_u16(),
-        message: message.into(),
-        instructions: vec!["some error"],
-    })
+    with_error_format()
+        .and(with_renderer(renderer))
+        .and(replied)
+        .map(
+            |format: ErrorFormat, renderer: Renderer, replied: Result<Response, Rejection>| {
+                match replied {
+                    Ok(response) => response,
+                    Err(err) => match format {
+                        ErrorFormat::Problem => with_app_error(&err, problem_response),
+                        ErrorFormat::Fragment => {
+                            with_app_error(&err, |err| fragment_response(err, &renderer))
+                        }
+                        ErrorFormat::Page => with_app_error(&err, redirect_response),
+                    },
+                }
+            },
+        )
 }
+// -- ENDBLOCK: NEGOTIATION
@@ -2,6 +2,8 @@ use std::sync::Arc;
 
 use warp::Filter;
 
+use super::request_context::{current_request_id, REQUEST_ID_HEADER};
+
 pub type HttpClient = Arc<reqwest::Client>;
 
 pub fn with_http_client(
@@ -9,3 +11,18 @@ pub fn with_http_client(
 ) -> impl Filter<Extract = (HttpClient,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || http_client.clone())
 }
+
+pub trait ForwardRequestId {
+    /// Forwards the id of the request being served as `X-Request-Id`, so the
+    /// backend log lines can be matched with ours.
+    fn forward_request_id(self) -> Self;
+}
+
+impl ForwardRequestId for reqwest::RequestBuilder {
+    fn forward_request_id(self) -> Self {
+        match current_request_id() {
+            Some(request_id) => self.header(REQUEST_ID_HEADER, request_id),
+            None => self,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde::de::{DeserializeOwned, Error as _};
+
+/// Incremental parser of a JSON array, chunks may split items anywhere. Each
+/// item is decoded once its last byte arrived, so a long array is never held
+/// whole. A top level `null` reads as an empty array.
+#[derive(Debug, Default)]
+pub struct JsonArrayDecoder {
+    buf: Vec<u8>,
+    /// Bytes of `buf` already looked at.
+    scanned: usize,
+    state: ArrayState,
+    /// Nesting of the item being read.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ArrayState {
+    #[default]
+    Start,
+    Null,
+    BeforeItem,
+    InItem,
+    End,
+}
+
+impl JsonArrayDecoder {
+    /// Feeds a chunk and returns the items it completed.
+    pub fn push<T: DeserializeOwned>(&mut self, chunk: &[u8]) -> Result<Vec<T>, serde_json::Error> {
+        self.buf.extend_from_slice(chunk);
+
+        let mut items = Vec::new();
+        let mut i = self.scanned;
+        while i < self.buf.len() {
+            let byte = self.buf[i];
+            match self.state {
+                _ if self.state != ArrayState::InItem && byte.is_ascii_whitespace() => (),
+                ArrayState::Start if byte == b'[' => self.state = ArrayState::BeforeItem,
+                ArrayState::Start if byte == b'n' => self.state = ArrayState::Null,
+                ArrayState::Null => (),
+                ArrayState::BeforeItem if byte == b']' => self.state = ArrayState::End,
+                ArrayState::BeforeItem => {
+                    // Everything before the item is consumed, it starts the buffer.
+                    self.buf.drain(..i);
+                    i = 0;
+                    self.state = ArrayState::InItem;
+                    self.depth = 0;
+                    continue;
+                }
+                ArrayState::InItem => {
+                    if let Some(ends_array) = self.item_byte(byte) {
+                        items.push(serde_json::from_slice(&self.buf[..i])?);
+                        self.buf.drain(..=i);
+                        i = 0;
+                        self.state = if ends_array {
+                            ArrayState::End
+                        } else {
+                            ArrayState::BeforeItem
+                        };
+                        continue;
+                    }
+                }
+                ArrayState::Start | ArrayState::End => {
+                    return Err(serde_json::Error::custom(format!(
+                        "unexpected `{}` outside of the array",
+                        byte as char
+                    )))
+                }
+            }
+            i += 1;
+        }
+
+        // Only a partial item or `null` needs its bytes kept.
+        if !matches!(self.state, ArrayState::InItem | ArrayState::Null) {
+            self.buf.clear();
+            i = 0;
+        }
+        self.scanned = i;
+        Ok(items)
+    }
+
+    /// Checks the array was closed, after the last chunk.
+    pub fn finish(&self) -> Result<(), serde_json::Error> {
+        match self.state {
+            ArrayState::End => Ok(()),
+            ArrayState::Null if self.buf.trim_ascii() == b"null" => Ok(()),
+            _ => Err(serde_json::Error::custom("the array ended early")),
+        }
+    }
+
+    /// Tracks strings and nesting, `Some` once `byte` ends the item: `true`
+    /// for the closing `]` of the array, `false` for a `,`.
+    fn item_byte(&mut self, byte: u8) -> Option<bool> {
+        if self.in_string {
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => (),
+            }
+            return None;
+        }
+        match byte {
+            b'"' => self.in_string = true,
+            b',' | b']' if self.depth == 0 => return Some(byte == b']'),
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+            _ => (),
+        }
+        None
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+use warp::Filter;
+
+use super::app_config::AppConfigT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable, for local development.
+    Pretty,
+    /// One JSON object per line, for the log shipper.
+    Json,
+}
+
+/// Installs the global tracing subscriber, `log_filter` uses the `RUST_LOG`
+/// directive syntax, e.g. `info,warptest=debug`.
+pub fn init_tracing(config: &AppConfigT) -> Result<(), String> {
+    let filter = EnvFilter::try_new(&config.log_filter)
+        .map_err(|e| format!("invalid log_filter '{}': {e}", config.log_filter))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match config.log_format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder.json().flatten_event(true).try_init(),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Wraps every request in a `request` span carrying method and path, and logs
+/// status and latency once the reply is ready. Request id and peer address
+/// come from the enclosing `client` span of `RequestContext`.
+pub fn with_request_tracing<F, R>(
+    filter: F,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = std::convert::Infallible> + Clone + Send + Sync + 'static,
+    R: warp::Reply,
+{
+    filter
+        .with(warp::log::custom(|info| {
+            let latency: Duration = info.elapsed();
+            tracing::info!(
+                status = info.status().as_u16(),
+                latency_ms = latency.as_secs_f64() * 1000.0,
+                "request finished"
+            );
+        }))
+        .with(warp::trace(|info| {
+            tracing::info_span!(
+                "request",
+                method = %info.method(),
+                path = %info.path(),
+            )
+        }))
+}
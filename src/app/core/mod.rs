@@ -1,9 +1,22 @@
+pub mod api_token_store;
 pub mod app_config;
+pub mod append_log;
 pub mod authenticator;
+pub mod backend_client;
+pub mod circuit_breaker;
+pub mod csrf;
 pub mod error;
-pub mod handlers;
 pub mod http_client;
 pub mod interfaces;
+pub mod json_array;
+pub mod logging;
 pub mod models;
+pub mod news_cache;
+pub mod openapi;
+pub mod rate_limiter;
 pub mod renderer;
+pub mod request_context;
 pub mod routes;
+pub mod session_store;
+pub mod summary_store;
+pub mod summary_stream;
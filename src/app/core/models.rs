@@ -1,19 +1,35 @@
+use std::fmt;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+const REDACTED: &str = "<redacted>";
+
 pub type UserIdT = String;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NewsContent {
     pub id: Option<UserIdT>,
     pub title: String,
     pub content: Option<String>,
     pub authors: String,
+    #[schemars(description = "YYYY-MM-DD")]
     pub publication_date: Option<String>,
+    #[schemars(url)]
     pub url: String,
     pub summary: Option<String>,
+    /// Only set on history entries.
+    #[serde(default)]
+    #[schemars(description = "Hanya pada entri riwayat")]
+    pub pinned: bool,
+    /// The user's own label of a history entry.
+    #[serde(default)]
+    #[schemars(description = "Catatan pengguna pada entri riwayat")]
+    pub note: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "UserHistory", description = "Terlama lebih dulu")]
 pub struct UserHistoryT(pub Option<Vec<NewsContent>>);
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -23,15 +39,107 @@ pub struct User {
     pub password: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, JsonSchema)]
 pub struct PublicUserWithId {
     pub id: String,
+    #[schemars(email)]
     pub email: String,
+    #[schemars(description = "Selalu null pada balasan")]
     pub password: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl fmt::Debug for PublicUserWithId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublicUserWithId")
+            .field("id", &self.id)
+            .field("email", &self.email)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct PublicUserCred {
+    #[schemars(email)]
     pub email: String,
+    #[schemars(extend("format" = "password"))]
     pub password: String,
 }
+
+/// Never print the password, the struct ends up in logs.
+impl fmt::Debug for PublicUserCred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublicUserCred")
+            .field("email", &self.email)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+/// An API token as listed to its owner, without the token itself.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub name: String,
+    #[schemars(description = "Detik unix")]
+    pub created_at: u64,
+    /// Unix seconds, `None` until the token is first used.
+    #[schemars(description = "Detik unix, null sebelum dipakai")]
+    pub last_used: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NewApiToken {
+    #[schemars(length(min = 1, max = 64))]
+    pub name: String,
+}
+
+/// The reply to creating a token, the only time `token` is shown.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+    #[schemars(description = "Hanya ditampilkan sekali")]
+    pub token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cred_debug_redacts_the_password() {
+        let cred = PublicUserCred {
+            email: "ani@contoh.id".into(),
+            password: "rahasia123".into(),
+        };
+
+        let debugged = format!("{cred:?}");
+
+        assert!(!debugged.contains("rahasia123"), "{debugged}");
+        assert!(debugged.contains("ani@contoh.id"));
+        assert!(debugged.contains(REDACTED));
+    }
+
+    #[test]
+    fn user_debug_redacts_the_password() {
+        let user = PublicUserWithId {
+            id: "u1".into(),
+            email: "ani@contoh.id".into(),
+            password: Some("rahasia123".into()),
+        };
+
+        let debugged = format!("{user:?}");
+
+        assert!(!debugged.contains("rahasia123"), "{debugged}");
+        assert!(debugged.contains(REDACTED));
+        assert!(!format!(
+            "{:?}",
+            PublicUserWithId {
+                password: None,
+                ..user
+            }
+        )
+        .contains(REDACTED));
+    }
+}
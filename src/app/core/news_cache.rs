@@ -0,0 +1,488 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use warp::Filter;
+
+use super::app_config::{AppConfigT, NewsCacheStoreKind};
+use super::append_log::{AppendLog, StoreError};
+use super::backend_client::{Backend, BackendClient, BackendError, HistoryStream, SummaryStream};
+use super::models::{NewsContent, PublicUserCred, PublicUserWithId, UserHistoryT};
+use super::session_store::unix_now;
+
+/// Query parameters that only track where a click came from.
+const TRACKING_PARAMS: [&str; 8] = [
+    "fbclid", "gclid", "dclid", "msclkid", "igshid", "mc_cid", "mc_eid", "_ga",
+];
+
+/// The cache key of a news url: lowercased host without default port,
+/// tracking parameters and fragment dropped, remaining parameters sorted and
+/// no trailing slash. `None` for anything that is not an http(s) url.
+pub fn canonical_news_url(news_url: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(news_url.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return None;
+    }
+
+    url.set_fragment(None);
+
+    let mut params = url
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_ascii_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    params.sort();
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+
+    let path = url.path().trim_end_matches('/').to_string();
+    url.set_path(&path);
+
+    let mut canonical = url.to_string();
+    // `set_path("")` still leaves the root slash.
+    if url.path() == "/" && url.query().is_none() {
+        canonical.pop();
+    }
+    Some(canonical)
+}
+
+pub type NewsCacheError = StoreError;
+
+/// Counters of the news cache, served on `/metrics/news-cache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct NewsCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay under `capacity`.
+    pub evictions: u64,
+    /// Entries dropped for being older than the ttl.
+    pub expirations: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+// -- BLOCK: NEWS_CACHE
+/// `(user_id, canonical url)`, the backend call a hit replaces is per user.
+type CacheKey = (String, String);
+
+/// One line of the cache log, a later line for the same user and url wins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedNews {
+    user_id: String,
+    url: String,
+    /// Unix seconds.
+    stored_at: u64,
+    content: NewsContent,
+}
+
+impl CachedNews {
+    fn key(&self) -> CacheKey {
+        (self.user_id.clone(), self.url.clone())
+    }
+}
+
+type CacheLog = AppendLog<CachedNews>;
+
+/// A change of the cache, as the log gets it.
+enum CacheLogWrite {
+    /// Append the entry, then rewrite the log to the live entries when the
+    /// cache decided it grew too long.
+    Append(Box<CachedNews>, Option<Vec<CachedNews>>),
+    /// Rewrite the log to the live entries, it has no removals.
+    Rewrite(Vec<CachedNews>),
+}
+
+/// The log, `None` for a memory only cache. Held while memory is updated and
+/// until the write is done, so the log gets changes in the order memory does.
+type SharedCacheLog = Arc<tokio::sync::Mutex<Option<CacheLog>>>;
+
+#[derive(Default)]
+struct NewsCacheInner {
+    entries: HashMap<CacheKey, (CachedNews, u64)>,
+    /// Last use tick -> key, the first entry is the least recently used.
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+impl NewsCacheInner {
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((_, used)) = self.entries.get_mut(key) {
+            self.recency.remove(used);
+            *used = tick;
+            self.recency.insert(tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.recency.remove(&used);
+        }
+    }
+
+    fn insert(&mut self, news: CachedNews) {
+        let key = news.key();
+        self.remove(&key);
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (news, self.tick));
+    }
+
+    fn pop_least_recent(&mut self) -> Option<CacheKey> {
+        let (_, key) = self.recency.pop_first()?;
+        self.entries.remove(&key);
+        Some(key)
+    }
+
+    fn live(&self) -> Vec<CachedNews> {
+        self.entries
+            .values()
+            .map(|(news, _)| news.clone())
+            .collect()
+    }
+}
+
+/// Parsed news by user and canonical url, least recently used entries go first once
+/// `capacity` is reached and entries older than `ttl_secs` are never served.
+pub struct NewsCacheT {
+    inner: Mutex<NewsCacheInner>,
+    log: SharedCacheLog,
+    capacity: usize,
+    ttl_secs: u64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+pub type NewsCache = Arc<NewsCacheT>;
+
+pub fn with_news_cache(
+    cache: NewsCache,
+) -> impl Filter<Extract = (NewsCache,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || cache.clone())
+}
+
+/// Builds the cache configured by the `news_cache_*` keys.
+pub async fn new_news_cache(config: &AppConfigT) -> Result<NewsCache, NewsCacheError> {
+    let cache = NewsCacheT::new(config.news_cache_capacity, config.news_cache_ttl_secs);
+    Ok(Arc::new(match config.news_cache_store {
+        NewsCacheStoreKind::Memory => cache,
+        NewsCacheStoreKind::File => cache.persisted(&config.news_cache_file, unix_now()).await?,
+    }))
+}
+
+fn is_expired(news: &CachedNews, ttl_secs: u64, now: u64) -> bool {
+    now.saturating_sub(news.stored_at) >= ttl_secs
+}
+
+impl NewsCacheT {
+    /// A memory only cache, a `capacity` of 0 caches nothing.
+    pub fn new(capacity: usize, ttl_secs: u64) -> Self {
+        NewsCacheT {
+            inner: Mutex::new(NewsCacheInner::default()),
+            log: SharedCacheLog::default(),
+            capacity,
+            ttl_secs,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    /// Loads the still fresh entries of the log at `path` and appends new
+    /// entries to it, the log is rewritten whenever it grows past twice the
+    /// capacity.
+    pub async fn persisted(self, path: impl AsRef<Path>, now: u64) -> Result<Self, NewsCacheError> {
+        let (mut log, records) = CacheLog::open(path).await?;
+        let mut latest = HashMap::new();
+        for news in records {
+            latest.insert(news.key(), news);
+        }
+        let mut loaded = latest
+            .into_values()
+            .filter(|news| !self.is_expired(news, now))
+            .collect::<Vec<_>>();
+        loaded.sort_by_key(|news| news.stored_at);
+        let loaded = loaded.split_off(loaded.len().saturating_sub(self.capacity));
+        log.compact(loaded.clone()).await?;
+        tracing::info!(
+            entries = loaded.len(),
+            path = %log.path().display(),
+            "loaded news cache"
+        );
+
+        {
+            let mut inner = self.lock();
+            for news in loaded {
+                inner.insert(news);
+            }
+        }
+        *self.log.lock().await = Some(log);
+        Ok(self)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, NewsCacheInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_expired(&self, news: &CachedNews, now: u64) -> bool {
+        is_expired(news, self.ttl_secs, now)
+    }
+
+    /// The content of `canonical_url` cached for `user_id`, counted as a hit
+    /// or a miss.
+    pub fn get(&self, user_id: &str, canonical_url: &str, now: u64) -> Option<NewsContent> {
+        let key = (user_id.to_string(), canonical_url.to_string());
+        let mut inner = self.lock();
+
+        let (found, expired) = match inner.entries.get(&key) {
+            Some((news, _)) if self.is_expired(news, now) => (None, true),
+            Some((news, _)) => (Some(news.content.clone()), false),
+            None => (None, false),
+        };
+        if expired {
+            inner.remove(&key);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if found.is_some() {
+            inner.touch(&key);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        found
+    }
+
+    pub async fn insert(
+        &self,
+        user_id: &str,
+        canonical_url: String,
+        content: NewsContent,
+        now: u64,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let news = CachedNews {
+            user_id: user_id.to_string(),
+            url: canonical_url,
+            stored_at: now,
+            content,
+        };
+
+        let log = self.log.clone().lock_owned().await;
+        let compact_to = {
+            let mut inner = self.lock();
+            while inner.entries.len() >= self.capacity && !inner.entries.contains_key(&news.key()) {
+                if inner.pop_least_recent().is_none() {
+                    break;
+                }
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+            inner.insert(news.clone());
+            log.as_ref()
+                .filter(|log| log.lines() >= self.capacity.saturating_mul(2))
+                .map(|_| inner.live())
+        };
+
+        write_cache_log(log, CacheLogWrite::Append(Box::new(news), compact_to)).await;
+    }
+
+    /// Drops the entries of `user_id` whose content is `content_id`, or all of
+    /// theirs for `None`, once that history entry changed on the backend.
+    pub async fn evict(&self, user_id: &str, content_id: Option<&str>) {
+        let log = self.log.clone().lock_owned().await;
+        let live = {
+            let mut inner = self.lock();
+            let evicted = inner
+                .entries
+                .iter()
+                .filter(|((user, _), (news, _))| {
+                    user == user_id
+                        && content_id.is_none_or(|id| news.content.id.as_deref() == Some(id))
+                })
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            if evicted.is_empty() {
+                return;
+            }
+            for key in &evicted {
+                inner.remove(key);
+            }
+            inner.live()
+        };
+
+        write_cache_log(log, CacheLogWrite::Rewrite(live)).await;
+    }
+
+    pub fn stats(&self) -> NewsCacheStats {
+        NewsCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            entries: self.lock().entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// Applies `write` to the log. It is spawned, so a caller going away
+/// mid-write can not lose the log or the lock order.
+async fn write_cache_log(
+    mut log: tokio::sync::OwnedMutexGuard<Option<CacheLog>>,
+    write: CacheLogWrite,
+) {
+    if log.is_none() {
+        return;
+    }
+    let written = tokio::spawn(async move {
+        let Some(log) = log.as_mut() else {
+            return Ok(());
+        };
+        match write {
+            CacheLogWrite::Append(news, compact_to) => {
+                log.append(&news).await?;
+                if let Some(live) = compact_to {
+                    log.compact(live).await?;
+                }
+                Ok(())
+            }
+            CacheLogWrite::Rewrite(live) => log.compact(live).await,
+        }
+    });
+
+    match written.await {
+        Ok(Ok(())) => (),
+        Ok(Err(log_error)) => tracing::error!(error = %log_error, "writing news cache log failed"),
+        Err(join_error) => tracing::error!(error = %join_error, "writing news cache log failed"),
+    }
+}
+// -- ENDBLOCK: NEWS_CACHE
+
+// -- BLOCK: CACHING_BACKEND_CLIENT
+/// Answers `parse_news_url` from the [`NewsCacheT`] when it can and passes
+/// every other call through to `inner`. History edits evict what they change,
+/// a cached parse would otherwise skip re-adding a deleted entry and keep a
+/// stale pin or note.
+///
+/// Entries are per user: parsing also adds the article to the user's history
+/// on the backend and the content id it returns is what that user summarizes
+/// and deletes later, so one user's parse can not stand in for another's.
+pub struct CachingBackendClient {
+    inner: Backend,
+    cache: NewsCache,
+}
+
+impl CachingBackendClient {
+    pub fn new(inner: Backend, cache: NewsCache) -> Self {
+        CachingBackendClient { inner, cache }
+    }
+}
+
+#[async_trait]
+impl BackendClient for CachingBackendClient {
+    async fn register(&self, cred: &PublicUserCred) -> Result<PublicUserWithId, BackendError> {
+        self.inner.register(cred).await
+    }
+
+    async fn login(&self, cred: &PublicUserCred) -> Result<PublicUserWithId, BackendError> {
+        self.inner.login(cred).await
+    }
+
+    async fn parse_news_url(
+        &self,
+        user_id: &str,
+        news_url: &str,
+    ) -> Result<NewsContent, BackendError> {
+        let Some(canonical_url) = canonical_news_url(news_url) else {
+            return self.inner.parse_news_url(user_id, news_url).await;
+        };
+
+        if let Some(content) = self.cache.get(user_id, &canonical_url, unix_now()) {
+            tracing::debug!(url = %canonical_url, "news cache hit");
+            return Ok(content);
+        }
+        tracing::debug!(url = %canonical_url, "news cache miss");
+
+        let content = self.inner.parse_news_url(user_id, news_url).await?;
+        self.cache
+            .insert(user_id, canonical_url, content.clone(), unix_now())
+            .await;
+        Ok(content)
+    }
+
+    async fn user_history(&self, user_id: &str) -> Result<UserHistoryT, BackendError> {
+        self.inner.user_history(user_id).await
+    }
+
+    async fn user_history_stream(&self, user_id: &str) -> Result<HistoryStream, BackendError> {
+        self.inner.user_history_stream(user_id).await
+    }
+
+    async fn delete_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+    ) -> Result<(), BackendError> {
+        self.inner
+            .delete_history_entry(user_id, news_content_id)
+            .await?;
+        self.cache.evict(user_id, Some(news_content_id)).await;
+        Ok(())
+    }
+
+    async fn clear_history(&self, user_id: &str) -> Result<(), BackendError> {
+        self.inner.clear_history(user_id).await?;
+        self.cache.evict(user_id, None).await;
+        Ok(())
+    }
+
+    async fn pin_history_entry(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        pinned: bool,
+    ) -> Result<(), BackendError> {
+        self.inner
+            .pin_history_entry(user_id, news_content_id, pinned)
+            .await?;
+        self.cache.evict(user_id, Some(news_content_id)).await;
+        Ok(())
+    }
+
+    async fn set_history_note(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+        note: Option<&str>,
+    ) -> Result<(), BackendError> {
+        self.inner
+            .set_history_note(user_id, news_content_id, note)
+            .await?;
+        self.cache.evict(user_id, Some(news_content_id)).await;
+        Ok(())
+    }
+
+    async fn summarize_stream(
+        &self,
+        user_id: &str,
+        news_content_id: &str,
+    ) -> Result<SummaryStream, BackendError> {
+        self.inner.summarize_stream(user_id, news_content_id).await
+    }
+}
+// -- ENDBLOCK: CACHING_BACKEND_CLIENT
@@ -0,0 +1,180 @@
+use schemars::generate::{SchemaGenerator, SchemaSettings};
+use schemars::{JsonSchema, Schema};
+use serde_json::{json, Map, Value};
+
+use super::error::ErrorMessage;
+
+pub const OPENAPI_VERSION: &str = "3.1.0";
+
+/// Where the document keeps the schemas of its models.
+const SCHEMAS_PATH: &str = "/components/schemas";
+
+/// Schema of a body, reply or query of an [`ApiOperation`].
+pub type SchemaOf = fn(&mut SchemaGenerator) -> Schema;
+
+/// [`SchemaOf`] `T`, derived by `schemars` from the same serde attributes
+/// that read and write it. Structs become a `$ref` to their component.
+pub fn schema_of<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
+
+/// The JSON schema generator of the document, models land under
+/// `#/components/schemas`.
+pub fn schema_generator() -> SchemaGenerator {
+    SchemaSettings::draft2020_12()
+        .with(|settings| {
+            settings.definitions_path = SCHEMAS_PATH.into();
+            settings.meta_schema = None;
+        })
+        .into_generator()
+}
+
+/// How an [`ApiOperation`] authenticates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiAuth {
+    Public,
+    /// An API token or the session cookie.
+    TokenOrCookie,
+    /// Only the session cookie, an API token is refused.
+    Cookie,
+}
+
+/// The document of one route, kept next to its filter. Path parameters are
+/// taken from the `{name}` segments of `path`, query parameters from the
+/// fields of `query`.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiOperation {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub tag: &'static str,
+    pub summary: &'static str,
+    pub auth: ApiAuth,
+    pub query: Option<SchemaOf>,
+    pub body: Option<SchemaOf>,
+    pub status: u16,
+    pub reply: Option<SchemaOf>,
+}
+
+impl ApiOperation {
+    fn path_params(&self) -> impl Iterator<Item = &'static str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+    }
+
+    fn to_operation(self, generator: &mut SchemaGenerator) -> Value {
+        let path_params = self
+            .path_params()
+            .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }));
+        let query_params = self.query.map(query_parameters).unwrap_or_default();
+        let parameters = path_params.chain(query_params).collect::<Vec<_>>();
+
+        let success = match self.reply {
+            Some(reply) => json!({
+                "description": self.summary,
+                "content": { "application/json": { "schema": reply(generator) } }
+            }),
+            None => json!({ "description": self.summary }),
+        };
+        let mut operation = json!({
+            "tags": [self.tag],
+            "summary": self.summary,
+            "operationId": operation_id(self.method, self.path),
+            "responses": {
+                self.status.to_string(): success,
+                "default": {
+                    "description": "Error dengan status http-nya",
+                    "content": { "application/json": { "schema": schema_of::<ErrorMessage>(generator) } }
+                }
+            }
+        });
+        if !parameters.is_empty() {
+            operation["parameters"] = Value::Array(parameters);
+        }
+        if let Some(body) = self.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": body(generator) } }
+            });
+        }
+        match self.auth {
+            ApiAuth::Public => {}
+            ApiAuth::TokenOrCookie => {
+                operation["security"] = json!([{ "bearerAuth": [] }, { "cookieAuth": [] }]);
+            }
+            ApiAuth::Cookie => operation["security"] = json!([{ "cookieAuth": [] }]),
+        }
+        operation
+    }
+}
+
+/// The fields of the query struct as `in: query` parameters.
+fn query_parameters(query: SchemaOf) -> Vec<Value> {
+    let mut generator = SchemaSettings::draft2020_12()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator();
+    let schema = query(&mut generator).to_value();
+    let required = schema["required"].as_array().cloned().unwrap_or_default();
+    schema["properties"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, property)| {
+            let mut parameter = json!({
+                "name": name,
+                "in": "query",
+                "required": required.contains(&Value::String(name.clone())),
+                "schema": property,
+            });
+            if let Some(description) = property.get("description") {
+                parameter["description"] = description.clone();
+            }
+            parameter
+        })
+        .collect()
+}
+
+/// `get /v1/me/history/{id}/pin` -> `get_me_history_id_pin`
+fn operation_id(method: &str, path: &str) -> String {
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != "v1")
+        .map(|segment| segment.trim_matches(['{', '}']).replace(['.', '-'], "_"))
+        .collect::<Vec<_>>();
+    format!("{}_{}", method.to_lowercase(), segments.join("_"))
+}
+
+/// The OpenAPI document of `operations`, with the schemas they reference as
+/// its components.
+pub fn openapi_document(title: &str, version: &str, operations: &[ApiOperation]) -> Value {
+    let mut generator = schema_generator();
+    let mut paths = Map::new();
+    for operation in operations {
+        let item = paths
+            .entry(operation.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[operation.method.to_lowercase()] = operation.to_operation(&mut generator);
+    }
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": { "title": title, "version": version },
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(true),
+            "securitySchemes": {
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Token API pribadi dari POST /v1/me/tokens"
+                },
+                "cookieAuth": {
+                    "type": "apiKey",
+                    "in": "cookie",
+                    "name": "session_id",
+                    "description": "Cookie dari POST /v1/auth/login"
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use warp::Filter;
+
+use super::app_config::AppConfigT;
+use super::error::AppError;
+
+/// Buckets are only pruned once this many keys are tracked.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token bucket per key: `burst` requests at once, refilled at `per_minute`.
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    burst: f64,
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, per_minute: u32) -> Self {
+        RateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            burst: burst.max(1) as f64,
+            refill_per_sec: per_minute.max(1) as f64 / 60.0,
+        }
+    }
+
+    /// Takes a token for `key`, or returns the seconds until one is available.
+    pub fn check(&self, key: &str, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() > MAX_TRACKED_KEYS {
+            buckets.retain(|_, bucket| self.refill(*bucket, now).tokens < self.burst);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        *bucket = self.refill(*bucket, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.refill_per_sec).ceil() as u64)
+        }
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now
+            .saturating_duration_since(bucket.updated_at)
+            .as_secs_f64();
+        Bucket {
+            tokens: (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst),
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Lockout {
+    consecutive_failures: u32,
+    locked_until: Option<Instant>,
+}
+
+/// Guards login and register against credential stuffing: per-IP and
+/// per-email rate limits, and a temporary lockout of an email after too many
+/// wrong passwords in a row.
+pub struct LoginLimiterT {
+    per_ip: RateLimiter,
+    per_email: RateLimiter,
+    lockouts: Mutex<HashMap<String, Lockout>>,
+    max_failures: u32,
+    lockout: Duration,
+    trusted_proxies: Arc<[IpAddr]>,
+}
+
+pub type LoginLimiter = Arc<LoginLimiterT>;
+
+impl LoginLimiterT {
+    pub fn from_config(config: &AppConfigT) -> Self {
+        LoginLimiterT {
+            per_ip: RateLimiter::new(config.login_ip_burst, config.login_ip_per_minute),
+            per_email: RateLimiter::new(config.login_email_burst, config.login_email_per_minute),
+            lockouts: Mutex::new(HashMap::new()),
+            max_failures: config.login_max_failures.max(1),
+            lockout: Duration::from_secs(config.login_lockout_secs),
+            trusted_proxies: config.trusted_proxies.clone().into(),
+        }
+    }
+
+    /// Proxies whose `X-Forwarded-For` names the client ip, see
+    /// `request_context::client_ip`.
+    pub fn trusted_proxies(&self) -> Arc<[IpAddr]> {
+        self.trusted_proxies.clone()
+    }
+
+    /// Called before the attempt reaches the backend. Without a client ip
+    /// only the email is limited, rather than every such request sharing one
+    /// bucket.
+    pub fn check(&self, email: &str, ip: Option<IpAddr>) -> Result<(), AppError> {
+        let now = Instant::now();
+        let email = normalize_email(email);
+
+        if let Some(locked_until) = self.locked_until(&email, now) {
+            let retry_after_secs = locked_until.duration_since(now).as_secs().max(1);
+            tracing::warn!(%email, retry_after_secs, "login locked out");
+            return Err(AppError::TooManyAttempts { retry_after_secs });
+        }
+
+        let per_ip = match ip {
+            Some(ip) => self.per_ip.check(&ip.to_string(), now),
+            None => {
+                tracing::debug!("login without a client ip, only the email is limited");
+                Ok(())
+            }
+        };
+        per_ip
+            .and_then(|()| self.per_email.check(&email, now))
+            .map_err(|retry_after_secs| {
+                tracing::warn!(?ip, %email, retry_after_secs, "login rate limited");
+                AppError::TooManyAttempts { retry_after_secs }
+            })
+    }
+
+    pub fn record_failure(&self, email: &str) {
+        let now = Instant::now();
+        let mut lockouts = self.lockouts.lock().unwrap_or_else(|e| e.into_inner());
+
+        if lockouts.len() > MAX_TRACKED_KEYS {
+            lockouts.retain(|_, l| l.locked_until.is_some_and(|until| until > now));
+        }
+
+        let lockout = lockouts.entry(normalize_email(email)).or_default();
+        lockout.consecutive_failures += 1;
+        if lockout.consecutive_failures >= self.max_failures {
+            lockout.consecutive_failures = 0;
+            lockout.locked_until = Some(now + self.lockout);
+        }
+    }
+
+    pub fn record_success(&self, email: &str) {
+        let mut lockouts = self.lockouts.lock().unwrap_or_else(|e| e.into_inner());
+        lockouts.remove(&normalize_email(email));
+    }
+
+    fn locked_until(&self, email: &str, now: Instant) -> Option<Instant> {
+        let lockouts = self.lockouts.lock().unwrap_or_else(|e| e.into_inner());
+        lockouts
+            .get(email)
+            .and_then(|l| l.locked_until)
+            .filter(|until| *until > now)
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub fn with_login_limiter(
+    limiter: LoginLimiter,
+) -> impl Filter<Extract = (LoginLimiter,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || limiter.clone())
+}
@@ -0,0 +1,174 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::service::Service;
+use hyper::Body;
+use tracing::Instrument;
+use uuid::Uuid;
+use warp::http::{HeaderMap, HeaderValue, Request, Response};
+use warp::Filter;
+
+pub type RequestIdT = String;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Longest incoming `X-Request-Id` we accept, longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: RequestIdT;
+}
+
+/// Id of the request being served, `None` outside of a [`RequestContext`].
+pub fn current_request_id() -> Option<RequestIdT> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// `future` keeping the request id of the caller, for tasks spawned while
+/// serving a request.
+pub fn in_request_scope<T>(future: T) -> impl Future<Output = T::Output>
+where
+    T: Future,
+{
+    let request_id = current_request_id();
+    async move {
+        match request_id {
+            Some(request_id) => REQUEST_ID.scope(request_id, future).await,
+            None => future.await,
+        }
+    }
+}
+
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Keeps the caller's `X-Request-Id` when it looks sane, otherwise mints one.
+fn request_id_of(headers: &HeaderMap) -> RequestIdT {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Peer address of the connection, `warp::addr::remote` is empty for a
+/// `warp::service` served by hyper.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+pub fn with_client_addr() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone
+{
+    warp::addr::remote()
+        .and(warp::ext::optional::<ClientAddr>())
+        .map(|remote: Option<SocketAddr>, client: Option<ClientAddr>| {
+            remote.or(client.map(|c| c.0))
+        })
+}
+
+/// The client ip of a request from `peer`. When `peer` is one of
+/// `trusted_proxies` it is the rightmost `X-Forwarded-For` entry that is not
+/// a trusted proxy itself, the entries left of it are written by the client
+/// and can be forged.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    for entry in forwarded_for.unwrap_or_default().rsplit(',') {
+        match entry.trim().parse::<IpAddr>() {
+            Ok(ip) if trusted_proxies.contains(&ip) => continue,
+            Ok(ip) => return Some(ip),
+            Err(_) => break,
+        }
+    }
+    Some(peer)
+}
+
+/// [`client_ip`] of the request, `None` when the peer address is unknown.
+pub fn with_client_ip(
+    trusted_proxies: Arc<[IpAddr]>,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    with_client_addr().and(warp::header::headers_cloned()).map(
+        move |addr: Option<SocketAddr>, headers: warp::http::HeaderMap| {
+            let forwarded_for = headers
+                .get(FORWARDED_FOR_HEADER)
+                .and_then(|value| value.to_str().ok());
+            client_ip(addr.map(|addr| addr.ip()), forwarded_for, &trusted_proxies)
+        },
+    )
+}
+
+/// Serves `inner`, the `warp::service` of the app, with a request id
+/// available through [`current_request_id`] and in a `client` span. The id
+/// is echoed back in the `X-Request-Id` response header.
+///
+/// A task local only covers a future it wraps and a filter can not wrap the
+/// future of the filters after it, so this wraps the service instead. The
+/// request reaches `inner` as hyper read it.
+#[derive(Clone)]
+pub struct RequestContext<S> {
+    inner: S,
+    remote_addr: Option<SocketAddr>,
+}
+
+impl<S> RequestContext<S> {
+    /// `remote_addr` is the peer of the connection, see [`ClientAddr`].
+    pub fn new(inner: S, remote_addr: Option<SocketAddr>) -> Self {
+        RequestContext { inner, remote_addr }
+    }
+}
+
+impl<S> Service<Request<Body>> for RequestContext<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let request_id = request_id_of(req.headers());
+        if let Some(remote_addr) = self.remote_addr {
+            req.extensions_mut().insert(ClientAddr(remote_addr));
+        }
+
+        let span = tracing::info_span!(
+            "client",
+            request_id = %request_id,
+            remote_addr = ?self.remote_addr
+        );
+        // Filters may already run while the service builds its future.
+        let handled = REQUEST_ID.sync_scope(request_id.clone(), || self.inner.call(req));
+        let handled = REQUEST_ID
+            .scope(request_id.clone(), handled)
+            .instrument(span);
+        Box::pin(async move {
+            let mut res = handled.await?;
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+            Ok(res)
+        })
+    }
+}
@@ -1,116 +1,59 @@
+use serde::Deserialize;
 use warp::Filter;
 
-use super::error::ErrorMessage;
-use super::renderer::{render, with_renderer, with_template, Renderer, WithTemplate};
+use super::error::{AppError, ErrorMessage, ErrorPage, ERROR_PAGES};
+use super::news_cache::{with_news_cache, NewsCache};
+use super::renderer::{render, with_renderer, Renderer, WithTemplate};
+use super::request_context::{is_valid_request_id, RequestIdT};
 
-// ROUTES
-pub fn error_routes(
-    renderer: Renderer,
-) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    error_404_page(renderer.clone())
-        .or(error_400_page(renderer.clone()))
-        .or(error_500_page(renderer.clone()))
-        .or(error_registering_existing_user_page(renderer.clone()))
-        .or(error_incorrect_password(renderer.clone()))
-}
-
-fn error_incorrect_password(
-    renderer: Renderer,
-) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("error")
-        .and(warp::path("incorrect-password"))
-        .and(warp::path::end())
-        .and(warp::get())
-        .and(with_template(WithTemplate {
-            name: "error_page",
-            value: ErrorMessage {
-                code: warp::http::StatusCode::UNAUTHORIZED.as_u16(),
-                message: "Unauthorizer",
-                instructions: vec![
-                    "Pastikan password dan email sesuai",
-                    "Hubungi pihak pengembang untuk mengganti password",
-                ],
-            },
-        }))
-        .and(with_renderer(renderer.clone()))
-        .map(render)
+#[derive(Deserialize)]
+struct ErrorPageQuery {
+    request_id: Option<RequestIdT>,
 }
 
-fn error_400_page(
+// ROUTES
+/// `/error/*`, one page per [`ERROR_PAGES`].
+pub fn error_routes(
     renderer: Renderer,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("error")
-        .and(warp::path("bad-request"))
-        .and(warp::path::end())
-        .and(warp::get())
-        .and(with_template(WithTemplate {
-            name: "error_page",
-            value: ErrorMessage {
-                code: warp::http::StatusCode::BAD_REQUEST.as_u16(),
-                message: "Bad Request",
-                instructions: vec!["Pastikan input sudah benar", "Hubungi pihak pengembang"],
-            },
-        }))
-        .and(with_renderer(renderer.clone()))
-        .map(render)
-}
+    let pages = ERROR_PAGES.map(|error| {
+        let error: AppError = error();
+        let slug = error.page().trim_start_matches("/error/");
+        (slug, error.error_message())
+    });
 
-fn error_404_page(
-    renderer: Renderer,
-) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("error")
-        .and(warp::path("not-found"))
+        .and(warp::path::param::<String>())
         .and(warp::path::end())
         .and(warp::get())
-        .and(with_template(WithTemplate {
+        .and_then(move |slug: String| {
+            let page = pages.iter().find(|(known, _)| *known == slug).cloned();
+            async move {
+                page.map(|(_, error)| error)
+                    .ok_or_else(warp::reject::not_found)
+            }
+        })
+        .and(warp::query::<ErrorPageQuery>())
+        // The `?request_id=` is set by `redirect_on_reject`.
+        .map(|error: ErrorMessage, query: ErrorPageQuery| WithTemplate {
             name: "error_page",
-            value: ErrorMessage {
-                code: warp::http::StatusCode::NOT_FOUND.as_u16(),
-                message: "Not Found",
-                instructions: vec!["Pastikan input sudah benar", "Hubungi pihak pengembang"],
+            value: ErrorPage {
+                error,
+                request_id: query.request_id.filter(|id| is_valid_request_id(id)),
             },
-        }))
+        })
         .and(with_renderer(renderer.clone()))
         .map(render)
 }
 
-fn error_500_page(
-    renderer: Renderer,
+/// Counters for monitoring, plain JSON without a session.
+pub fn monitoring_routes(
+    news_cache: NewsCache,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("error")
-        .and(warp::path("server-error"))
-        .and(warp::get())
+    warp::path("metrics")
+        .and(warp::path("news-cache"))
         .and(warp::path::end())
-        .and(with_template(WithTemplate {
-            name: "error_page",
-            value: ErrorMessage {
-                code: warp::http::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                message: "Internal Server Error",
-                instructions: vec![
-                    "Coba kembali dan refresh halaman",
-                    "Hubungi pihak pengembang",
-                ],
-            },
-        }))
-        .and(with_renderer(renderer.clone()))
-        .map(render)
-}
-
-fn error_registering_existing_user_page(
-    renderer: Renderer,
-) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("error")
-        .and(warp::path("email-taken"))
         .and(warp::get())
-        .and(warp::path::end())
-        .and(with_template(WithTemplate {
-            name: "error_page",
-            value: ErrorMessage {
-                code: warp::http::StatusCode::CONFLICT.as_u16(),
-                message: "Conflict",
-                instructions: vec!["Coba gunakan email lain", "Hubungi pihak pengembang"],
-            },
-        }))
-        .and(with_renderer(renderer.clone()))
-        .map(render)
+        .and(with_news_cache(news_cache.clone()))
+        .map(|news_cache: NewsCache| warp::reply::json(&news_cache.stats()))
 }
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use super::app_config::{AppConfigT, SessionStoreKind};
+use super::append_log::{AppendLog, StoreError};
+use super::authenticator::SessionIdT;
+use super::models::UserIdT;
+
+pub type SessionStoreError = StoreError;
+
+/// Seconds since the unix epoch, the unit of every session timestamp.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub user_id: UserIdT,
+    pub created_at: u64,
+    pub last_seen: u64,
+}
+
+impl Session {
+    pub fn new(user_id: UserIdT, now: u64) -> Self {
+        Session {
+            user_id,
+            created_at: now,
+            last_seen: now,
+        }
+    }
+
+    pub fn is_expired(&self, policy: &SessionPolicy, now: u64) -> bool {
+        now.saturating_sub(self.last_seen) > policy.idle_timeout_secs
+            || now.saturating_sub(self.created_at) > policy.max_lifetime_secs
+    }
+
+    /// Seconds the session stays valid without further activity.
+    pub fn remaining_secs(&self, policy: &SessionPolicy, now: u64) -> u64 {
+        let idle_left = (self.last_seen + policy.idle_timeout_secs).saturating_sub(now);
+        let lifetime_left = (self.created_at + policy.max_lifetime_secs).saturating_sub(now);
+        idle_left.min(lifetime_left)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SessionPolicy {
+    /// Session ends after this long without a request.
+    pub idle_timeout_secs: u64,
+    /// Session ends this long after login, activity or not.
+    pub max_lifetime_secs: u64,
+}
+
+impl SessionPolicy {
+    pub fn from_config(config: &AppConfigT) -> Self {
+        SessionPolicy {
+            idle_timeout_secs: config.session_idle_timeout_secs,
+            max_lifetime_secs: config.session_max_lifetime_secs,
+        }
+    }
+}
+
+/// Maps session ids (the `session_id` cookie) to the logged in user.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionStoreError>;
+
+    async fn insert(
+        &self,
+        session_id: SessionIdT,
+        session: Session,
+    ) -> Result<(), SessionStoreError>;
+
+    /// Moves `last_seen` forward, returns `None` when the session does not exist.
+    async fn touch(
+        &self,
+        session_id: &str,
+        last_seen: u64,
+    ) -> Result<Option<Session>, SessionStoreError>;
+
+    async fn remove(&self, session_id: &str) -> Result<Option<Session>, SessionStoreError>;
+
+    /// Drops every session of `user_id`, returns how many were dropped.
+    async fn remove_user(&self, user_id: &str) -> Result<usize, SessionStoreError>;
+
+    /// Drops every session expired at `now`, returns how many were dropped.
+    async fn remove_expired(
+        &self,
+        policy: &SessionPolicy,
+        now: u64,
+    ) -> Result<usize, SessionStoreError>;
+
+    /// Rewrites a persisted store without its superseded records once they
+    /// outgrow the live ones, returns whether it did.
+    async fn compact(&self) -> Result<bool, SessionStoreError> {
+        Ok(false)
+    }
+}
+
+/// Builds the store selected by `session_store` in the config.
+pub async fn new_session_store(
+    config: &AppConfigT,
+) -> Result<Arc<dyn SessionStore>, SessionStoreError> {
+    Ok(match config.session_store {
+        SessionStoreKind::Memory => Arc::new(MemorySessionStore::default()),
+        SessionStoreKind::File => Arc::new(
+            FileSessionStore::open(
+                &config.session_file,
+                SessionPolicy::from_config(config),
+                unix_now(),
+            )
+            .await?,
+        ),
+    })
+}
+
+// -- BLOCK: MEMORY_SESSION_STORE
+/// Sessions kept in process memory, every restart logs every user out.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<HashMap<SessionIdT, Session>>,
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionStoreError> {
+        Ok(self.sessions.read().await.get(session_id).cloned())
+    }
+
+    async fn insert(
+        &self,
+        session_id: SessionIdT,
+        session: Session,
+    ) -> Result<(), SessionStoreError> {
+        self.sessions.write().await.insert(session_id, session);
+        Ok(())
+    }
+
+    async fn touch(
+        &self,
+        session_id: &str,
+        last_seen: u64,
+    ) -> Result<Option<Session>, SessionStoreError> {
+        let mut sessions = self.sessions.write().await;
+        Ok(sessions.get_mut(session_id).map(|session| {
+            session.last_seen = last_seen;
+            session.clone()
+        }))
+    }
+
+    async fn remove(&self, session_id: &str) -> Result<Option<Session>, SessionStoreError> {
+        Ok(self.sessions.write().await.remove(session_id))
+    }
+
+    async fn remove_user(&self, user_id: &str) -> Result<usize, SessionStoreError> {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| session.user_id != user_id);
+        Ok(before - sessions.len())
+    }
+
+    async fn remove_expired(
+        &self,
+        policy: &SessionPolicy,
+        now: u64,
+    ) -> Result<usize, SessionStoreError> {
+        let mut sessions = self.sessions.write().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| !session.is_expired(policy, now));
+        Ok(before - sessions.len())
+    }
+}
+// -- ENDBLOCK: MEMORY_SESSION_STORE
+
+// -- BLOCK: FILE_SESSION_STORE
+/// One line of the append-only session log.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum SessionRecord {
+    Insert {
+        session_id: SessionIdT,
+        #[serde(flatten)]
+        session: Session,
+    },
+    Touch {
+        session_id: SessionIdT,
+        last_seen: u64,
+    },
+    Remove {
+        session_id: SessionIdT,
+    },
+}
+
+/// Sessions kept in memory and mirrored to an append-only JSON lines file,
+/// so they survive restarts. The log is replayed and compacted on open,
+/// without the sessions expired by then, and again by [`SessionStore::compact`].
+pub struct FileSessionStore {
+    inner: RwLock<FileSessionStoreInner>,
+}
+
+struct FileSessionStoreInner {
+    sessions: HashMap<SessionIdT, Session>,
+    log: AppendLog<SessionRecord>,
+}
+
+impl FileSessionStore {
+    pub async fn open(
+        path: impl AsRef<Path>,
+        policy: SessionPolicy,
+        now: u64,
+    ) -> Result<Self, SessionStoreError> {
+        let (log, records) = AppendLog::open(path).await?;
+        let mut sessions = replay_sessions(records);
+        let before = sessions.len();
+        sessions.retain(|_, session| !session.is_expired(&policy, now));
+
+        let mut inner = FileSessionStoreInner { sessions, log };
+        inner.compact().await?;
+
+        tracing::info!(
+            sessions = inner.sessions.len(),
+            expired = before - inner.sessions.len(),
+            path = %inner.log.path().display(),
+            "loaded session log"
+        );
+        Ok(FileSessionStore {
+            inner: RwLock::new(inner),
+        })
+    }
+}
+
+impl FileSessionStoreInner {
+    async fn compact(&mut self) -> Result<(), SessionStoreError> {
+        let records = self
+            .sessions
+            .iter()
+            .map(|(session_id, session)| SessionRecord::Insert {
+                session_id: session_id.clone(),
+                session: session.clone(),
+            })
+            .collect();
+        self.log.compact(records).await
+    }
+
+    async fn remove_where(
+        &mut self,
+        matches: impl Fn(&Session) -> bool,
+    ) -> Result<usize, SessionStoreError> {
+        let removed = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| matches(session))
+            .map(|(session_id, _)| session_id.clone())
+            .collect::<Vec<_>>();
+        for session_id in &removed {
+            let record = SessionRecord::Remove {
+                session_id: session_id.clone(),
+            };
+            self.log.append(&record).await?;
+            self.sessions.remove(session_id);
+        }
+        Ok(removed.len())
+    }
+}
+
+fn replay_sessions(records: Vec<SessionRecord>) -> HashMap<SessionIdT, Session> {
+    let mut sessions = HashMap::new();
+    for record in records {
+        match record {
+            SessionRecord::Insert {
+                session_id,
+                session,
+            } => {
+                sessions.insert(session_id, session);
+            }
+            SessionRecord::Touch {
+                session_id,
+                last_seen,
+            } => {
+                if let Some(session) = sessions.get_mut(&session_id) {
+                    session.last_seen = last_seen;
+                }
+            }
+            SessionRecord::Remove { session_id } => {
+                sessions.remove(&session_id);
+            }
+        }
+    }
+    sessions
+}
+
+#[async_trait]
+impl SessionStore for FileSessionStore {
+    async fn get(&self, session_id: &str) -> Result<Option<Session>, SessionStoreError> {
+        Ok(self.inner.read().await.sessions.get(session_id).cloned())
+    }
+
+    async fn insert(
+        &self,
+        session_id: SessionIdT,
+        session: Session,
+    ) -> Result<(), SessionStoreError> {
+        let mut inner = self.inner.write().await;
+        let record = SessionRecord::Insert {
+            session_id: session_id.clone(),
+            session: session.clone(),
+        };
+        inner.log.append(&record).await?;
+        inner.sessions.insert(session_id, session);
+        Ok(())
+    }
+
+    async fn touch(
+        &self,
+        session_id: &str,
+        last_seen: u64,
+    ) -> Result<Option<Session>, SessionStoreError> {
+        let mut inner = self.inner.write().await;
+        if !inner.sessions.contains_key(session_id) {
+            return Ok(None);
+        }
+        let record = SessionRecord::Touch {
+            session_id: session_id.to_string(),
+            last_seen,
+        };
+        inner.log.append(&record).await?;
+        Ok(inner.sessions.get_mut(session_id).map(|session| {
+            session.last_seen = last_seen;
+            session.clone()
+        }))
+    }
+
+    async fn remove(&self, session_id: &str) -> Result<Option<Session>, SessionStoreError> {
+        let mut inner = self.inner.write().await;
+        if !inner.sessions.contains_key(session_id) {
+            return Ok(None);
+        }
+        let record = SessionRecord::Remove {
+            session_id: session_id.to_string(),
+        };
+        inner.log.append(&record).await?;
+        Ok(inner.sessions.remove(session_id))
+    }
+
+    async fn remove_user(&self, user_id: &str) -> Result<usize, SessionStoreError> {
+        let mut inner = self.inner.write().await;
+        inner
+            .remove_where(|session| session.user_id == user_id)
+            .await
+    }
+
+    async fn remove_expired(
+        &self,
+        policy: &SessionPolicy,
+        now: u64,
+    ) -> Result<usize, SessionStoreError> {
+        let mut inner = self.inner.write().await;
+        inner
+            .remove_where(|session| session.is_expired(policy, now))
+            .await
+    }
+
+    async fn compact(&self) -> Result<bool, SessionStoreError> {
+        let mut inner = self.inner.write().await;
+        // Mostly touches then, rewriting any sooner would not shrink it much.
+        if inner.log.lines() <= inner.sessions.len().saturating_mul(2) {
+            return Ok(false);
+        }
+        inner.compact().await?;
+        Ok(true)
+    }
+}
+// -- ENDBLOCK: FILE_SESSION_STORE
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use warp::Filter;
+
+use super::app_config::{AppConfigT, SummaryStoreKind};
+use super::append_log::{AppendLog, StoreError};
+
+pub type SummaryStoreError = StoreError;
+
+/// Finished summaries by news content id, so a content is only summarized once.
+#[async_trait]
+pub trait SummaryStore: Send + Sync {
+    async fn get(&self, content_id: &str) -> Result<Option<String>, SummaryStoreError>;
+
+    async fn put(&self, content_id: String, summary: String) -> Result<(), SummaryStoreError>;
+}
+
+pub type Summaries = Arc<dyn SummaryStore>;
+
+pub fn with_summaries(
+    summaries: Summaries,
+) -> impl Filter<Extract = (Summaries,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || summaries.clone())
+}
+
+/// Builds the store selected by `summary_store` in the config.
+pub async fn new_summary_store(config: &AppConfigT) -> Result<Summaries, SummaryStoreError> {
+    let capacity = config.summary_store_capacity;
+    Ok(match config.summary_store {
+        SummaryStoreKind::Memory => Arc::new(MemorySummaryStore::new(capacity)),
+        SummaryStoreKind::File => {
+            Arc::new(FileSummaryStore::open(&config.summary_file, capacity).await?)
+        }
+    })
+}
+
+/// Up to `capacity` summaries, the first stored goes first. Summarizing again
+/// only costs a backend call, so nothing smarter is needed.
+struct BoundedSummaries {
+    summaries: HashMap<String, String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl BoundedSummaries {
+    fn new(capacity: usize) -> Self {
+        BoundedSummaries {
+            summaries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&self, content_id: &str) -> Option<String> {
+        self.summaries.get(content_id).cloned()
+    }
+
+    fn insert(&mut self, content_id: String, summary: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.summaries.contains_key(&content_id) {
+            while self.order.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.summaries.remove(&oldest);
+                }
+            }
+            self.order.push_back(content_id.clone());
+        }
+        self.summaries.insert(content_id, summary);
+    }
+}
+
+// -- BLOCK: MEMORY_SUMMARY_STORE
+pub struct MemorySummaryStore {
+    summaries: RwLock<BoundedSummaries>,
+}
+
+impl MemorySummaryStore {
+    pub fn new(capacity: usize) -> Self {
+        MemorySummaryStore {
+            summaries: RwLock::new(BoundedSummaries::new(capacity)),
+        }
+    }
+}
+
+impl Default for MemorySummaryStore {
+    fn default() -> Self {
+        MemorySummaryStore::new(AppConfigT::default().summary_store_capacity)
+    }
+}
+
+#[async_trait]
+impl SummaryStore for MemorySummaryStore {
+    async fn get(&self, content_id: &str) -> Result<Option<String>, SummaryStoreError> {
+        Ok(self.summaries.read().await.get(content_id))
+    }
+
+    async fn put(&self, content_id: String, summary: String) -> Result<(), SummaryStoreError> {
+        self.summaries.write().await.insert(content_id, summary);
+        Ok(())
+    }
+}
+// -- ENDBLOCK: MEMORY_SUMMARY_STORE
+
+// -- BLOCK: FILE_SUMMARY_STORE
+/// One line of the summary log, a later line for the same id wins.
+#[derive(Debug, Serialize, Deserialize)]
+struct SummaryRecord {
+    content_id: String,
+    summary: String,
+}
+
+/// Summaries kept in memory and appended to a JSON lines file, replayed on
+/// open. The log is rewritten with the kept ones on open and whenever it
+/// grows past twice the capacity.
+pub struct FileSummaryStore {
+    inner: RwLock<FileSummaryStoreInner>,
+}
+
+struct FileSummaryStoreInner {
+    summaries: BoundedSummaries,
+    log: AppendLog<SummaryRecord>,
+}
+
+impl FileSummaryStore {
+    pub async fn open(path: impl AsRef<Path>, capacity: usize) -> Result<Self, SummaryStoreError> {
+        let (log, records) = AppendLog::<SummaryRecord>::open(path).await?;
+        let mut summaries = BoundedSummaries::new(capacity);
+        for record in records {
+            summaries.insert(record.content_id, record.summary);
+        }
+
+        let mut inner = FileSummaryStoreInner { summaries, log };
+        inner.compact().await?;
+
+        tracing::info!(
+            summaries = inner.summaries.summaries.len(),
+            path = %inner.log.path().display(),
+            "loaded summary log"
+        );
+        Ok(FileSummaryStore {
+            inner: RwLock::new(inner),
+        })
+    }
+}
+
+impl FileSummaryStoreInner {
+    async fn compact(&mut self) -> Result<(), SummaryStoreError> {
+        let records = self
+            .summaries
+            .order
+            .iter()
+            .filter_map(|content_id| {
+                Some(SummaryRecord {
+                    content_id: content_id.clone(),
+                    summary: self.summaries.get(content_id)?,
+                })
+            })
+            .collect();
+        self.log.compact(records).await
+    }
+}
+
+#[async_trait]
+impl SummaryStore for FileSummaryStore {
+    async fn get(&self, content_id: &str) -> Result<Option<String>, SummaryStoreError> {
+        Ok(self.inner.read().await.summaries.get(content_id))
+    }
+
+    async fn put(&self, content_id: String, summary: String) -> Result<(), SummaryStoreError> {
+        let mut inner = self.inner.write().await;
+        let record = SummaryRecord {
+            content_id: content_id.clone(),
+            summary: summary.clone(),
+        };
+        inner.log.append(&record).await?;
+        inner.summaries.insert(content_id, summary);
+        if inner.log.lines() > inner.summaries.capacity.saturating_mul(2) {
+            inner.compact().await?;
+        }
+        Ok(())
+    }
+}
+// -- ENDBLOCK: FILE_SUMMARY_STORE
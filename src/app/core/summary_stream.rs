@@ -0,0 +1,178 @@
+use std::convert::Infallible;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tracing::Instrument;
+use warp::sse::Event;
+
+use super::backend_client::SummaryStream;
+use super::request_context::in_request_scope;
+use super::summary_store::Summaries;
+
+/// Events buffered between the backend and a slow browser.
+const PROXY_BUFFER: usize = 32;
+
+/// One event of the summarizer's `text/event-stream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryEvent {
+    /// `None` for plain `message` events.
+    pub event: Option<String>,
+    pub data: String,
+}
+
+impl SummaryEvent {
+    pub fn is_message(&self) -> bool {
+        matches!(self.event.as_deref(), None | Some("message"))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.event.as_deref() == Some("done")
+    }
+}
+
+/// Incremental `text/event-stream` parser, chunks may split lines anywhere.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buf: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feeds a chunk and returns the events it completed.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SummaryEvent> {
+        self.buf.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let line = self.buf.drain(..=end).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SummaryEvent {
+                        event: self.event.take(),
+                        data: std::mem::take(&mut self.data).join("\n"),
+                    });
+                }
+                self.event = None;
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => (),
+            }
+        }
+        events
+    }
+}
+
+/// Re-emits the summarizer's `message` and `done` events to the browser, and
+/// stores the assembled summary of `content_id` once `done` arrives.
+///
+/// The backend is read from a spawned task, when the browser goes away the
+/// task notices, stops and drops the backend connection with it. A backend
+/// failure or a stream ending without `done` is reported as an `error` event.
+pub fn proxy_summary_stream(
+    backend_stream: SummaryStream,
+    content_id: String,
+    summaries: Summaries,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+    let (tx, rx) = mpsc::channel(PROXY_BUFFER);
+    tokio::spawn(
+        in_request_scope(forward_summary_events(
+            backend_stream,
+            content_id,
+            summaries,
+            tx,
+        ))
+        .in_current_span(),
+    );
+    ReceiverStream::new(rx).map(Ok)
+}
+
+/// A stored summary as the same events the summarizer would have sent.
+pub fn replay_summary(summary: String) -> impl Stream<Item = Result<Event, Infallible>> {
+    tokio_stream::iter([
+        Ok(Event::default().data(summary)),
+        Ok(Event::default().event("done").data("")),
+    ])
+}
+
+async fn forward_summary_events(
+    mut backend_stream: SummaryStream,
+    content_id: String,
+    summaries: Summaries,
+    tx: mpsc::Sender<Event>,
+) {
+    let mut decoder = SseDecoder::default();
+    // The browser appends every message as is, so does the stored summary.
+    let mut summary = String::new();
+
+    loop {
+        let chunk = tokio::select! {
+            _ = tx.closed() => {
+                tracing::debug!("browser left the summary stream");
+                return;
+            }
+            chunk = backend_stream.next() => chunk,
+        };
+
+        let chunk = match chunk {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(backend_error)) => {
+                tracing::warn!(error = %backend_error, "summary stream failed");
+                let _ = tx.send(error_event("summary stream failed")).await;
+                return;
+            }
+            None => {
+                tracing::warn!("summary stream ended before done");
+                let _ = tx.send(error_event("summary stream ended early")).await;
+                return;
+            }
+        };
+
+        for summary_event in decoder.push(&chunk) {
+            let is_done = summary_event.is_done();
+            let event = if is_done {
+                Event::default().event("done").data(summary_event.data)
+            } else if summary_event.is_message() {
+                summary.push_str(&summary_event.data);
+                Event::default().data(summary_event.data)
+            } else {
+                tracing::debug!(event = ?summary_event.event, "dropping summary event");
+                continue;
+            };
+
+            if is_done {
+                // Stored even when the browser left just before `done`. An
+                // empty one is not, it would be replayed instead of retried.
+                if summary.trim().is_empty() {
+                    tracing::warn!("summary stream done without a summary");
+                } else if let Err(store_error) = summaries.put(content_id, summary).await {
+                    tracing::error!(error = %store_error, "storing summary failed");
+                }
+                let _ = tx.send(event).await;
+                tracing::debug!("summary stream done");
+                return;
+            }
+            if tx.send(event).await.is_err() {
+                tracing::debug!("browser left the summary stream");
+                return;
+            }
+        }
+    }
+}
+
+fn error_event(message: &str) -> Event {
+    Event::default().event("error").data(message)
+}
@@ -0,0 +1,140 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::app::core::backend_client::{Backend, BackendError};
+use crate::app::core::error::AppError;
+use crate::app::core::models::{NewsContent, UserIdT};
+use crate::app::core::summary_store::Summaries;
+
+/// Longest note kept on a history entry, in characters.
+pub const HISTORY_NOTE_MAX_CHARS: usize = 200;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct PinForm {
+    pub pinned: bool,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct NoteForm {
+    /// Empty or missing removes the note.
+    #[serde(default)]
+    #[schemars(length(max = HISTORY_NOTE_MAX_CHARS), description = "Kosong atau null menghapus catatan")]
+    pub note: Option<String>,
+}
+
+/// A change to the history of a user.
+pub enum HistoryEdit {
+    Delete { content_id: String },
+    Clear,
+    Pin { content_id: String, pinned: bool },
+    Note { content_id: String, note: NoteForm },
+}
+
+/// Summary finished earlier, a failing store only costs a re-summarize. An
+/// empty summary counts as none.
+pub async fn stored_summary(content_id: &str, summaries: &Summaries) -> Option<String> {
+    match summaries.get(content_id).await {
+        Ok(summary) => summary.filter(|s| !s.trim().is_empty()),
+        Err(store_error) => {
+            tracing::error!(error = %store_error, "reading stored summary failed");
+            None
+        }
+    }
+}
+
+/// [`stored_summary`] of a content in the history of `user_id` only, the
+/// store is shared by all users. Anything else is left to the backend.
+pub async fn stored_summary_of_user(
+    user_id: &str,
+    content_id: &str,
+    backend: &Backend,
+    summaries: &Summaries,
+) -> Option<String> {
+    let summary = stored_summary(content_id, summaries).await?;
+    let in_history = match backend.user_history(user_id).await {
+        Ok(h) => {
+            h.0.unwrap_or_default()
+                .iter()
+                .any(|news| news.id.as_deref() == Some(content_id))
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "loading history for a stored summary failed");
+            false
+        }
+    };
+    in_history.then_some(summary)
+}
+
+/// `news` with the stored summary of its content if one finished earlier,
+/// over the one the backend has. An empty summary counts as none.
+pub async fn with_stored_summary(mut news: NewsContent, summaries: &Summaries) -> NewsContent {
+    let stored = match news.id.as_deref() {
+        Some(content_id) => stored_summary(content_id, summaries).await,
+        None => None,
+    };
+    news.summary = stored.or(news.summary.filter(|s| !s.is_empty()));
+    news
+}
+
+/// Parses `url` for `user_id`, see [`with_stored_summary`].
+pub async fn analyze_news(
+    url: String,
+    user_id: UserIdT,
+    backend: Backend,
+    summaries: Summaries,
+) -> Result<NewsContent, BackendError> {
+    let content = backend.parse_news_url(&user_id, &url).await?;
+    Ok(with_stored_summary(content, &summaries).await)
+}
+
+/// The whole history of `user_id`, oldest first.
+pub async fn load_history(
+    user_id: &str,
+    backend: &Backend,
+) -> Result<Vec<NewsContent>, warp::Rejection> {
+    match backend.user_history(user_id).await {
+        Ok(h) => Ok(h.0.unwrap_or_default()),
+        Err(e) => {
+            tracing::warn!(error = %e, "loading history failed");
+            Err(AppError::from(e).into())
+        }
+    }
+}
+
+pub async fn edit_history(
+    user_id: UserIdT,
+    edit: HistoryEdit,
+    backend: Backend,
+) -> Result<(), warp::Rejection> {
+    let result = match edit {
+        HistoryEdit::Delete { content_id } => {
+            backend.delete_history_entry(&user_id, &content_id).await
+        }
+        HistoryEdit::Clear => backend.clear_history(&user_id).await,
+        HistoryEdit::Pin { content_id, pinned } => {
+            backend
+                .pin_history_entry(&user_id, &content_id, pinned)
+                .await
+        }
+        HistoryEdit::Note { content_id, note } => {
+            let note = note
+                .note
+                .unwrap_or_default()
+                .trim()
+                .chars()
+                .take(HISTORY_NOTE_MAX_CHARS)
+                .collect::<String>();
+            let note = Some(note.as_str()).filter(|note| !note.is_empty());
+            backend.set_history_note(&user_id, &content_id, note).await
+        }
+    };
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(BackendError::Status(reqwest::StatusCode::NOT_FOUND)) => Err(AppError::NotFound.into()),
+        Err(e) => {
+            tracing::warn!(error = %e, "updating history failed");
+            Err(AppError::from(e).into())
+        }
+    }
+}
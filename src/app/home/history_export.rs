@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use tokio_stream::{Stream, StreamExt};
+use warp::hyper::body::Bytes;
+
+use crate::app::core::backend_client::BackendError;
+use crate::app::core::models::NewsContent;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+    Md,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Md => "text/markdown; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Md => "md",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// The exported fields of a history entry.
+#[derive(Debug, Serialize)]
+struct ExportRecord<'a> {
+    title: &'a str,
+    authors: &'a str,
+    publication_date: Option<&'a str>,
+    url: &'a str,
+    summary: Option<&'a str>,
+}
+
+impl<'a> From<&'a NewsContent> for ExportRecord<'a> {
+    fn from(news: &'a NewsContent) -> Self {
+        ExportRecord {
+            title: &news.title,
+            authors: &news.authors,
+            publication_date: news.publication_date.as_deref(),
+            url: &news.url,
+            summary: news.summary.as_deref(),
+        }
+    }
+}
+
+/// Encodes `history` one record per chunk as it arrives, so the whole export
+/// is never held at once. An error of `history` ends the download early, the
+/// status went out with the first chunk.
+pub fn export_stream<S>(
+    history: S,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<Bytes, BackendError>> + Send + 'static
+where
+    S: Stream<Item = Result<NewsContent, BackendError>> + Send + 'static,
+{
+    let head = tokio_stream::iter(export_head(format)).map(Ok);
+    let mut index = 0;
+    let records = history.map(move |news| {
+        let record = export_record(format, index, &news?);
+        index += 1;
+        Ok(record)
+    });
+    let tail = tokio_stream::iter(export_tail(format)).map(Ok);
+
+    head.chain(records)
+        .chain(tail)
+        .map(|chunk| chunk.map(Bytes::from))
+}
+
+fn export_head(format: ExportFormat) -> Option<String> {
+    match format {
+        ExportFormat::Csv => Some("title,authors,publication_date,url,summary\r\n".into()),
+        ExportFormat::Json => Some("[".into()),
+        ExportFormat::Md => Some("# Riwayat analisis\n".into()),
+        ExportFormat::Ndjson => None,
+    }
+}
+
+fn export_tail(format: ExportFormat) -> Option<String> {
+    match format {
+        ExportFormat::Json => Some("\n]\n".into()),
+        ExportFormat::Csv | ExportFormat::Md | ExportFormat::Ndjson => None,
+    }
+}
+
+/// Record `index` of the export, `index` places the JSON array commas.
+fn export_record(format: ExportFormat, index: usize, news: &NewsContent) -> String {
+    let record = ExportRecord::from(news);
+    match format {
+        ExportFormat::Csv => {
+            let cells = [
+                record.title,
+                record.authors,
+                record.publication_date.unwrap_or_default(),
+                record.url,
+                record.summary.unwrap_or_default(),
+            ];
+            let mut row = cells.map(csv_cell).join(",");
+            row.push_str("\r\n");
+            row
+        }
+        ExportFormat::Json => {
+            let separator = if index == 0 { "\n" } else { ",\n" };
+            format!("{separator}{}", json_line(&record))
+        }
+        ExportFormat::Ndjson => format!("{}\n", json_line(&record)),
+        ExportFormat::Md => {
+            let mut entry = format!(
+                "\n## [{}]({})\n\n- Penulis: {}\n- Terbit: {}\n",
+                markdown_text(&single_line(record.title)),
+                record.url.replace(' ', "%20").replace(')', "%29"),
+                markdown_text(&single_line(record.authors)),
+                record.publication_date.unwrap_or("-"),
+            );
+            if let Some(summary) = record.summary.filter(|s| !s.is_empty()) {
+                entry.push_str(&format!("\n{}\n", markdown_text(summary)));
+            }
+            entry
+        }
+    }
+}
+
+fn json_line(record: &ExportRecord) -> String {
+    // Plain strings and options, serializing cannot fail.
+    serde_json::to_string(record).unwrap_or_default()
+}
+
+/// RFC 4180 quoting. Cells a spreadsheet would run as a formula get a
+/// leading `'`, titles come from arbitrary news sites.
+fn csv_cell(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Escapes what would turn plain text into markdown markup.
+fn markdown_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
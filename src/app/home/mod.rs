@@ -1 +1,3 @@
+pub mod handlers;
+pub mod history_export;
 pub mod routes;
@@ -2,158 +2,524 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::future::Future;
 
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use warp::Filter;
+use tokio_stream::StreamExt;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
 
-use crate::app::core::app_config::{load_config, with_app_config, AppConfig};
-use crate::app::core::authenticator::{with_cookies_session_auth, UserSessions};
-use crate::app::core::http_client::{with_http_client, HttpClient};
+use crate::app::core::api_token_store::ApiTokens;
+use crate::app::core::authenticator::{with_user_auth, UserSessions};
+use crate::app::core::backend_client::{with_backend, Backend, BackendError};
+use crate::app::core::csrf::{csrf_protect, with_csrf_cookie, with_csrf_token, CsrfToken};
+use crate::app::core::error::AppError;
 use crate::app::core::models::{NewsContent, UserHistoryT, UserIdT};
 use crate::app::core::renderer::{render, with_renderer, Renderer, WithTemplate};
+use crate::app::core::request_context::current_request_id;
+use crate::app::core::session_store::unix_now;
+use crate::app::core::summary_store::{with_summaries, Summaries};
+use crate::app::core::summary_stream::{proxy_summary_stream, replay_summary};
+use crate::app::home::handlers::{
+    analyze_news, edit_history, stored_summary_of_user, with_stored_summary, HistoryEdit, NoteForm,
+    PinForm,
+};
+use crate::app::home::history_export::{export_stream, ExportFormat};
 
 pub fn home_routes(
     renderer: Renderer,
-    http_client: HttpClient,
+    backend: Backend,
     sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    home_page(renderer.clone(), sessions.clone())
+    home_page(renderer.clone(), sessions.clone(), tokens.clone())
         .or(analyzer_search(
             renderer.clone(),
-            http_client.clone(),
+            backend.clone(),
             sessions.clone(),
+            tokens.clone(),
+            summaries.clone(),
+        ))
+        .or(analyzer_summary_stream(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+            summaries.clone(),
+        ))
+        .or(user_history_items(
+            renderer.clone(),
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(user_history(
+            renderer.clone(),
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(export_history(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+            summaries.clone(),
+        ))
+        .or(delete_history_entry(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(clear_history(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(pin_history_entry(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
+        ))
+        .or(set_history_note(
+            backend.clone(),
+            sessions.clone(),
+            tokens.clone(),
         ))
-        .or(user_history(renderer.clone(), http_client.clone()))
 }
 
 fn home_page(
     renderer: Renderer,
     sessions: UserSessions,
+    tokens: ApiTokens,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("home")
         .and(warp::path::end())
         .and(warp::get())
-        .and(with_cookies_session_auth(sessions.clone()))
-        .map(|user_id| {
-            println!("__home_page__: Extracting user ID based on cookie session -> {user_id}");
-            WithTemplate {
-                name: "home_page",
-                value: json!({
-                    "title": "Warptest",
-                    "subtitle": "testing some warp app",
-                    "user_id": user_id
-                }),
-            }
-        })
-        // -- y -> renderer()
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_csrf_token())
         .and(with_renderer(renderer.clone()))
-        // -- render(x, y)
-        .map(render)
+        .map(
+            |user_id: UserIdT, csrf_token: CsrfToken, renderer: Renderer| {
+                tracing::debug!(%user_id, "rendering home page");
+                let page = render(
+                    WithTemplate {
+                        name: "home_page",
+                        value: json!({
+                            "title": "Warptest",
+                            "subtitle": "testing some warp app",
+                            "csrf_token": csrf_token.value
+                        }),
+                    },
+                    renderer,
+                );
+                with_csrf_cookie(page, &csrf_token)
+            },
+        )
 }
 
 fn analyzer_search(
     renderer: Renderer,
-    http_client: HttpClient,
+    backend: Backend,
     sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     warp::path("analyze")
+        .and(warp::path::end())
         .and(warp::get())
         .and(warp::query())
         .map(|q: HashMap<String, String>| match q.get("url") {
             Some(url) => url.into(),
             None => "invalid query key!".into(),
         })
-        .and(with_http_client(http_client.clone()))
-        .and(with_cookies_session_auth(sessions.clone()))
-        .and(with_app_config(load_config().clone()))
-        .map(
-            |url: String, client: HttpClient, user_id: UserIdT, app_config: AppConfig| async move {
-                Ok(client
-                    .post(format!(
-                        "{}/{}/news-contents/parse-news-url",
-                        app_config.local_backend_api, user_id,
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .and(with_summaries(summaries.clone()))
+        .map(analyze_news)
+        .and(with_renderer(renderer.clone()))
+        .and_then(render_result)
+}
+
+/// What a path segment made by `render_result` keeps unescaped, a `.` is
+/// escaped too so an id can not be a dot segment.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'~');
+
+/// The next path segment, percent-decoded.
+fn decoded_param() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    warp::path::param::<String>().and_then(|segment: String| async move {
+        percent_decode_str(&segment)
+            .decode_utf8()
+            .map(|param| param.into_owned())
+            .map_err(|_| warp::reject::not_found())
+    })
+}
+
+/// Relays the backend's summary of `content_id` as server-sent events, so the
+/// browser never talks to the backend itself.
+fn analyzer_summary_stream(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path("analyze")
+        .and(decoded_param())
+        .and(warp::path("summary"))
+        .and(warp::path("stream"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .and(with_summaries(summaries.clone()))
+        .then(
+            |content_id: ContentID, user_id: UserIdT, backend: Backend, summaries: Summaries| async move {
+                if let Some(summary) =
+                    stored_summary_of_user(&user_id, &content_id, &backend, &summaries).await
+                {
+                    tracing::debug!(%content_id, "replaying stored summary");
+                    return warp::sse::reply(replay_summary(summary)).into_response();
+                }
+                match backend.summarize_stream(&user_id, &content_id).await {
+                    Ok(backend_stream) => warp::sse::reply(warp::sse::keep_alive().stream(
+                        proxy_summary_stream(backend_stream, content_id, summaries),
                     ))
-                    .query(&[("news_url", url)])
-                    .send()
-                    .await?
-                    .json()
-                    .await?)
+                    .into_response(),
+                    Err(backend_error) => {
+                        tracing::warn!(error = %backend_error, %content_id, "opening summary stream failed");
+                        let status = match backend_error {
+                            BackendError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+                            _ => StatusCode::BAD_GATEWAY,
+                        };
+                        status.into_response()
+                    }
+                }
             },
         )
-        .and(with_renderer(renderer.clone()))
-        .and(with_cookies_session_auth(sessions.clone()))
-        .and(with_app_config(load_config().clone()))
-        .and_then(render_result)
 }
 
+/// `GET /me/history`, or `GET /{user_id}/history` of the logged in user only.
+/// Renders the whole drawer with the first page of the history.
 fn user_history(
     renderer: Renderer,
-    http_client: HttpClient,
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     type UserID = String;
-    warp::any()
-        .and(warp::path::param::<UserID>()) // s(String,)
-        .and(warp::path("history")) // ((),)
+    let my_history = warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_user_auth(sessions.clone(), tokens.clone()));
+    let history_of = warp::path::param::<UserID>()
+        .and(warp::path("history"))
         .and(warp::path::end())
         .and(warp::get())
-        .and(with_http_client(http_client.clone())) // s(String, HttpClient,)
-        .and(with_app_config(load_config())) // s(String, Http Client, AppConfig,)
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and_then(|path_user_id: UserID, user_id: UserIdT| async move {
+            if path_user_id != user_id {
+                tracing::warn!(%user_id, %path_user_id, "history of another user requested");
+                return Err(warp::reject::custom(AppError::Forbidden));
+            }
+            Ok(user_id)
+        });
+
+    my_history
+        .or(history_of)
+        .unify() // (UserIdT,)
+        .and(with_backend(backend.clone())) // (UserIdT, Backend,)
         .map(
-            |user_id, client: HttpClient, config: AppConfig| async move {
-                // let user_history = UserHistoryT::default();
-                // let _user_history_endpoint =
-                // "http://localhost:8000/api/v0/users/fa160d0b-2922-496e-a7b0-abc133c48ca7/history";
-                Ok(client
-                    .get(format!(
-                        "{}/users/{}/history",
-                        config.local_backend_api, user_id
-                    ))
-                    .send()
-                    .await?
-                    .json()
-                    .await?)
-            },
+            |user_id: UserIdT, backend: Backend| async move { backend.user_history(&user_id).await },
         ) // (Result<NewsContent[], Error>)
-        .and(with_renderer(renderer.clone())) // (Result<..>, Renderer,)
-        .and_then(render_history_list)
+        .and(warp::query::<HistoryQuery>()) // (Result<..>, HistoryQuery,)
+        .and(with_renderer(renderer.clone())) // (Result<..>, HistoryQuery, Renderer,)
+        .and_then(render_history_drawer)
 }
 
-async fn render_result(
-    news_content: impl Future<Output = Result<NewsContent, reqwest::Error>>,
+/// `GET /me/history/items`, only the list items, for the search box and the
+/// "load more" button.
+fn user_history_items(
     renderer: Renderer,
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path("items"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .map(
+            |user_id: UserIdT, backend: Backend| async move { backend.user_history(&user_id).await },
+        )
+        .and(warp::query::<HistoryQuery>())
+        .and(with_renderer(renderer.clone()))
+        .and_then(render_history_items)
+}
+
+// -- BLOCK: HISTORY_EXPORT
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    /// `YYYY-MM-DD`, inclusive bounds on the publication date.
+    from: Option<String>,
+    to: Option<String>,
+}
+
+/// `GET /me/history/export?format=csv|json|md|ndjson&from=&to=`, a download
+/// of the history streamed record by record, with the stored summaries.
+fn export_history(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(warp::query::<ExportQuery>())
+        .and(with_backend(backend.clone()))
+        .and(with_summaries(summaries.clone()))
+        .and_then(handle_export_history)
+}
+
+/// An empty bound is no bound, a malformed one is rejected.
+fn export_bound(date: Option<&str>) -> Result<Option<i64>, warp::Rejection> {
+    match date.map(str::trim).filter(|date| !date.is_empty()) {
+        None => Ok(None),
+        Some(date) if date.len() == 10 => days_from_date(date)
+            .map(Some)
+            .ok_or_else(|| AppError::InvalidInput.into()),
+        Some(_) => Err(AppError::InvalidInput.into()),
+    }
+}
+
+async fn handle_export_history(
     user_id: UserIdT,
-    app_config: AppConfig,
+    query: ExportQuery,
+    backend: Backend,
+    summaries: Summaries,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let from = export_bound(query.from.as_deref())?;
+    let to = export_bound(query.to.as_deref())?;
+
+    let history = match backend.user_history_stream(&user_id).await {
+        Ok(history) => history,
+        Err(e) => {
+            tracing::warn!(error = %e, "loading history failed");
+            return Err(AppError::from(e).into());
+        }
+    };
+    // Undated entries cannot be placed in the range.
+    let in_range = move |news: &NewsContent| {
+        (from.is_none() && to.is_none())
+            || news
+                .publication_date
+                .as_deref()
+                .and_then(days_from_date)
+                .is_some_and(|day| {
+                    from.is_none_or(|from| day >= from) && to.is_none_or(|to| day <= to)
+                })
+    };
+    let history = history
+        .filter(move |news| news.as_ref().map_or(true, &in_range))
+        .then(move |news| {
+            let summaries = summaries.clone();
+            async move {
+                match news {
+                    Ok(news) => Ok(with_stored_summary(news, &summaries).await),
+                    Err(e) => Err(e),
+                }
+            }
+        })
+        .map(|news| news.inspect_err(|e| tracing::warn!(error = %e, "history export stopped")));
+    tracing::info!(format = ?query.format, "exporting history");
+
+    let format = query.format;
+    let body = warp::hyper::Body::wrap_stream(export_stream(history, format));
+    let mut response = warp::reply::Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(
+        warp::http::header::CONTENT_TYPE,
+        warp::http::HeaderValue::from_static(format.content_type()),
+    );
+    if let Ok(disposition) = warp::http::HeaderValue::from_str(&format!(
+        "attachment; filename=\"riwayat-analisis.{}\"",
+        format.extension()
+    )) {
+        headers.insert(warp::http::header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(response)
+}
+// -- ENDBLOCK: HISTORY_EXPORT
+
+// -- BLOCK: HISTORY_EDITING
+/// htmx event the history list reloads itself on, see `history_drawer_component`.
+const HISTORY_CHANGED_EVENT: &str = "history-changed";
+
+const HISTORY_FORM_MAX_BYTES: u64 = 4 * 1024;
+
+fn delete_history_entry(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path::param::<ContentID>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(csrf_protect())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |content_id: ContentID, user_id: UserIdT, backend: Backend| async move {
+                edit_history(user_id, HistoryEdit::Delete { content_id }, backend).await?;
+                Ok::<_, warp::Rejection>(history_changed())
+            },
+        )
+}
+
+fn clear_history(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(csrf_protect())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(with_backend(backend.clone()))
+        .and_then(|user_id: UserIdT, backend: Backend| async move {
+            edit_history(user_id, HistoryEdit::Clear, backend).await?;
+            Ok::<_, warp::Rejection>(history_changed())
+        })
+}
+
+fn pin_history_entry(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path::param::<ContentID>())
+        .and(warp::path("pin"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(csrf_protect())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(warp::body::content_length_limit(HISTORY_FORM_MAX_BYTES))
+        .and(warp::body::form::<PinForm>())
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |content_id: ContentID, user_id: UserIdT, form: PinForm, backend: Backend| async move {
+                let edit = HistoryEdit::Pin {
+                    content_id,
+                    pinned: form.pinned,
+                };
+                edit_history(user_id, edit, backend).await?;
+                Ok::<_, warp::Rejection>(history_changed())
+            },
+        )
+}
+
+fn set_history_note(
+    backend: Backend,
+    sessions: UserSessions,
+    tokens: ApiTokens,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    type ContentID = String;
+    warp::path("me")
+        .and(warp::path("history"))
+        .and(warp::path::param::<ContentID>())
+        .and(warp::path("note"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(csrf_protect())
+        .and(with_user_auth(sessions.clone(), tokens.clone()))
+        .and(warp::body::content_length_limit(HISTORY_FORM_MAX_BYTES))
+        .and(warp::body::form::<NoteForm>())
+        .and(with_backend(backend.clone()))
+        .and_then(
+            |content_id: ContentID, user_id: UserIdT, note: NoteForm, backend: Backend| async move {
+                edit_history(user_id, HistoryEdit::Note { content_id, note }, backend).await?;
+                Ok::<_, warp::Rejection>(history_changed())
+            },
+        )
+}
+
+/// `204 No Content` telling htmx to reload the history list.
+fn history_changed() -> warp::reply::Response {
+    warp::reply::with_header(StatusCode::NO_CONTENT, "HX-Trigger", HISTORY_CHANGED_EVENT)
+        .into_response()
+}
+// -- ENDBLOCK: HISTORY_EDITING
+
+async fn render_result(
+    news_content: impl Future<Output = Result<NewsContent, BackendError>>,
+    renderer: Renderer,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
     match news_content.await {
         Ok(content) => {
-            // println!("__content__: {:?}", content);
-            let content_id_clone = content.id.clone().unwrap();
-            let summarizer_service_endpoint = format!(
-                "{}/{}/news_contents/summarize-news-content-stream?news_content_id={}",
-                app_config.local_backend_api, user_id, content_id_clone
-            );
-            // println!(
-            //     "__render_result__: Content ID -> {}\nEndpoint -> {summarizer_endpoint}",
-            //     content.id.clone().unwrap()
-            // );
+            // Streamed only without a summary yet, and only for content the
+            // backend gave an id, like `analyze_news` does with the store.
+            let summarizer_endpoint = match (&content.id, &content.summary) {
+                (Some(content_id), None) => Some(format!(
+                    "/analyze/{}/summary/stream",
+                    utf8_percent_encode(content_id, PATH_SEGMENT)
+                )),
+                _ => None,
+            };
+            if content.id.is_none() {
+                tracing::warn!(url = %content.url, "analyzed content has no id, not summarizing");
+            }
+            let summary = content.summary.clone();
             Ok(render(
                 WithTemplate {
                     name: "analyze_result_component",
                     value: json!({
                         "news_content": content,
-                        "summarizer_endpoint": summarizer_service_endpoint
+                        "summarizer_endpoint": summarizer_endpoint,
+                        "summary": summary
+                    }),
+                },
+                renderer.clone(),
+            ))
+        }
+        Err(BackendError::Unavailable { retry_after_secs }) => {
+            tracing::warn!(retry_after_secs, "analyzer unavailable, not calling it");
+            Ok(render(
+                WithTemplate {
+                    name: "analyzer_unavailable_component",
+                    value: json!({
+                        "retry_after_secs": retry_after_secs,
+                        "request_id": current_request_id(),
                     }),
                 },
                 renderer.clone(),
             ))
         }
         Err(e) => {
-            println!("__render_result__: ERROR {}", e.to_string());
+            tracing::warn!(error = %e, "analyzing news failed");
             Ok(render(
                 WithTemplate {
                     name: "analyze_result_error_component",
                     value: json!({
                         "error": e.to_string(),
-                        "message": e.source().unwrap().to_string(),
+                        "message": e.source().map_or_else(|| e.to_string(), |source| source.to_string()),
+                        "request_id": current_request_id(),
                         "instructions": [
                         "Pastikan url mengarah ke suatu media berita",
                         "Coba gunakan media berita mainstream",
@@ -167,30 +533,254 @@ async fn render_result(
     }
 }
 
-async fn render_history_list(
-    user_history: impl Future<Output = Result<UserHistoryT, reqwest::Error>>,
+// -- BLOCK: HISTORY_LISTING
+/// History entries per page of the drawer.
+const HISTORY_PAGE_SIZE: usize = 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HistorySort {
+    /// Last analyzed first, the backend lists the history oldest first.
+    #[default]
+    Recent,
+    Oldest,
+    /// Newest publication date first.
+    Published,
+    Title,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct HistoryQuery {
+    /// Matched against title, authors, note and the domain of the url.
+    q: String,
+    sort: HistorySort,
+    /// Id of the last entry of the previous page. An id rather than a
+    /// position, deleting an entry would shift positions under the next page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+enum HistorySection {
+    #[serde(rename = "Disematkan")]
+    Pinned,
+    #[serde(rename = "Hari ini")]
+    Today,
+    #[serde(rename = "Minggu ini")]
+    ThisWeek,
+    #[serde(rename = "Lebih lama")]
+    Older,
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryGroup {
+    /// `None` when the sort does not group the history.
+    label: Option<HistorySection>,
+    /// The previous page ended in this group, the label is already shown.
+    continued: bool,
+    items: Vec<NewsContent>,
+}
+
+/// Days since the unix epoch of a `YYYY-MM-DD...` date.
+fn days_from_date(date: &str) -> Option<i64> {
+    let mut parts = date.get(..10)?.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Howard Hinnant's days_from_civil.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+/// Pinned entries first, the rest grouped by publication date since the
+/// history has no date of the analysis. Undated entries count as older.
+fn history_section(news: &NewsContent, today: i64) -> HistorySection {
+    if news.pinned {
+        return HistorySection::Pinned;
+    }
+    // 1970-01-01 was a thursday, weeks start on monday.
+    let week_start = today - (today + 3).rem_euclid(7);
+    match news.publication_date.as_deref().and_then(days_from_date) {
+        Some(day) if day >= today => HistorySection::Today,
+        Some(day) if day >= week_start => HistorySection::ThisWeek,
+        _ => HistorySection::Older,
+    }
+}
+
+fn news_domain(news: &NewsContent) -> String {
+    reqwest::Url::parse(&news.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn matches_history_search(news: &NewsContent, search: &str) -> bool {
+    search.is_empty()
+        || [
+            &news.title,
+            &news.authors,
+            news.note.as_deref().unwrap_or_default(),
+            &news_domain(news),
+        ]
+        .iter()
+        .any(|field| field.to_lowercase().contains(search))
+}
+
+/// Filters and sorts the history, then cuts the page after `cursor`. Returns
+/// the groups of the page and the cursor of the next one.
+///
+/// Pinned entries come first in every sort. Only the publication date sort
+/// is grouped further, by [`history_section`], any other sort would be broken
+/// up by the groups and only labels the pinned ones. A page whose cursor entry was
+/// deleted meanwhile is empty, the drawer reloads the list on each change.
+fn history_page(
+    history: Vec<NewsContent>,
+    query: &HistoryQuery,
+    today: i64,
+) -> (Vec<HistoryGroup>, Option<String>) {
+    let search = query.q.trim().to_lowercase();
+    let mut history = history
+        .into_iter()
+        // Pages are cut at entry ids, an entry without one could not be
+        // continued after, nor edited.
+        .filter(|news| news.id.is_some() && matches_history_search(news, &search))
+        .collect::<Vec<_>>();
+
+    match query.sort {
+        HistorySort::Recent => history.reverse(),
+        HistorySort::Oldest => (),
+        HistorySort::Published => history.sort_by(|a, b| {
+            let published =
+                |news: &NewsContent| news.publication_date.as_deref().and_then(days_from_date);
+            published(b).cmp(&published(a))
+        }),
+        HistorySort::Title => history.sort_by_key(|news| news.title.to_lowercase()),
+    }
+    // Pinned entries lead in every sort, the sort is stable so both parts
+    // keep the chosen order.
+    history.sort_by_key(|news| !news.pinned);
+    let section_of = |news: &NewsContent| match query.sort {
+        HistorySort::Published => Some(history_section(news, today)),
+        _ => news.pinned.then_some(HistorySection::Pinned),
+    };
+
+    let start = match query.cursor.as_deref().filter(|cursor| !cursor.is_empty()) {
+        None => 0,
+        Some(cursor) => history
+            .iter()
+            .position(|news| news.id.as_deref() == Some(cursor))
+            .map_or(history.len(), |last| last + 1),
+    };
+    let end = history.len().min(start + HISTORY_PAGE_SIZE);
+    let next_cursor = if end < history.len() {
+        history[end - 1].id.clone()
+    } else {
+        None
+    };
+    let mut previous_section = start
+        .checked_sub(1)
+        .and_then(|last| history.get(last))
+        .map(section_of);
+
+    let mut groups: Vec<HistoryGroup> = Vec::new();
+    for news in history.into_iter().take(end).skip(start) {
+        let section = section_of(&news);
+        match groups.last_mut() {
+            Some(group) if group.label == section => group.items.push(news),
+            _ => groups.push(HistoryGroup {
+                label: section,
+                continued: previous_section == Some(section),
+                items: vec![news],
+            }),
+        }
+        previous_section = Some(section);
+    }
+
+    (groups, next_cursor)
+}
+
+async fn render_history_drawer(
+    user_history: impl Future<Output = Result<UserHistoryT, BackendError>>,
+    query: HistoryQuery,
     renderer: Renderer,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-    match user_history.await {
-        Ok(h) => Ok(render(
-            WithTemplate {
-                name: "history_drawer_component",
-                value: json!({
-                    "history": h.0
-                }),
-            },
-            renderer.clone(),
-        )),
-        Err(_) => Ok(render(
-            {
+    render_history(
+        user_history.await,
+        query,
+        renderer,
+        "history_drawer_component",
+    )
+}
+
+async fn render_history_items(
+    user_history: impl Future<Output = Result<UserHistoryT, BackendError>>,
+    query: HistoryQuery,
+    renderer: Renderer,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+    render_history(
+        user_history.await,
+        query,
+        renderer,
+        "history_list_component",
+    )
+}
+
+fn render_history(
+    user_history: Result<UserHistoryT, BackendError>,
+    query: HistoryQuery,
+    renderer: Renderer,
+    template: &'static str,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+    match user_history {
+        Ok(h) => {
+            let today = (unix_now() / (24 * 60 * 60)) as i64;
+            let (groups, next_cursor) = history_page(h.0.unwrap_or_default(), &query, today);
+            let next_page = next_cursor.map(|cursor| {
+                let next = HistoryQuery {
+                    cursor: Some(cursor),
+                    ..query.clone()
+                };
+                format!(
+                    "/me/history/items?{}",
+                    serde_urlencoded::to_string(&next).unwrap_or_default()
+                )
+            });
+            Ok(render(
                 WithTemplate {
-                    name: "history_drawer_component",
+                    name: template,
                     value: json!({
-                        "error": "some error!"
+                        "groups": groups,
+                        "next_page": next_page,
+                        "query": query,
                     }),
-                }
-            },
-            renderer.clone(),
-        )),
+                },
+                renderer.clone(),
+            ))
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "loading history failed");
+            Ok(render(
+                {
+                    WithTemplate {
+                        name: template,
+                        value: json!({
+                            "error": "some error!",
+                            "query": query,
+                        }),
+                    }
+                },
+                renderer.clone(),
+            ))
+        }
     }
 }
+// -- ENDBLOCK: HISTORY_LISTING
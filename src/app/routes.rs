@@ -1,15 +1,21 @@
 use warp::Filter;
 
 use super::auth::routes::auth_routes;
+use super::core::api_token_store::ApiTokens;
 use super::core::authenticator::UserSessions;
-use super::core::http_client::HttpClient;
+use super::core::backend_client::Backend;
+use super::core::rate_limiter::LoginLimiter;
 use super::core::renderer::Renderer;
+use super::core::summary_store::Summaries;
 use super::home::routes::home_routes;
 
 pub fn app_routes(
     renderer: Renderer,
-    http_client: HttpClient,
+    backend: Backend,
     session: UserSessions,
+    tokens: ApiTokens,
+    summaries: Summaries,
+    login_limiter: LoginLimiter,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let some_app = warp::path("app")
         .and(warp::path::end())
@@ -19,12 +25,16 @@ pub fn app_routes(
     some_app
         .or(auth_routes(
             renderer.clone(),
-            http_client.clone(),
+            backend.clone(),
             session.clone(),
+            tokens.clone(),
+            login_limiter.clone(),
         ))
         .or(home_routes(
             renderer.clone(),
-            http_client.clone(),
+            backend.clone(),
             session.clone(),
+            tokens.clone(),
+            summaries.clone(),
         ))
 }
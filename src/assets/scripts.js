@@ -37,3 +37,12 @@ function loadNewsHistory(historyItemAnchor) {
   analyzeInput.value = historyItemAnchor.getAttribute("data-history-url");
   toggleDrawer(document.querySelector("#drawer-button"));
 }
+
+// htmx does not swap 4xx/5xx replies, except the error fragments the server
+// retargets to #error-toast.
+document.addEventListener("htmx:beforeSwap", (evt) => {
+  const retarget = evt.detail.xhr.getResponseHeader("HX-Retarget");
+  if (evt.detail.isError && retarget === "#error-toast") {
+    evt.detail.shouldSwap = true;
+  }
+});
@@ -10,17 +10,22 @@ pub fn warptest_check() -> String {
 
 #[inline]
 pub fn project(path: &str) -> String {
-    match path.chars().nth(0) {
-        Some(c) => assert!(
+    if let Some(c) = path.chars().next() {
+        assert!(
             c == '/',
             "__project_fn__: ERR, Path to '{path}' must start with '/' !"
-        ),
-        _ => (),
+        )
     }
     format!("{PROJECT_SOURCE}{path}")
 }
 
 pub fn register_templates(hb: &mut Handlebars) {
+    register_templates_in(hb, PROJECT_SOURCE)
+}
+
+/// Like [`register_templates`], but reads the templates from `source_dir`
+/// instead of [`PROJECT_SOURCE`], e.g. `concat!(env!("CARGO_MANIFEST_DIR"), "/src")`.
+pub fn register_templates_in(hb: &mut Handlebars, source_dir: &str) {
     let templates = [
         ("index_html", "/index.html"),
         ("home_page", "/app/home/home_page.html"),
@@ -32,6 +37,10 @@ pub fn register_templates(hb: &mut Handlebars) {
             "analyze_result_error_component",
             "/app/home/analyze_result_error_component.html",
         ),
+        (
+            "analyzer_unavailable_component",
+            "/app/home/analyzer_unavailable_component.html",
+        ),
         (
             "analyze_search_component",
             "/app/home/analyze_search_component.html",
@@ -40,14 +49,20 @@ pub fn register_templates(hb: &mut Handlebars) {
             "history_drawer_component",
             "/app/home/history_drawer_component.html",
         ),
+        (
+            "history_list_component",
+            "/app/home/history_list_component.html",
+        ),
         ("auth_page", "/app/auth/auth_page.html"),
         ("login_page", "/app/auth/login_page.html"),
         ("register_page", "/app/auth/register_page.html"),
         ("error_page", "/assets/error_page.html"),
+        ("error_fragment", "/assets/error_fragment.html"),
         ("phantom_html", "/assets/phantom.html"),
     ];
 
     for (name, path) in templates {
-        hb.register_template_file(name, project(path)).unwrap();
+        hb.register_template_file(name, format!("{source_dir}{path}"))
+            .unwrap();
     }
 }
@@ -1,14 +1,24 @@
-use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Duration;
 
 use handlebars::Handlebars;
-use tokio::sync::Mutex;
+use hyper::server::conn::AddrStream;
+use hyper::service::make_service_fn;
 use warp::Filter;
-use warptest::app::core::app_config::load_config;
-use warptest::app::core::authenticator::SessionIdT;
-use warptest::app::core::error::redirect_on_reject;
-use warptest::app::core::models::UserIdT;
-use warptest::app::core::routes::error_routes;
+use warptest::app::api::routes::api_routes;
+use warptest::app::core::api_token_store::new_api_token_store;
+use warptest::app::core::app_config::init_config;
+use warptest::app::core::authenticator::{renew_session_cookie, spawn_session_sweeper};
+use warptest::app::core::backend_client::{Backend, ReqwestBackendClient};
+use warptest::app::core::error::{json_on_reject, recover_negotiated};
+use warptest::app::core::logging::{init_tracing, with_request_tracing};
+use warptest::app::core::news_cache::{new_news_cache, CachingBackendClient};
+use warptest::app::core::rate_limiter::LoginLimiterT;
+use warptest::app::core::request_context::RequestContext;
+use warptest::app::core::routes::{error_routes, monitoring_routes};
+use warptest::app::core::session_store::{new_session_store, SessionPolicy};
+use warptest::app::core::summary_store::new_summary_store;
 use warptest::app::routes::app_routes;
 use warptest::{project, register_templates};
 
@@ -16,9 +26,50 @@ use warptest::{project, register_templates};
 async fn main() {
     // -- BLOCK: CONFIGURE_APP
     //
-    let _app_config = { load_config() };
+    let app_config = {
+        match init_config() {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("__main__: ERR, failed to load config: {err}");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    if let Err(err) = init_tracing(&app_config) {
+        eprintln!("__main__: ERR, failed to set up logging: {err}");
+        std::process::exit(1);
+    }
+
+    let users_sessions = {
+        match new_session_store(&app_config).await {
+            Ok(store) => store,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to open session store");
+                std::process::exit(1);
+            }
+        }
+    };
 
-    let users_sessions = { Arc::new(Mutex::new(HashMap::<SessionIdT, UserIdT>::new())) };
+    let summaries = {
+        match new_summary_store(&app_config).await {
+            Ok(store) => store,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to open summary store");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let api_tokens = {
+        match new_api_token_store(&app_config).await {
+            Ok(store) => store,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to open api token store");
+                std::process::exit(1);
+            }
+        }
+    };
 
     let hb = {
         let mut hb = Handlebars::new();
@@ -26,7 +77,28 @@ async fn main() {
         Arc::new(hb)
     };
 
-    let rqwest = { Arc::new(reqwest::Client::new()) };
+    let news_cache = {
+        match new_news_cache(&app_config).await {
+            Ok(cache) => cache,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to open news cache");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let backend: Backend = {
+        match ReqwestBackendClient::new(&app_config) {
+            Ok(client) => Arc::new(CachingBackendClient::new(
+                Arc::new(client),
+                news_cache.clone(),
+            )),
+            Err(err) => {
+                tracing::error!(error = %err, "failed to build backend client");
+                std::process::exit(1);
+            }
+        }
+    };
 
     let cors = {
         warp::cors()
@@ -44,23 +116,73 @@ async fn main() {
     let assets_route =
         { warp::get().and(warp::path("assets").and(warp::fs::dir(project("/assets")))) };
 
-    let _version_prefix = { warp::path("v1") };
+    let login_limiter = { Arc::new(LoginLimiterT::from_config(&app_config)) };
+
+    let version_prefix = { warp::path("v1") };
+
+    let api_route = {
+        version_prefix.and(
+            renew_session_cookie(
+                api_routes(
+                    backend.clone(),
+                    users_sessions.clone(),
+                    api_tokens.clone(),
+                    summaries.clone(),
+                    login_limiter.clone(),
+                ),
+                users_sessions.clone(),
+            )
+            .recover(json_on_reject),
+        )
+    };
 
     let routes = {
-        root_redirect
+        let routes = root_redirect
             .or(assets_route)
-            .or(app_routes(
-                hb.clone(),
-                rqwest.clone(),
+            .or(api_route)
+            .or(renew_session_cookie(
+                app_routes(
+                    hb.clone(),
+                    backend.clone(),
+                    users_sessions.clone(),
+                    api_tokens.clone(),
+                    summaries.clone(),
+                    login_limiter.clone(),
+                ),
                 users_sessions.clone(),
             ))
             .or(error_routes(hb.clone()))
+            .or(monitoring_routes(news_cache.clone()))
             // .or(warp::any()
             //     .map(|| warp::redirect(warp::http::Uri::from_static("/error/not-found"))))
             .with(cors)
-            .recover(redirect_on_reject)
+            // Erases the route tree type, it is too deep to compile once more
+            // wrapped by the recovery.
+            .boxed();
+        with_request_tracing(recover_negotiated(routes, hb.clone()))
     };
     // -- ENDBLOCK: CONFIGURE_APP
 
-    warp::serve(routes).run(([127, 0, 0, 1], 6969)).await;
+    spawn_session_sweeper(
+        users_sessions.clone(),
+        SessionPolicy::from_config(&app_config),
+        Duration::from_secs(app_config.session_sweep_interval_secs),
+    );
+
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let service = RequestContext::new(warp::service(routes.clone()), Some(conn.remote_addr()));
+        async move { Ok::<_, Infallible>(service) }
+    });
+    let server = match hyper::Server::try_bind(&app_config.address) {
+        Ok(builder) => builder.serve(make_service),
+        Err(err) => {
+            tracing::error!(error = %err, "server failed");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(address = %server.local_addr(), "listening");
+    if let Err(err) = server.await {
+        tracing::error!(error = %err, "server failed");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,294 @@
+mod support;
+
+use serde_json::{json, Value};
+use support::*;
+use warp::http::Response;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+use warptest::app::core::api_token_store::{
+    hash_api_token, ApiToken, ApiTokenStore, FileApiTokenStore,
+};
+
+fn json_body(res: &Response<Bytes>) -> Value {
+    serde_json::from_slice(res.body()).unwrap()
+}
+
+async fn create_token<F>(app: &F, cookie: &str, name: &str) -> Response<Bytes>
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    let (cookie, csrf) = with_csrf(app, cookie).await;
+    warp::test::request()
+        .method("POST")
+        .path("/v1/me/tokens")
+        .header("cookie", cookie)
+        .header("x-csrf-token", csrf)
+        .header("content-type", "application/json")
+        .body(json!({ "name": name }).to_string())
+        .reply(app)
+        .await
+}
+
+async fn get_with_bearer<F>(app: &F, path: &str, token: &str) -> Response<Bytes>
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    warp::test::request()
+        .path(path)
+        .header("authorization", format!("Bearer {token}"))
+        .reply(app)
+        .await
+}
+
+// -- BLOCK: TOKEN_LIFECYCLE
+#[tokio::test]
+async fn created_token_is_shown_once_and_listed_without_it() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = create_token(&app, &cookie, "  cron laporan  ").await;
+
+    assert_eq!(res.status(), 201);
+    let created = json_body(&res);
+    assert!(created["token"].as_str().unwrap().starts_with("sgk_"));
+    assert_eq!(created["name"], "cron laporan");
+    assert_eq!(created["last_used"], Value::Null);
+
+    let res = warp::test::request()
+        .path("/v1/me/tokens")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 200);
+    let listed = json_body(&res);
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["id"], created["id"]);
+    assert!(listed[0].get("token").is_none());
+    assert!(listed[0].get("token_hash").is_none());
+}
+
+#[tokio::test]
+async fn bearer_token_authenticates_api_and_page_routes() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let token = json_body(&create_token(&app, &cookie, "notebook").await)["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let api = get_with_bearer(&app, "/v1/me/history", &token).await;
+    assert_eq!(api.status(), 200);
+    assert_eq!(json_body(&api)[0]["title"], FAKE_TITLE);
+
+    let page = get_with_bearer(&app, "/me/history", &token).await;
+    assert_eq!(page.status(), 200);
+    assert!(body_text(&page).contains(FAKE_TITLE));
+
+    let history = fake.requests_to(Endpoint::History);
+    assert!(history.iter().all(|req| req.path.contains(FAKE_USER_ID)));
+
+    let listed = warp::test::request()
+        .path("/v1/me/tokens")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    assert!(json_body(&listed)[0]["last_used"].is_u64());
+}
+
+#[tokio::test]
+async fn token_management_needs_the_session_cookie() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let created = json_body(&create_token(&app, &cookie, "bocor").await);
+    let token = created["token"].as_str().unwrap();
+
+    let list = get_with_bearer(&app, "/v1/me/tokens", token).await;
+    assert_eq!(list.status(), 401);
+
+    let create = warp::test::request()
+        .method("POST")
+        .path("/v1/me/tokens")
+        .header("authorization", format!("Bearer {token}"))
+        .header("cookie", &cookie)
+        .header("content-type", "application/json")
+        .body(json!({ "name": "turunan" }).to_string())
+        .reply(&app)
+        .await;
+    assert_eq!(create.status(), 401);
+
+    let revoke = warp::test::request()
+        .method("DELETE")
+        .path(&format!(
+            "/v1/me/tokens/{}",
+            created["id"].as_str().unwrap()
+        ))
+        .header("authorization", format!("Bearer {token}"))
+        .reply(&app)
+        .await;
+    assert_eq!(revoke.status(), 401);
+    assert_eq!(
+        get_with_bearer(&app, "/v1/me/history", token)
+            .await
+            .status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn bearer_edits_skip_the_csrf_token() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let token = json_body(&create_token(&app, &cookie, "skrip").await)["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let res = warp::test::request()
+        .method("DELETE")
+        .path(&format!("/me/history/{FAKE_CONTENT_ID}"))
+        .header("authorization", format!("Bearer {token}"))
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 204);
+    assert_eq!(fake.requests_to(Endpoint::DeleteHistoryEntry).len(), 1);
+}
+
+#[tokio::test]
+async fn bad_bearer_token_does_not_fall_back_to_the_cookie() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .method("DELETE")
+        .path("/v1/me/history")
+        .header("cookie", &cookie)
+        .header("authorization", "Bearer sgk_palsu")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 401);
+    assert!(fake.requests_to(Endpoint::ClearHistory).is_empty());
+}
+
+#[tokio::test]
+async fn non_bearer_authorization_uses_the_cookie() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/v1/me/history")
+        .header("cookie", &cookie)
+        .header("authorization", "Basic YWRtaW46YWRtaW4=")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(fake.requests_to(Endpoint::History).len(), 1);
+}
+
+#[tokio::test]
+async fn revoked_token_is_rejected() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let created = json_body(&create_token(&app, &cookie, "sekali pakai").await);
+    let token = created["token"].as_str().unwrap();
+    let revoke_path = format!("/v1/me/tokens/{}", created["id"].as_str().unwrap());
+    let (cookie, csrf) = with_csrf(&app, &cookie).await;
+
+    let revoke = warp::test::request()
+        .method("DELETE")
+        .path(&revoke_path)
+        .header("cookie", &cookie)
+        .header("x-csrf-token", &csrf)
+        .reply(&app)
+        .await;
+    assert_eq!(revoke.status(), 204);
+
+    let res = get_with_bearer(&app, "/v1/me/history", token).await;
+    assert_eq!(res.status(), 401);
+
+    let again = warp::test::request()
+        .method("DELETE")
+        .path(&revoke_path)
+        .header("cookie", &cookie)
+        .header("x-csrf-token", &csrf)
+        .reply(&app)
+        .await;
+    assert_eq!(again.status(), 404);
+}
+
+#[tokio::test]
+async fn token_name_must_be_given_and_short() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    assert_eq!(create_token(&app, &cookie, "   ").await.status(), 400);
+    assert_eq!(
+        create_token(&app, &cookie, &"x".repeat(65)).await.status(),
+        400
+    );
+}
+// -- ENDBLOCK: TOKEN_LIFECYCLE
+
+// -- BLOCK: FILE_STORE
+#[tokio::test]
+async fn file_store_keeps_hashes_across_a_restart() {
+    let path = std::env::temp_dir().join(format!("api-tokens-{}.jsonl", uuid::Uuid::new_v4()));
+    let token = |id: &str, secret: &str| ApiToken {
+        id: id.into(),
+        user_id: FAKE_USER_ID.into(),
+        name: id.into(),
+        token_hash: hash_api_token(secret),
+        created_at: 1,
+        last_used: None,
+    };
+
+    let store = FileApiTokenStore::open(&path).await.unwrap();
+    store.insert(token("a", "sgk_rahasia_a")).await.unwrap();
+    store.insert(token("b", "sgk_rahasia_b")).await.unwrap();
+    store
+        .touch(&hash_api_token("sgk_rahasia_a"), 42)
+        .await
+        .unwrap();
+    assert!(store.revoke(FAKE_USER_ID, "b").await.unwrap());
+    assert!(!store.revoke("orang-lain", "a").await.unwrap());
+    drop(store);
+
+    assert!(!std::fs::read_to_string(&path)
+        .unwrap()
+        .contains("sgk_rahasia"));
+
+    let reopened = FileApiTokenStore::open(&path).await.unwrap();
+    let found = reopened
+        .find(&hash_api_token("sgk_rahasia_a"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.last_used, Some(42));
+    assert!(reopened
+        .find(&hash_api_token("sgk_rahasia_b"))
+        .await
+        .unwrap()
+        .is_none());
+    std::fs::remove_file(path).unwrap();
+}
+// -- ENDBLOCK: FILE_STORE
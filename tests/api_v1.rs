@@ -0,0 +1,411 @@
+mod support;
+
+use serde_json::{json, Value};
+use support::*;
+use warp::http::Response;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+use warptest::app::core::authenticator::UserSessions;
+
+async fn setup() -> (FakeBackend, UserSessions) {
+    (FakeBackend::start().await, test_sessions())
+}
+
+fn json_of(res: &Response<Bytes>) -> Value {
+    assert_eq!(
+        res.headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok()),
+        Some("application/json"),
+        "{}",
+        body_text(res)
+    );
+    serde_json::from_slice(res.body()).unwrap()
+}
+
+async fn post_json<F>(app: &F, path: &str, body: Value) -> Response<Bytes>
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    warp::test::request()
+        .method("POST")
+        .path(path)
+        .header("content-type", "application/json")
+        .body(body.to_string())
+        .reply(app)
+        .await
+}
+
+// -- BLOCK: AUTH
+#[tokio::test]
+async fn login_returns_the_user_and_a_session_cookie() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_json(
+        &app,
+        "/v1/auth/login",
+        json!({ "email": "ani@contoh.id", "password": "rahasia123" }),
+    )
+    .await;
+
+    assert_eq!(res.status(), 200);
+    let user = json_of(&res);
+    assert_eq!(user["id"], FAKE_USER_ID);
+    assert_eq!(user["email"], "ani@contoh.id");
+    assert_eq!(user["password"], Value::Null);
+
+    let session_cookie = set_cookie(&res, "session_id").expect("session cookie is set");
+    let history = warp::test::request()
+        .path("/v1/me/history")
+        .header("cookie", session_cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(history.status(), 200);
+}
+
+#[tokio::test]
+async fn register_is_created() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_json(
+        &app,
+        "/v1/auth/register",
+        json!({ "email": "baru@contoh.id", "password": "rahasia123" }),
+    )
+    .await;
+
+    assert_eq!(res.status(), 201);
+    assert_eq!(json_of(&res)["email"], "baru@contoh.id");
+    assert!(set_cookie(&res, "session_id").is_some());
+}
+
+#[tokio::test]
+async fn auth_failures_have_their_status_and_an_error_message() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+    let cred = json!({ "email": "ani@contoh.id", "password": "salah" });
+
+    fake.script_next(Endpoint::Login, Scripted::Status(401));
+    let res = post_json(&app, "/v1/auth/login", cred.clone()).await;
+    assert_eq!(res.status(), 401);
+    let error = json_of(&res);
+    assert_eq!(error["code"], 401);
+    assert_eq!(error["message"], "Unauthorized");
+    assert!(!error["instructions"].as_array().unwrap().is_empty());
+
+    fake.script_next(Endpoint::Register, Scripted::Status(409));
+    let res = post_json(&app, "/v1/auth/register", cred).await;
+    assert_eq!(res.status(), 409);
+    assert_eq!(json_of(&res)["code"], 409);
+}
+
+#[tokio::test]
+async fn form_body_is_unsupported() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/v1/auth/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("email=ani%40contoh.id&password=rahasia123")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 415);
+    assert_eq!(json_of(&res)["code"], 415);
+    assert!(fake.requests_to(Endpoint::Login).is_empty());
+}
+
+#[tokio::test]
+async fn logout_ends_the_session() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let (cookie, csrf) = with_csrf(&app, &cookie).await;
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/v1/auth/logout")
+        .header("cookie", &cookie)
+        .header("x-csrf-token", csrf)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 204);
+    assert_eq!(
+        set_cookie(&res, "session_id").as_deref(),
+        Some("session_id=")
+    );
+
+    let res = warp::test::request()
+        .path("/v1/me/history")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 401);
+}
+// -- ENDBLOCK: AUTH
+
+// -- BLOCK: ANALYZE_AND_HISTORY
+#[tokio::test]
+async fn analyze_returns_the_news_content() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/v1/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let news = json_of(&res);
+    assert_eq!(news["id"], FAKE_CONTENT_ID);
+    assert_eq!(news["title"], FAKE_TITLE);
+    let parsed = fake.requests_to(Endpoint::ParseNewsUrl);
+    assert!(parsed[0].path.contains(FAKE_USER_ID));
+}
+
+#[tokio::test]
+async fn analyze_errors_map_to_statuses() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let analyze = |path: &'static str| {
+        warp::test::request()
+            .path(path)
+            .header("cookie", cookie.clone())
+            .reply(&app)
+    };
+
+    let missing_url = analyze("/v1/analyze").await;
+    assert_eq!(missing_url.status(), 400);
+    assert_eq!(json_of(&missing_url)["message"], "Bad Request");
+
+    fake.script_next(Endpoint::ParseNewsUrl, Scripted::Status(422));
+    assert_eq!(analyze("/v1/analyze?url=bukan-url").await.status(), 400);
+
+    fake.script_next(Endpoint::ParseNewsUrl, Scripted::Status(500));
+    let failed = analyze("/v1/analyze?url=https%3A%2F%2Fberita.example").await;
+    assert_eq!(failed.status(), 502);
+    assert_eq!(json_of(&failed)["code"], 502);
+}
+
+#[tokio::test]
+async fn history_is_the_backend_history() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/v1/me/history")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let history = json_of(&res);
+    assert_eq!(history.as_array().unwrap().len(), 1);
+    assert_eq!(history[0]["title"], FAKE_TITLE);
+    assert_eq!(history[0]["pinned"], false);
+}
+
+#[tokio::test]
+async fn history_without_session_is_unauthorized_json() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/v1/me/history")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 401);
+    assert_eq!(location(&res), None);
+    assert_eq!(json_of(&res)["code"], 401);
+    assert!(fake.requests_to(Endpoint::History).is_empty());
+}
+
+#[tokio::test]
+async fn history_can_be_edited_with_json() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let (cookie, csrf) = with_csrf(&app, &cookie).await;
+    let entry = format!("/v1/me/history/{FAKE_CONTENT_ID}");
+
+    let pin = warp::test::request()
+        .method("PUT")
+        .path(&format!("{entry}/pin"))
+        .header("cookie", &cookie)
+        .header("x-csrf-token", &csrf)
+        .header("content-type", "application/json")
+        .body(r#"{"pinned":true}"#)
+        .reply(&app)
+        .await;
+    assert_eq!(pin.status(), 204);
+
+    let note = warp::test::request()
+        .method("PUT")
+        .path(&format!("{entry}/note"))
+        .header("cookie", &cookie)
+        .header("x-csrf-token", &csrf)
+        .header("content-type", "application/json")
+        .body(r#"{"note":"  penting  "}"#)
+        .reply(&app)
+        .await;
+    assert_eq!(note.status(), 204);
+
+    let delete = warp::test::request()
+        .method("DELETE")
+        .path(&entry)
+        .header("cookie", &cookie)
+        .header("x-csrf-token", &csrf)
+        .reply(&app)
+        .await;
+    assert_eq!(delete.status(), 204);
+
+    let updates = fake.requests_to(Endpoint::UpdateHistoryEntry);
+    assert_eq!(updates[0].body, r#"{"pinned":true}"#);
+    assert_eq!(updates[1].body, r#"{"note":"penting"}"#);
+    assert_eq!(fake.requests_to(Endpoint::DeleteHistoryEntry).len(), 1);
+}
+
+#[tokio::test]
+async fn unknown_api_path_is_a_json_not_found() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/v1/tidak-ada")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 404);
+    assert_eq!(json_of(&res)["message"], "Not Found");
+}
+
+#[tokio::test]
+async fn id_walking_out_of_its_segment_is_not_found() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+    let (cookie, csrf) = with_csrf(&app, &cookie).await;
+
+    for id in [
+        "..%2F..%2Fkorban%2Fhistory%2Fc1",
+        "..%5C..%5Ckorban",
+        "%2E%2E",
+        "%2E",
+    ] {
+        for (method, path) in [
+            ("DELETE", format!("/v1/me/history/{id}")),
+            ("PUT", format!("/v1/me/history/{id}/pin")),
+        ] {
+            let res = warp::test::request()
+                .method(method)
+                .path(&path)
+                .header("cookie", &cookie)
+                .header("x-csrf-token", &csrf)
+                .header("content-type", "application/json")
+                .body(r#"{"pinned":true}"#)
+                .reply(&app)
+                .await;
+            assert_eq!(res.status(), 404, "{method} {path}");
+            assert_eq!(json_of(&res)["code"], 404);
+        }
+    }
+    assert!(fake.requests().is_empty());
+}
+// -- ENDBLOCK: ANALYZE_AND_HISTORY
+
+// -- BLOCK: CSRF
+#[tokio::test]
+async fn cookie_changes_need_the_csrf_token() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions.clone());
+
+    for (method, path) in [
+        ("DELETE", "/v1/me/history".to_string()),
+        ("DELETE", format!("/v1/me/history/{FAKE_CONTENT_ID}")),
+        ("POST", "/v1/auth/logout".to_string()),
+    ] {
+        let res = warp::test::request()
+            .method(method)
+            .path(&path)
+            .header("cookie", &cookie)
+            .reply(&app)
+            .await;
+        assert_eq!(res.status(), 403, "{method} {path}");
+        assert_eq!(json_of(&res)["code"], 403);
+    }
+    assert!(fake.requests().is_empty());
+    assert_eq!(
+        warp::test::request()
+            .path("/v1/me/history")
+            .header("cookie", &cookie)
+            .reply(&app)
+            .await
+            .status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn login_hands_out_the_csrf_token() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let login = post_json(
+        &app,
+        "/v1/auth/login",
+        json!({ "email": "ani@contoh.id", "password": "rahasia123" }),
+    )
+    .await;
+    let csrf = login.headers()["x-csrf-token"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let cookie = format!(
+        "{}; {}",
+        set_cookie(&login, "session_id").unwrap(),
+        set_cookie(&login, "csrf_token").unwrap()
+    );
+    assert_eq!(
+        set_cookie(&login, "csrf_token").as_deref(),
+        Some(format!("csrf_token={csrf}").as_str())
+    );
+
+    let res = warp::test::request()
+        .method("DELETE")
+        .path("/v1/me/history")
+        .header("cookie", cookie)
+        .header("x-csrf-token", csrf)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 204);
+    assert_eq!(fake.requests_to(Endpoint::ClearHistory).len(), 1);
+}
+
+#[tokio::test]
+async fn json_body_needs_its_content_type() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/v1/auth/login")
+        .body(r#"{"email":"ani@contoh.id","password":"rahasia123"}"#)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 415);
+    assert!(fake.requests_to(Endpoint::Login).is_empty());
+}
+// -- ENDBLOCK: CSRF
@@ -0,0 +1,78 @@
+mod support;
+
+use support::*;
+use warptest::app::core::backend_client::BackendError;
+use warptest::app::core::error::{AppError, ErrorCause};
+
+// -- BLOCK: FROM_CAUSE
+#[test]
+fn backend_errors_keep_their_meaning() {
+    let unavailable = AppError::from(BackendError::Unavailable {
+        retry_after_secs: 30,
+    });
+    assert_eq!(unavailable.status(), 503);
+    assert_eq!(unavailable.retry_after_secs(), Some(30));
+
+    let failed = AppError::from(BackendError::Status(
+        reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+    ));
+    assert_eq!(failed.status(), 502);
+    assert_eq!(failed.page(), "/error/server-error");
+}
+
+#[test]
+fn internal_errors_keep_the_cause_but_do_not_show_it() {
+    let json_error = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+    let err = AppError::from(json_error);
+
+    assert!(matches!(err, AppError::Internal(Some(ErrorCause::Json(_)))));
+    assert_eq!(err.status(), 500);
+    let shown = serde_json::to_string(&err.error_message()).unwrap();
+    assert!(!shown.contains("EOF"), "{shown}");
+}
+// -- ENDBLOCK: FROM_CAUSE
+
+// -- BLOCK: PAGES
+#[tokio::test]
+async fn error_pages_render_the_app_error() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request()
+        .path("/error/incorrect-password?request_id=abc-123")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let page = body_text(&res);
+    assert!(page.contains("Unauthorized"));
+    assert!(page.contains(AppError::IncorrectPassword.detail()));
+    assert!(page.contains("abc-123"));
+
+    let unknown = warp::test::request()
+        .path("/error/tidak-ada")
+        .reply(&app)
+        .await;
+    assert_eq!(error_page(&unknown).as_deref(), Some("/error/not-found"));
+}
+
+#[tokio::test]
+async fn backend_failure_on_register_is_a_server_error() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Register, Scripted::Status(500));
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/v1/auth/register")
+        .header("content-type", "application/json")
+        .body(r#"{"email":"baru@contoh.id","password":"rahasia123"}"#)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 502);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["message"], "Bad Gateway");
+    assert_eq!(error["detail"], "Layanan analisis gagal menjawab.");
+}
+// -- ENDBLOCK: PAGES
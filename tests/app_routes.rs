@@ -0,0 +1,548 @@
+mod support;
+
+use std::time::Duration;
+
+use support::*;
+use tokio_stream::StreamExt;
+use warp::Filter;
+use warptest::app::core::authenticator::{sign_session_id, UserSessions};
+use warptest::app::core::session_store::{unix_now, Session};
+
+/// Fetches the login page and returns the `Cookie` header plus the token to
+/// send back in `X-CSRF-Token`.
+async fn csrf_pair<F>(app: &F) -> (String, String)
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    let res = warp::test::request().path("/auth/login").reply(app).await;
+    assert_eq!(res.status(), 200);
+    let cookie = set_cookie(&res, "csrf_token").expect("login page sets the csrf cookie");
+    let token = cookie.trim_start_matches("csrf_token=").to_string();
+    (cookie, token)
+}
+
+async fn post_credentials<F>(
+    app: &F,
+    path: &str,
+    email: &str,
+) -> warp::http::Response<warp::hyper::body::Bytes>
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    let (cookie, token) = csrf_pair(app).await;
+    warp::test::request()
+        .method("POST")
+        .path(path)
+        .header("cookie", cookie)
+        .header("x-csrf-token", token)
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("email={email}&password=rahasia123"))
+        .reply(app)
+        .await
+}
+
+async fn setup() -> (FakeBackend, UserSessions) {
+    (FakeBackend::start().await, test_sessions())
+}
+
+// -- BLOCK: AUTH
+#[tokio::test]
+async fn register_logs_the_new_user_in() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions.clone());
+
+    let res = post_credentials(&app, "/auth/register", "baru%40contoh.id").await;
+
+    assert_eq!(res.status(), 301);
+    assert_eq!(location(&res).as_deref(), Some("/home"));
+    let session_cookie = set_cookie(&res, "session_id").expect("session cookie is set");
+
+    let home = warp::test::request()
+        .path("/home")
+        .header("cookie", session_cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(home.status(), 200);
+    assert!(body_text(&home).contains("/me/history"));
+
+    let registered = fake.requests_to(Endpoint::Register);
+    assert_eq!(registered.len(), 1);
+    assert!(registered[0].body.contains("baru@contoh.id"));
+}
+
+#[tokio::test]
+async fn register_with_taken_email_shows_conflict_page() {
+    let (fake, sessions) = setup().await;
+    fake.script(Endpoint::Register, Scripted::Status(409));
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_credentials(&app, "/auth/register", "lama%40contoh.id").await;
+
+    assert_eq!(res.status(), 301);
+    assert_eq!(error_page(&res).as_deref(), Some("/error/email-taken"));
+    assert!(set_cookie(&res, "session_id").is_none());
+}
+
+#[tokio::test]
+async fn login_succeeds_with_a_session_cookie() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_credentials(&app, "/auth/login", "budi%40contoh.id").await;
+
+    assert_eq!(res.status(), 301);
+    assert_eq!(location(&res).as_deref(), Some("/home"));
+    assert!(set_cookie(&res, "session_id").is_some());
+}
+
+#[tokio::test]
+async fn login_with_wrong_password_shows_incorrect_password_page() {
+    let (fake, sessions) = setup().await;
+    fake.script(Endpoint::Login, Scripted::Status(401));
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_credentials(&app, "/auth/login", "budi%40contoh.id").await;
+
+    assert_eq!(
+        error_page(&res).as_deref(),
+        Some("/error/incorrect-password")
+    );
+    assert!(set_cookie(&res, "session_id").is_none());
+}
+
+#[tokio::test]
+async fn login_with_unknown_email_shows_not_found_page() {
+    let (fake, sessions) = setup().await;
+    fake.script(Endpoint::Login, Scripted::Status(404));
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_credentials(&app, "/auth/login", "siapa%40contoh.id").await;
+
+    assert_eq!(error_page(&res).as_deref(), Some("/error/not-found"));
+}
+
+#[tokio::test]
+async fn login_with_malformed_backend_reply_does_not_log_in() {
+    let (fake, sessions) = setup().await;
+    fake.script(Endpoint::Login, Scripted::MalformedJson);
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = post_credentials(&app, "/auth/login", "budi%40contoh.id").await;
+
+    assert!(location(&res).unwrap().starts_with("/error/"));
+    assert!(set_cookie(&res, "session_id").is_none());
+}
+
+#[tokio::test]
+async fn login_without_csrf_token_is_forbidden() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/auth/login")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("email=budi%40contoh.id&password=rahasia123")
+        .reply(&app)
+        .await;
+
+    assert_eq!(error_page(&res).as_deref(), Some("/error/forbidden"));
+    assert!(fake.requests().is_empty());
+}
+
+#[tokio::test]
+async fn auth_forms_carry_the_csrf_token_as_a_field() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    for page in ["/auth/login", "/auth/register"] {
+        let res = warp::test::request().path(page).reply(&app).await;
+        let token = set_cookie(&res, "csrf_token")
+            .unwrap()
+            .trim_start_matches("csrf_token=")
+            .to_string();
+        assert!(
+            body_text(&res).contains(&format!(r#"name="csrf_token" value="{token}""#)),
+            "{page}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn login_form_without_htmx_sends_the_token_as_a_field() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+    let (cookie, token) = csrf_pair(&app).await;
+
+    let post = |csrf_field: String| {
+        warp::test::request()
+            .method("POST")
+            .path("/auth/login")
+            .header("cookie", &cookie)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(format!(
+                "email=budi%40contoh.id&password=rahasia123&csrf_token={csrf_field}"
+            ))
+    };
+
+    let forged = post("palsu".into()).reply(&app).await;
+    assert_eq!(error_page(&forged).as_deref(), Some("/error/forbidden"));
+    assert!(fake.requests().is_empty());
+
+    let res = post(token).reply(&app).await;
+    assert_eq!(location(&res).as_deref(), Some("/home"));
+    assert!(set_cookie(&res, "session_id").is_some());
+}
+
+/// Logs `FAKE_USER_ID` in on two browsers and another user on a third, then
+/// posts `path` from the first browser the way htmx does.
+async fn htmx_logout(path: &str) -> (warp::http::Response<warp::hyper::body::Bytes>, UserSessions) {
+    let (fake, sessions) = setup().await;
+    let now = unix_now();
+    for (session_id, user_id) in [
+        ("first", FAKE_USER_ID),
+        ("second", FAKE_USER_ID),
+        ("other", "another-user"),
+    ] {
+        sessions
+            .insert(session_id.into(), Session::new(user_id.into(), now))
+            .await
+            .unwrap();
+    }
+    let app = test_app(test_backend(&fake), sessions.clone());
+    let session_cookie = format!("session_id={}", sign_session_id("first"));
+    let (cookie, token) = with_csrf(&app, &session_cookie).await;
+
+    let res = warp::test::request()
+        .method("POST")
+        .path(path)
+        .header("cookie", cookie)
+        .header("x-csrf-token", token)
+        .header("hx-request", "true")
+        .reply(&app)
+        .await;
+    (res, sessions)
+}
+
+fn assert_logged_out(res: &warp::http::Response<warp::hyper::body::Bytes>) {
+    assert_eq!(res.headers()["hx-location"], "/auth/login");
+    assert_eq!(
+        set_cookie(res, "session_id").as_deref(),
+        Some("session_id=")
+    );
+    let cleared = res
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("session_id="))
+        .collect::<Vec<_>>();
+    assert_eq!(cleared.len(), 1, "{cleared:?}");
+    assert!(cleared[0].contains("Max-Age=0"), "{}", cleared[0]);
+}
+
+#[tokio::test]
+async fn htmx_logout_ends_only_the_current_session() {
+    let (res, sessions) = htmx_logout("/auth/logout").await;
+
+    assert_logged_out(&res);
+    assert!(sessions.get("first").await.unwrap().is_none());
+    assert!(sessions.get("second").await.unwrap().is_some());
+    assert!(sessions.get("other").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn htmx_logout_everywhere_ends_every_session_of_the_user() {
+    let (res, sessions) = htmx_logout("/auth/logout-everywhere").await;
+
+    assert_logged_out(&res);
+    assert!(sessions.get("first").await.unwrap().is_none());
+    assert!(sessions.get("second").await.unwrap().is_none());
+    assert!(sessions.get("other").await.unwrap().is_some());
+}
+// -- ENDBLOCK: AUTH
+
+// -- BLOCK: HOME
+#[tokio::test]
+async fn home_without_session_redirects_to_auth() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request().path("/home").reply(&app).await;
+
+    assert_eq!(res.status(), 301);
+    assert_eq!(location(&res).as_deref(), Some("/auth"));
+}
+
+#[tokio::test]
+async fn analyze_renders_the_parsed_news() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let body = body_text(&res);
+    assert!(body.contains(FAKE_TITLE));
+    assert!(body.contains(&format!("/analyze/{FAKE_CONTENT_ID}/summary/stream")));
+
+    let parsed = fake.requests_to(Endpoint::ParseNewsUrl);
+    assert_eq!(parsed.len(), 1);
+    assert!(parsed[0].path.contains(FAKE_USER_ID));
+    assert!(parsed[0].query.contains("berita.example"));
+}
+
+#[tokio::test]
+async fn analyze_renders_news_without_an_id_unsummarized() {
+    let (fake, sessions) = setup().await;
+    let mut content = fake_news_content();
+    content["id"] = serde_json::Value::Null;
+    fake.script(Endpoint::ParseNewsUrl, Scripted::Json(content));
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let body = body_text(&res);
+    assert!(body.contains(FAKE_TITLE));
+    assert!(!body.contains("new EventSource"));
+}
+
+#[tokio::test]
+async fn analyze_escapes_the_summary_stream_url_of_an_odd_content_id() {
+    let (fake, sessions) = setup().await;
+    let odd_id = "../\"); alert(1); (\"";
+    let mut content = fake_news_content();
+    content["id"] = odd_id.into();
+    fake.script(Endpoint::ParseNewsUrl, Scripted::Json(content));
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+
+    let body = body_text(&res);
+    let endpoint = "/analyze/%2E%2E%2F%22%29%3B%20alert%281%29%3B%20%28%22/summary/stream";
+    assert!(body.contains(&format!("data-summarizer-endpoint=\"{endpoint}\"")));
+    assert!(!body.contains(odd_id));
+    assert!(!body.contains("innerHTML"));
+
+    let res = warp::test::request()
+        .path(endpoint)
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 200);
+    let summarized = fake.requests_to(Endpoint::Summarize);
+    assert_eq!(summarized.len(), 1);
+    let query = serde_urlencoded::from_str::<Vec<(String, String)>>(&summarized[0].query).unwrap();
+    assert_eq!(query, [("news_content_id".to_string(), odd_id.to_string())]);
+}
+
+#[tokio::test]
+async fn analyze_with_malformed_backend_reply_renders_the_error_component() {
+    let (fake, sessions) = setup().await;
+    fake.script(Endpoint::ParseNewsUrl, Scripted::MalformedJson);
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let body = body_text(&res);
+    assert!(body.contains("Error:"));
+    assert!(!body.contains(FAKE_TITLE));
+}
+
+#[tokio::test]
+async fn analyze_waits_for_a_slow_backend() {
+    let (fake, sessions) = setup().await;
+    fake.script(
+        Endpoint::ParseNewsUrl,
+        Scripted::Slow(Duration::from_millis(300)),
+    );
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert!(body_text(&res).contains(FAKE_TITLE));
+}
+
+#[tokio::test]
+async fn history_lists_the_analyzed_news() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path(&format!("/{FAKE_USER_ID}/history"))
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert!(body_text(&res).contains(FAKE_TITLE));
+    assert_eq!(fake.requests_to(Endpoint::History).len(), 1);
+}
+
+#[tokio::test]
+async fn history_with_backend_error_renders_the_error_drawer() {
+    let (fake, sessions) = setup().await;
+    fake.script(Endpoint::History, Scripted::Status(500));
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path(&format!("/{FAKE_USER_ID}/history"))
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert!(!body_text(&res).contains(FAKE_TITLE));
+}
+
+#[tokio::test]
+async fn my_history_uses_the_session_user() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/me/history")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert!(body_text(&res).contains(FAKE_TITLE));
+    assert!(fake.requests_to(Endpoint::History)[0]
+        .path
+        .contains(FAKE_USER_ID));
+}
+
+#[tokio::test]
+async fn history_without_session_redirects_to_auth() {
+    let (fake, sessions) = setup().await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    for path in [format!("/{FAKE_USER_ID}/history"), "/me/history".into()] {
+        let res = warp::test::request().path(&path).reply(&app).await;
+        assert_eq!(location(&res).as_deref(), Some("/auth"), "{path}");
+    }
+    assert!(fake.requests().is_empty());
+}
+
+#[tokio::test]
+async fn history_of_another_user_is_forbidden() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/0b1d6c1e-5c55-4e0e-9f39-2f4c1e7d8a90/history")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+
+    assert!(res.status().is_redirection());
+    assert!(location(&res).unwrap().starts_with("/error/forbidden"));
+
+    let res = warp::test::request()
+        .path("/0b1d6c1e-5c55-4e0e-9f39-2f4c1e7d8a90/history")
+        .header("cookie", &cookie)
+        .header("hx-request", "true")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 403);
+    assert_eq!(location(&res), None);
+    assert!(fake.requests().is_empty());
+}
+
+#[tokio::test]
+async fn home_page_loads_history_without_the_user_id() {
+    let (fake, sessions) = setup().await;
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/home")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    let body = body_text(&res);
+    assert!(body.contains("hx-get=\"/me/history\""));
+    assert!(!body.contains(FAKE_USER_ID));
+}
+// -- ENDBLOCK: HOME
+
+// -- BLOCK: BACKEND_CLIENT
+#[tokio::test]
+async fn summarize_stream_yields_the_backend_events() {
+    let fake = FakeBackend::start().await;
+    let backend = test_backend(&fake);
+
+    let mut stream = backend
+        .summarize_stream(FAKE_USER_ID, FAKE_CONTENT_ID)
+        .await
+        .unwrap();
+    let mut events = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        events.extend_from_slice(&chunk.unwrap());
+    }
+    let events = String::from_utf8(events).unwrap();
+
+    for chunk in FAKE_SUMMARY_CHUNKS {
+        assert!(events.contains(&format!("data: {chunk}")));
+    }
+    assert!(events.contains("event: done"));
+    let summarized = fake.requests_to(Endpoint::Summarize);
+    assert_eq!(
+        summarized[0].query,
+        format!("news_content_id={FAKE_CONTENT_ID}")
+    );
+}
+
+#[tokio::test]
+async fn summarize_stream_reports_backend_status() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Summarize, Scripted::Status(404));
+    let backend = test_backend(&fake);
+
+    let result = backend
+        .summarize_stream(FAKE_USER_ID, FAKE_CONTENT_ID)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(warptest::app::core::backend_client::BackendError::Status(status)) if status == 404
+    ));
+}
+// -- ENDBLOCK: BACKEND_CLIENT
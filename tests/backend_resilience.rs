@@ -0,0 +1,209 @@
+mod support;
+
+use std::time::Duration;
+
+use support::*;
+use tokio_stream::StreamExt;
+use warptest::app::core::app_config::AppConfigT;
+use warptest::app::core::backend_client::Backend;
+
+const ANALYZE_PATH: &str = "/analyze?url=https%3A%2F%2Fberita.example%2Fberas";
+const UNAVAILABLE: &str = "Analyzer sedang tidak tersedia";
+
+async fn analyze(backend: Backend) -> String {
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(backend, sessions);
+
+    let res = warp::test::request()
+        .path(ANALYZE_PATH)
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 200);
+    body_text(&res)
+}
+
+async fn history(backend: Backend) -> String {
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(backend, sessions);
+
+    let res = warp::test::request()
+        .path("/me/history")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 200);
+    body_text(&res)
+}
+
+// -- BLOCK: TIMEOUTS
+#[tokio::test]
+async fn stalled_backend_times_out_into_the_error_component() {
+    let fake = FakeBackend::start().await;
+    fake.script(
+        Endpoint::ParseNewsUrl,
+        Scripted::Slow(Duration::from_secs(5)),
+    );
+    let backend = test_backend_with(&AppConfigT {
+        backend_read_timeout_ms: 200,
+        ..test_config(&fake)
+    });
+
+    let started = std::time::Instant::now();
+    let body = analyze(backend).await;
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(body.contains("Error:"));
+    assert!(!body.contains(FAKE_TITLE));
+}
+
+#[tokio::test]
+async fn summary_stream_outlasts_the_read_timeout() {
+    let fake = FakeBackend::start().await;
+    fake.script(
+        Endpoint::Summarize,
+        Scripted::Slow(Duration::from_millis(600)),
+    );
+    let backend = test_backend_with(&AppConfigT {
+        backend_read_timeout_ms: 200,
+        ..test_config(&fake)
+    });
+
+    let stream = backend
+        .summarize_stream(FAKE_USER_ID, FAKE_CONTENT_ID)
+        .await
+        .unwrap();
+    let body = stream
+        .map(|chunk| chunk.unwrap())
+        .collect::<Vec<_>>()
+        .await
+        .concat();
+
+    assert!(String::from_utf8_lossy(&body).contains(FAKE_SUMMARY_CHUNKS[1]));
+}
+
+#[tokio::test]
+async fn stalled_summary_stream_times_out() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Summarize, Scripted::Slow(Duration::from_secs(5)));
+    let backend = test_backend_with(&AppConfigT {
+        backend_stream_read_timeout_ms: 200,
+        ..test_config(&fake)
+    });
+
+    let started = std::time::Instant::now();
+    let opened = backend
+        .summarize_stream(FAKE_USER_ID, FAKE_CONTENT_ID)
+        .await;
+
+    assert!(opened.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+// -- ENDBLOCK: TIMEOUTS
+
+// -- BLOCK: RETRIES
+#[tokio::test]
+async fn history_is_retried_until_the_backend_recovers() {
+    let fake = FakeBackend::start().await;
+    fake.script_next(Endpoint::History, Scripted::Status(503));
+    fake.script_next(Endpoint::History, Scripted::Status(502));
+
+    let body = history(test_backend(&fake)).await;
+
+    assert!(body.contains(FAKE_TITLE));
+    assert_eq!(fake.requests_to(Endpoint::History).len(), 3);
+}
+
+#[tokio::test]
+async fn history_gives_up_after_the_configured_retries() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Status(503));
+
+    let body = history(test_backend(&fake)).await;
+
+    assert!(!body.contains(FAKE_TITLE));
+    assert_eq!(
+        fake.requests_to(Endpoint::History).len(),
+        1 + AppConfigT::default().backend_retries as usize
+    );
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Status(404));
+
+    history(test_backend(&fake)).await;
+
+    assert_eq!(fake.requests_to(Endpoint::History).len(), 1);
+}
+
+#[tokio::test]
+async fn analyze_is_not_retried() {
+    let fake = FakeBackend::start().await;
+    fake.script_next(Endpoint::ParseNewsUrl, Scripted::Status(503));
+
+    let body = analyze(test_backend(&fake)).await;
+
+    assert!(body.contains("Error:"));
+    assert_eq!(fake.requests_to(Endpoint::ParseNewsUrl).len(), 1);
+}
+// -- ENDBLOCK: RETRIES
+
+// -- BLOCK: CIRCUIT_BREAKER
+#[tokio::test]
+async fn breaker_opens_and_renders_analyzer_unavailable() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::ParseNewsUrl, Scripted::Status(500));
+    let backend = test_backend_with(&AppConfigT {
+        backend_breaker_failures: 2,
+        ..test_config(&fake)
+    });
+
+    for _ in 0..2 {
+        let body = analyze(backend.clone()).await;
+        assert!(body.contains("Error:"));
+    }
+    let body = analyze(backend.clone()).await;
+
+    assert!(body.contains(UNAVAILABLE));
+    assert_eq!(fake.requests_to(Endpoint::ParseNewsUrl).len(), 2);
+}
+
+#[tokio::test]
+async fn breaker_lets_a_trial_call_through_after_the_open_period() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::ParseNewsUrl, Scripted::Status(500));
+    let backend = test_backend_with(&AppConfigT {
+        backend_breaker_failures: 1,
+        backend_breaker_open_secs: 1,
+        ..test_config(&fake)
+    });
+
+    analyze(backend.clone()).await;
+    assert!(analyze(backend.clone()).await.contains(UNAVAILABLE));
+
+    fake.script(Endpoint::ParseNewsUrl, Scripted::Success);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    assert!(analyze(backend.clone()).await.contains(FAKE_TITLE));
+    assert!(analyze(backend.clone()).await.contains(FAKE_TITLE));
+}
+
+#[tokio::test]
+async fn client_errors_do_not_open_the_breaker() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::ParseNewsUrl, Scripted::Status(404));
+    let backend = test_backend_with(&AppConfigT {
+        backend_breaker_failures: 1,
+        ..test_config(&fake)
+    });
+
+    for _ in 0..3 {
+        assert!(!analyze(backend.clone()).await.contains(UNAVAILABLE));
+    }
+    assert_eq!(fake.requests_to(Endpoint::ParseNewsUrl).len(), 3);
+}
+// -- ENDBLOCK: CIRCUIT_BREAKER
@@ -0,0 +1,162 @@
+mod support;
+
+use serde_json::Value;
+use support::*;
+use warp::http::{HeaderMap, Request};
+use warptest::app::core::error::ErrorFormat;
+
+fn format_of(accept: &str, hx_request: Option<&str>) -> ErrorFormat {
+    let mut headers = HeaderMap::new();
+    headers.insert("accept", accept.parse().unwrap());
+    if let Some(hx) = hx_request {
+        headers.insert("hx-request", hx.parse().unwrap());
+    }
+    ErrorFormat::of(&headers)
+}
+
+// -- BLOCK: FORMAT
+#[test]
+fn accept_and_hx_request_pick_the_format() {
+    assert_eq!(format_of("application/json", None), ErrorFormat::Problem);
+    assert_eq!(
+        format_of("application/problem+json", None),
+        ErrorFormat::Problem
+    );
+    assert_eq!(
+        format_of("text/html;q=0.5, application/json", None),
+        ErrorFormat::Problem
+    );
+    assert_eq!(
+        format_of(
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            None
+        ),
+        ErrorFormat::Page
+    );
+    assert_eq!(
+        format_of("text/html, application/json;q=0.9", None),
+        ErrorFormat::Page
+    );
+    assert_eq!(format_of("*/*", None), ErrorFormat::Page);
+    assert_eq!(format_of("", None), ErrorFormat::Page);
+    assert_eq!(
+        format_of("application/json", Some("true")),
+        ErrorFormat::Fragment
+    );
+}
+// -- ENDBLOCK: FORMAT
+
+// -- BLOCK: PROBLEM
+#[tokio::test]
+async fn json_client_gets_a_problem_with_the_status() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request()
+        .path("/me/history")
+        .header("accept", "application/json")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 401);
+    assert_eq!(location(&res), None);
+    assert_eq!(res.headers()["content-type"], "application/problem+json");
+    let problem: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], "Unauthorized");
+    assert_eq!(problem["status"], 401);
+    assert!(!problem["instructions"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn problem_keeps_not_found_and_bad_request() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let missing = warp::test::request()
+        .path("/tidak-ada")
+        .header("accept", "application/problem+json")
+        .reply(&app)
+        .await;
+    assert_eq!(missing.status(), 404);
+
+    let bad_sort = warp::test::request()
+        .path("/me/history/items?sort=acak")
+        .header("cookie", cookie)
+        .header("accept", "application/json")
+        .reply(&app)
+        .await;
+    assert_eq!(bad_sort.status(), 400);
+    let problem: Value = serde_json::from_slice(bad_sort.body()).unwrap();
+    assert_eq!(problem["status"], 400);
+}
+// -- ENDBLOCK: PROBLEM
+
+// -- BLOCK: FRAGMENT
+#[tokio::test]
+async fn htmx_request_gets_an_inline_fragment() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .method("DELETE")
+        .path("/me/history")
+        .header("cookie", cookie)
+        .header("hx-request", "true")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 403);
+    assert_eq!(location(&res), None);
+    assert_eq!(res.headers()["hx-retarget"], "#error-toast");
+    assert_eq!(res.headers()["hx-reswap"], "innerHTML");
+    let body = body_text(&res);
+    assert!(body.contains("Forbidden"));
+    assert!(body.contains("Muat ulang halaman"));
+    assert!(!body.contains("<html"));
+    assert!(fake.requests().is_empty());
+}
+
+#[tokio::test]
+async fn htmx_request_without_session_goes_to_login() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request()
+        .path("/me/history")
+        .header("hx-request", "true")
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 401);
+    assert_eq!(res.headers()["hx-redirect"], "/auth");
+}
+// -- ENDBLOCK: FRAGMENT
+
+// -- BLOCK: PAGE
+#[tokio::test]
+async fn browser_navigation_still_redirects_to_the_error_page() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let req = Request::get("/tidak-ada")
+        .header(
+            "accept",
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        )
+        .body(Default::default())
+        .unwrap();
+    let res = reply_in_context(app, req).await;
+
+    assert_eq!(res.status(), 301);
+    assert_eq!(error_page(&res).as_deref(), Some("/error/not-found"));
+    let request_id = res.headers()["x-request-id"].to_str().unwrap();
+    assert!(location(&res)
+        .unwrap()
+        .ends_with(&format!("?request_id={request_id}")));
+}
+// -- ENDBLOCK: PAGE
@@ -0,0 +1,505 @@
+mod support;
+
+use serde_json::json;
+use support::*;
+use warptest::app::core::session_store::unix_now;
+
+/// `YYYY-MM-DD` of `days` since the unix epoch (Howard Hinnant's civil_from_days).
+fn date_of_days(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn today() -> i64 {
+    (unix_now() / (24 * 60 * 60)) as i64
+}
+
+fn entry(n: usize, title: &str, url: &str, published: &str) -> serde_json::Value {
+    json!({
+        "id": format!("content-{n}"),
+        "title": title,
+        "content": null,
+        "authors": "Redaksi",
+        "publication_date": published,
+        "url": url,
+        "summary": null
+    })
+}
+
+/// `count` entries published long ago, analyzed in order `Berita 0`, `Berita 1`, ...
+fn numbered_history(count: usize) -> serde_json::Value {
+    (0..count)
+        .map(|n| {
+            entry(
+                n,
+                &format!("Berita {n:02}"),
+                "https://berita.example/x",
+                "2020-01-01",
+            )
+        })
+        .collect()
+}
+
+async fn get_history(fake: &FakeBackend, path: &str) -> String {
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(fake), sessions);
+
+    let res = warp::test::request()
+        .path(path)
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 200, "{path}");
+    body_text(&res)
+}
+
+/// The `hx-get` of the "load more" button, unescaped.
+fn next_page(body: &str) -> Option<String> {
+    let start = body.find("id=\"history-load-more\"")?;
+    let rest = &body[start..];
+    let href = rest.split("hx-get=\"").nth(1)?.split('"').next()?;
+    Some(href.replace("&amp;", "&").replace("&#x3D;", "="))
+}
+
+fn titles(body: &str) -> Vec<String> {
+    body.split("<span class=\"ms-3\">")
+        .skip(1)
+        .filter_map(|rest| rest.split("</span>").next())
+        .map(str::to_string)
+        .collect()
+}
+
+// -- BLOCK: PAGINATION
+#[tokio::test]
+async fn history_is_paginated_with_a_load_more_cursor() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(numbered_history(45)));
+
+    let first = get_history(&fake, "/me/history").await;
+    assert_eq!(titles(&first).len(), 20);
+    assert_eq!(titles(&first)[0], "Berita 44");
+    assert!(first.contains("id=\"history-search-form\""));
+
+    let second_path = next_page(&first).expect("first page links the next");
+    let second = get_history(&fake, &second_path).await;
+    assert_eq!(titles(&second).len(), 20);
+    assert_eq!(titles(&second)[0], "Berita 24");
+    assert!(!second.contains("id=\"history-search-form\""));
+
+    let third = get_history(&fake, &next_page(&second).unwrap()).await;
+    assert_eq!(
+        titles(&third),
+        [
+            "Berita 04",
+            "Berita 03",
+            "Berita 02",
+            "Berita 01",
+            "Berita 00"
+        ]
+    );
+    assert_eq!(next_page(&third), None);
+}
+
+#[tokio::test]
+async fn continued_group_label_is_not_repeated() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(numbered_history(25)));
+
+    let first = get_history(&fake, "/me/history?sort=published").await;
+    let second = get_history(&fake, &next_page(&first).unwrap()).await;
+
+    assert!(first.contains("Lebih lama"));
+    assert!(!second.contains("Lebih lama"));
+}
+
+#[tokio::test]
+async fn deleting_an_entry_does_not_shift_the_next_page() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(numbered_history(45)));
+    let first = get_history(&fake, "/me/history").await;
+    assert_eq!(titles(&first).last().unwrap(), "Berita 25");
+
+    let mut history = numbered_history(45);
+    history.as_array_mut().unwrap().remove(40);
+    fake.script(Endpoint::History, Scripted::Json(history));
+    let second = get_history(&fake, &next_page(&first).unwrap()).await;
+
+    assert_eq!(titles(&second)[0], "Berita 24");
+    assert_eq!(titles(&second).last().unwrap(), "Berita 05");
+}
+
+#[tokio::test]
+async fn entry_without_id_does_not_end_the_pages() {
+    let fake = FakeBackend::start().await;
+    let mut history = numbered_history(22);
+    history[2]["id"] = serde_json::Value::Null;
+    fake.script(Endpoint::History, Scripted::Json(history));
+
+    let first = get_history(&fake, "/me/history").await;
+    assert_eq!(titles(&first).len(), 20);
+    assert!(!titles(&first).contains(&"Berita 02".to_string()));
+
+    let second = get_history(&fake, &next_page(&first).expect("more entries left")).await;
+    assert_eq!(titles(&second), ["Berita 00"]);
+}
+
+#[tokio::test]
+async fn deleted_cursor_entry_ends_the_list() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(numbered_history(5)));
+
+    let body = get_history(&fake, "/me/history/items?cursor=content-99").await;
+
+    assert!(titles(&body).is_empty());
+    assert!(!body.contains("Belum ada riwayat."));
+    assert_eq!(next_page(&body), None);
+}
+
+#[tokio::test]
+async fn empty_history_says_so() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(json!([])));
+
+    let body = get_history(&fake, "/me/history").await;
+
+    assert!(body.contains("Belum ada riwayat."));
+    assert_eq!(next_page(&body), None);
+}
+// -- ENDBLOCK: PAGINATION
+
+// -- BLOCK: SEARCH_SORT
+fn mixed_history() -> serde_json::Value {
+    let mut by_siti = entry(2, "Cuaca ekstrem", "https://cuaca.example/a", "2020-01-02");
+    by_siti["authors"] = "Siti Aminah".into();
+    json!([
+        entry(
+            0,
+            "Harga beras naik",
+            "https://pangan.example/beras",
+            "2020-01-03"
+        ),
+        entry(
+            1,
+            "Banjir di Jakarta",
+            "https://kota.example/banjir",
+            "2020-01-01"
+        ),
+        by_siti,
+    ])
+}
+
+#[tokio::test]
+async fn search_matches_title_author_and_domain() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(mixed_history()));
+
+    for (q, expected) in [
+        ("BERAS", "Harga beras naik"),
+        ("aminah", "Cuaca ekstrem"),
+        ("kota.example", "Banjir di Jakarta"),
+    ] {
+        let body = get_history(&fake, &format!("/me/history/items?q={q}")).await;
+        assert_eq!(titles(&body), [expected], "{q}");
+    }
+
+    let none = get_history(&fake, "/me/history/items?q=gempa").await;
+    assert!(titles(&none).is_empty());
+    assert!(none.contains("Tidak ada riwayat yang cocok."));
+}
+
+#[tokio::test]
+async fn history_can_be_sorted() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(mixed_history()));
+
+    for (sort, expected) in [
+        (
+            "recent",
+            ["Cuaca ekstrem", "Banjir di Jakarta", "Harga beras naik"],
+        ),
+        (
+            "oldest",
+            ["Harga beras naik", "Banjir di Jakarta", "Cuaca ekstrem"],
+        ),
+        (
+            "published",
+            ["Harga beras naik", "Cuaca ekstrem", "Banjir di Jakarta"],
+        ),
+        (
+            "title",
+            ["Banjir di Jakarta", "Cuaca ekstrem", "Harga beras naik"],
+        ),
+    ] {
+        let body = get_history(&fake, &format!("/me/history/items?sort={sort}")).await;
+        assert_eq!(titles(&body), expected, "{sort}");
+    }
+}
+
+#[tokio::test]
+async fn unknown_sort_is_a_bad_request() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/me/history/items?sort=acak")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert!(location(&res).unwrap().starts_with("/error/bad-request"));
+}
+// -- ENDBLOCK: SEARCH_SORT
+
+// -- BLOCK: GROUPING
+#[tokio::test]
+async fn history_is_grouped_by_publication_date() {
+    let today = today();
+    let monday = today - (today + 3).rem_euclid(7);
+    let mut history = vec![
+        entry(0, "Lama", "https://a.example/1", &date_of_days(today - 30)),
+        entry(1, "Tanpa tanggal", "https://a.example/2", ""),
+        entry(2, "Hari ini", "https://a.example/3", &date_of_days(today)),
+    ];
+    if monday < today {
+        history.push(entry(
+            3,
+            "Awal minggu",
+            "https://a.example/4",
+            &date_of_days(monday),
+        ));
+    }
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(history.into()));
+
+    let body = get_history(&fake, "/me/history/items?sort=published").await;
+
+    let mut expected = vec!["Hari ini"];
+    if monday < today {
+        expected.push("Awal minggu");
+    }
+    expected.extend(["Lama", "Tanpa tanggal"]);
+    assert_eq!(titles(&body), expected);
+
+    let today_label = body.find(">Hari ini</li>").unwrap();
+    let older_label = body.find(">Lebih lama</li>").unwrap();
+    assert!(today_label < older_label);
+    assert_eq!(body.contains(">Minggu ini</li>"), monday < today);
+}
+
+#[tokio::test]
+async fn other_sorts_only_group_the_pinned() {
+    let today = today();
+    let mut pinned = entry(2, "Disematkan", "https://a.example/3", "2020-01-01");
+    pinned["pinned"] = true.into();
+    let history = json!([
+        entry(0, "Hari ini", "https://a.example/1", &date_of_days(today)),
+        entry(1, "Lama", "https://a.example/2", "2020-01-01"),
+        pinned,
+    ]);
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(history));
+
+    for (sort, expected) in [
+        ("recent", ["Disematkan", "Lama", "Hari ini"]),
+        ("oldest", ["Disematkan", "Hari ini", "Lama"]),
+        ("title", ["Disematkan", "Hari ini", "Lama"]),
+    ] {
+        let body = get_history(&fake, &format!("/me/history/items?sort={sort}")).await;
+        assert_eq!(titles(&body), expected, "{sort}");
+        assert_eq!(body.matches("history-group-label").count(), 1, "{sort}");
+        assert!(body.contains(">Disematkan</li>"), "{sort}");
+    }
+}
+// -- ENDBLOCK: GROUPING
+
+// -- BLOCK: HISTORY_EDITING
+async fn edit_history(
+    fake: &FakeBackend,
+    method: &str,
+    path: &str,
+    form: &str,
+) -> warp::http::Response<warp::hyper::body::Bytes> {
+    let sessions = test_sessions();
+    let session_cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(fake), sessions);
+    let (cookie, token) = with_csrf(&app, &session_cookie).await;
+
+    warp::test::request()
+        .method(method)
+        .path(path)
+        .header("cookie", cookie)
+        .header("x-csrf-token", token)
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(form)
+        .reply(&app)
+        .await
+}
+
+fn assert_history_changed(res: &warp::http::Response<warp::hyper::body::Bytes>) {
+    assert_eq!(res.status(), 204);
+    assert_eq!(res.headers()["hx-trigger"], "history-changed");
+}
+
+#[tokio::test]
+async fn entry_can_be_deleted() {
+    let fake = FakeBackend::start().await;
+
+    let res = edit_history(&fake, "DELETE", "/me/history/content-7", "").await;
+
+    assert_history_changed(&res);
+    let deleted = fake.requests_to(Endpoint::DeleteHistoryEntry);
+    assert_eq!(
+        deleted[0].path,
+        format!("/api/v0/users/{FAKE_USER_ID}/history/content-7")
+    );
+}
+
+#[tokio::test]
+async fn entry_id_stays_one_backend_path_segment() {
+    let fake = FakeBackend::start().await;
+
+    edit_history(
+        &fake,
+        "DELETE",
+        "/me/history/..%2F..%2Fkorban%2Fhistory%2Fc1",
+        "",
+    )
+    .await;
+    edit_history(
+        &fake,
+        "PUT",
+        "/me/history/..%2F..%2Fkorban/pin",
+        "pinned=true",
+    )
+    .await;
+
+    let deleted = fake.requests_to(Endpoint::DeleteHistoryEntry);
+    assert_eq!(
+        deleted[0].path,
+        format!("/api/v0/users/{FAKE_USER_ID}/history/..%252F..%252Fkorban%252Fhistory%252Fc1")
+    );
+    let updated = fake.requests_to(Endpoint::UpdateHistoryEntry);
+    assert_eq!(
+        updated[0].path,
+        format!("/api/v0/users/{FAKE_USER_ID}/history/..%252F..%252Fkorban")
+    );
+}
+
+#[tokio::test]
+async fn history_can_be_cleared() {
+    let fake = FakeBackend::start().await;
+
+    let res = edit_history(&fake, "DELETE", "/me/history", "").await;
+
+    assert_history_changed(&res);
+    assert_eq!(fake.requests_to(Endpoint::ClearHistory).len(), 1);
+    assert!(fake.requests_to(Endpoint::DeleteHistoryEntry).is_empty());
+}
+
+#[tokio::test]
+async fn entry_can_be_pinned_and_unpinned() {
+    let fake = FakeBackend::start().await;
+
+    assert_history_changed(
+        &edit_history(&fake, "PUT", "/me/history/content-7/pin", "pinned=true").await,
+    );
+    assert_history_changed(
+        &edit_history(&fake, "PUT", "/me/history/content-7/pin", "pinned=false").await,
+    );
+
+    let bodies = fake
+        .requests_to(Endpoint::UpdateHistoryEntry)
+        .into_iter()
+        .map(|req| serde_json::from_str::<serde_json::Value>(&req.body).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bodies,
+        [json!({ "pinned": true }), json!({ "pinned": false })]
+    );
+}
+
+#[tokio::test]
+async fn note_is_trimmed_and_empty_removes_it() {
+    let fake = FakeBackend::start().await;
+
+    edit_history(
+        &fake,
+        "PUT",
+        "/me/history/content-7/note",
+        "note=+Untuk+skripsi+",
+    )
+    .await;
+    edit_history(&fake, "PUT", "/me/history/content-7/note", "note=").await;
+
+    let bodies = fake
+        .requests_to(Endpoint::UpdateHistoryEntry)
+        .into_iter()
+        .map(|req| serde_json::from_str::<serde_json::Value>(&req.body).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bodies,
+        [json!({ "note": "Untuk skripsi" }), json!({ "note": null })]
+    );
+}
+
+#[tokio::test]
+async fn editing_without_csrf_token_is_forbidden() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .method("DELETE")
+        .path("/me/history")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert!(location(&res).unwrap().starts_with("/error/forbidden"));
+    assert!(fake.requests().is_empty());
+}
+
+#[tokio::test]
+async fn editing_a_missing_entry_is_not_found() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::DeleteHistoryEntry, Scripted::Status(404));
+
+    let res = edit_history(&fake, "DELETE", "/me/history/content-7", "").await;
+
+    assert!(location(&res).unwrap().starts_with("/error/not-found"));
+}
+
+#[tokio::test]
+async fn pinned_entries_come_first_with_their_note() {
+    let mut pinned = entry(0, "Disematkan lama", "https://a.example/1", "2020-01-01");
+    pinned["pinned"] = true.into();
+    pinned["note"] = "bahan skripsi".into();
+    let history = json!([
+        pinned,
+        entry(1, "Biasa", "https://a.example/2", "2020-01-02"),
+    ]);
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(history));
+
+    let body = get_history(&fake, "/me/history/items").await;
+    assert_eq!(titles(&body), ["Disematkan lama", "Biasa"]);
+    assert!(body.contains(">Disematkan</li>"));
+    assert!(body.contains("bahan skripsi"));
+    assert!(body.contains("Lepas sematan"));
+
+    let searched = get_history(&fake, "/me/history/items?q=skripsi").await;
+    assert_eq!(titles(&searched), ["Disematkan lama"]);
+}
+// -- ENDBLOCK: HISTORY_EDITING
@@ -0,0 +1,317 @@
+mod support;
+
+use std::sync::Arc;
+
+use serde_json::json;
+use support::*;
+use tokio_stream::StreamExt;
+use warptest::app::core::backend_client::BackendError;
+use warptest::app::core::json_array::JsonArrayDecoder;
+use warptest::app::core::models::NewsContent;
+use warptest::app::core::summary_store::{MemorySummaryStore, Summaries};
+
+fn record(
+    title: &str,
+    published: serde_json::Value,
+    summary: serde_json::Value,
+) -> serde_json::Value {
+    json!({
+        "id": title,
+        "title": title,
+        "content": "isi lengkap yang tidak diekspor",
+        "authors": "Redaksi",
+        "publication_date": published,
+        "url": "https://berita.example/a",
+        "summary": summary
+    })
+}
+
+async fn export(fake: &FakeBackend, query: &str) -> warp::http::Response<warp::hyper::body::Bytes> {
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(fake), sessions);
+
+    warp::test::request()
+        .path(&format!("/me/history/export?{query}"))
+        .header("cookie", cookie)
+        .reply(&app)
+        .await
+}
+
+// -- BLOCK: FORMATS
+#[tokio::test]
+async fn csv_export_quotes_and_defuses_cells() {
+    let fake = FakeBackend::start().await;
+    fake.script(
+        Endpoint::History,
+        Scripted::Json(json!([
+            record("Beras, \"mahal\"", "2025-03-01".into(), "Naik\n10%".into()),
+            record("=HYPERLINK(\"x\")", json!(null), json!(null)),
+        ])),
+    );
+
+    let res = export(&fake, "format=csv").await;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "text/csv; charset=utf-8");
+    assert_eq!(
+        res.headers()["content-disposition"],
+        "attachment; filename=\"riwayat-analisis.csv\""
+    );
+    assert_eq!(
+        body_text(&res),
+        "title,authors,publication_date,url,summary\r\n\
+         \"Beras, \"\"mahal\"\"\",Redaksi,2025-03-01,https://berita.example/a,\"Naik\n10%\"\r\n\
+         \"'=HYPERLINK(\"\"x\"\")\",Redaksi,,https://berita.example/a,\r\n"
+    );
+}
+
+#[tokio::test]
+async fn json_and_ndjson_exports_hold_the_exported_fields() {
+    let fake = FakeBackend::start().await;
+    fake.script(
+        Endpoint::History,
+        Scripted::Json(json!([
+            record("Satu", "2025-03-01".into(), "Ringkas".into()),
+            record("Dua", json!(null), json!(null)),
+        ])),
+    );
+    let expected = json!([
+        {
+            "title": "Satu",
+            "authors": "Redaksi",
+            "publication_date": "2025-03-01",
+            "url": "https://berita.example/a",
+            "summary": "Ringkas"
+        },
+        {
+            "title": "Dua",
+            "authors": "Redaksi",
+            "publication_date": null,
+            "url": "https://berita.example/a",
+            "summary": null
+        }
+    ]);
+
+    let res = export(&fake, "format=json").await;
+    assert_eq!(res.headers()["content-type"], "application/json");
+    let exported: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(exported, expected);
+
+    let res = export(&fake, "format=ndjson").await;
+    assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+    let lines = body_text(&res)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(serde_json::Value::from(lines), expected);
+}
+
+#[tokio::test]
+async fn empty_json_export_is_an_empty_array() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(json!([])));
+
+    let res = export(&fake, "format=json").await;
+
+    let exported: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(exported, json!([]));
+}
+
+#[tokio::test]
+async fn markdown_export_escapes_titles() {
+    let fake = FakeBackend::start().await;
+    fake.script(
+        Endpoint::History,
+        Scripted::Json(json!([record(
+            "[Iklan] *Promo*",
+            "2025-03-01".into(),
+            "Ringkas".into()
+        )])),
+    );
+
+    let res = export(&fake, "format=md").await;
+
+    assert_eq!(
+        res.headers()["content-type"],
+        "text/markdown; charset=utf-8"
+    );
+    let body = body_text(&res);
+    assert!(body.starts_with("# Riwayat analisis\n"));
+    assert!(body.contains("## [\\[Iklan\\] \\*Promo\\*](https://berita.example/a)"));
+    assert!(body.contains("- Terbit: 2025-03-01"));
+    assert!(body.contains("\nRingkas\n"));
+}
+
+#[tokio::test]
+async fn exports_and_lists_the_summary_streamed_through_the_proxy() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    let app = test_app_with(test_backend(&fake), sessions, summaries);
+
+    let streamed = warp::test::request()
+        .path(&format!("/analyze/{FAKE_CONTENT_ID}/summary/stream"))
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    assert_eq!(streamed.status(), 200);
+    let summary = FAKE_SUMMARY_CHUNKS.concat();
+
+    let res = warp::test::request()
+        .path("/me/history/export?format=ndjson")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    let exported: serde_json::Value = serde_json::from_str(body_text(&res).trim()).unwrap();
+    assert_eq!(exported["summary"], json!(summary));
+
+    let res = warp::test::request()
+        .path("/v1/me/history")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    let listed: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(listed[0]["summary"], json!(summary));
+}
+// -- ENDBLOCK: FORMATS
+
+// -- BLOCK: RANGE
+#[tokio::test]
+async fn export_is_limited_to_the_date_range() {
+    let fake = FakeBackend::start().await;
+    fake.script(
+        Endpoint::History,
+        Scripted::Json(json!([
+            record("Februari", "2025-02-28".into(), json!(null)),
+            record("Maret", "2025-03-01".into(), json!(null)),
+            record("Maret akhir", "2025-03-31T08:00:00".into(), json!(null)),
+            record("April", "2025-04-01".into(), json!(null)),
+            record("Tanpa tanggal", json!(null), json!(null)),
+        ])),
+    );
+
+    let res = export(&fake, "format=ndjson&from=2025-03-01&to=2025-03-31").await;
+
+    let titles = body_text(&res)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["title"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, [json!("Maret"), json!("Maret akhir")]);
+}
+
+#[tokio::test]
+async fn blank_bounds_export_everything() {
+    let fake = FakeBackend::start().await;
+
+    let res = export(&fake, "format=ndjson&from=&to=").await;
+
+    assert_eq!(res.status(), 200);
+    assert!(body_text(&res).contains(FAKE_TITLE));
+}
+
+#[tokio::test]
+async fn bad_format_or_date_is_a_bad_request() {
+    let fake = FakeBackend::start().await;
+
+    for query in ["format=xlsx", "from=01-03-2025", "to=2025-13-01"] {
+        let res = export(&fake, query).await;
+        assert!(
+            location(&res).unwrap().starts_with("/error/bad-request"),
+            "{query}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn export_requires_a_session() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request()
+        .path("/me/history/export?format=csv")
+        .reply(&app)
+        .await;
+
+    assert_eq!(location(&res).as_deref(), Some("/auth"));
+}
+// -- ENDBLOCK: RANGE
+
+// -- BLOCK: STREAMING
+#[test]
+fn history_array_decodes_across_any_chunk_split() {
+    let body = json!([
+        record("Kutip \"] , [\" dan \\", "2025-03-01".into(), json!(null)),
+        record("{kurung}", json!(null), "ringkas, [1]".into()),
+        record("Ω unicode ü", "2025-03-02".into(), json!(null)),
+    ])
+    .to_string()
+    .replace(",{", " ,\n {");
+    let expected: Vec<NewsContent> = serde_json::from_str(&body).unwrap();
+
+    for split in 0..=body.len() {
+        let mut decoder = JsonArrayDecoder::default();
+        let mut items: Vec<NewsContent> = decoder.push(&body.as_bytes()[..split]).unwrap();
+        items.extend(
+            decoder
+                .push::<NewsContent>(&body.as_bytes()[split..])
+                .unwrap(),
+        );
+        decoder.finish().unwrap();
+        assert_eq!(
+            serde_json::to_value(&items).unwrap(),
+            serde_json::to_value(&expected).unwrap(),
+            "split at {split}"
+        );
+    }
+}
+
+#[test]
+fn null_and_empty_histories_have_no_items() {
+    for body in ["null", " [ ] ", "[]"] {
+        let mut decoder = JsonArrayDecoder::default();
+        let items: Vec<NewsContent> = decoder.push(body.as_bytes()).unwrap();
+        assert!(items.is_empty(), "{body}");
+        decoder.finish().unwrap();
+    }
+}
+
+#[test]
+fn truncated_or_foreign_history_is_an_error() {
+    let mut truncated = JsonArrayDecoder::default();
+    let items: Vec<NewsContent> = truncated
+        .push(br#"[{"id":"a","title":"A","content":null,"authors":"","url":"u","summary":null},{"id""#)
+        .unwrap();
+    assert_eq!(items.len(), 1);
+    assert!(truncated.finish().is_err());
+
+    let mut object = JsonArrayDecoder::default();
+    assert!(object.push::<NewsContent>(b"{\"detail\": 1}").is_err());
+}
+
+#[tokio::test]
+async fn malformed_history_ends_the_stream_with_an_error() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::MalformedJson);
+    let backend = test_backend(&fake);
+
+    let mut history = backend.user_history_stream(FAKE_USER_ID).await.unwrap();
+
+    assert!(matches!(
+        history.next().await,
+        Some(Err(BackendError::Decode(_)))
+    ));
+    assert!(history.next().await.is_none());
+}
+
+#[tokio::test]
+async fn failing_history_is_an_error_page_before_the_download() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Status(404));
+
+    let res = export(&fake, "format=csv").await;
+
+    assert_eq!(error_page(&res).as_deref(), Some("/error/server-error"));
+}
+// -- ENDBLOCK: STREAMING
@@ -0,0 +1,233 @@
+mod support;
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde_json::json;
+use support::*;
+use warp::http::Response;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+use warptest::app::api::routes::api_routes;
+use warptest::app::core::api_token_store::MemoryApiTokenStore;
+use warptest::app::core::app_config::AppConfigT;
+use warptest::app::core::error::json_on_reject;
+use warptest::app::core::rate_limiter::LoginLimiterT;
+use warptest::app::core::request_context::client_ip;
+use warptest::app::core::summary_store::MemorySummaryStore;
+
+const CLIENT: &str = "203.0.113.7:50000";
+const PROXY: &str = "10.0.0.1:40000";
+
+/// The `/v1` routes with a limiter built from `config`.
+fn limited_app(
+    fake: &FakeBackend,
+    config: AppConfigT,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Infallible> + Clone {
+    let limiter = Arc::new(LoginLimiterT::from_config(&config));
+    warp::path("v1")
+        .and(api_routes(
+            test_backend(fake),
+            test_sessions(),
+            Arc::new(MemoryApiTokenStore::default()),
+            Arc::new(MemorySummaryStore::default()),
+            limiter,
+        ))
+        .recover(json_on_reject)
+}
+
+async fn login<F>(app: &F, email: &str, peer: &str, forwarded_for: Option<&str>) -> Response<Bytes>
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    let mut req = warp::test::request()
+        .method("POST")
+        .path("/v1/auth/login")
+        .remote_addr(peer.parse::<SocketAddr>().unwrap())
+        .header("content-type", "application/json")
+        .body(json!({ "email": email, "password": "rahasia123" }).to_string());
+    if let Some(forwarded_for) = forwarded_for {
+        req = req.header("x-forwarded-for", forwarded_for);
+    }
+    req.reply(app).await
+}
+
+fn retry_after<B>(res: &Response<B>) -> u64 {
+    res.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+// -- BLOCK: TOKEN_BUCKETS
+#[tokio::test]
+async fn ip_bucket_limits_one_client_across_emails() {
+    let fake = FakeBackend::start().await;
+    let app = limited_app(
+        &fake,
+        AppConfigT {
+            login_ip_burst: 3,
+            login_ip_per_minute: 1,
+            ..test_config(&fake)
+        },
+    );
+
+    for n in 0..3 {
+        let res = login(&app, &format!("user{n}@contoh.id"), CLIENT, None).await;
+        assert_eq!(res.status(), 200);
+    }
+    let limited = login(&app, "user9@contoh.id", CLIENT, None).await;
+    let other_client = login(&app, "user9@contoh.id", "198.51.100.2:1", None).await;
+
+    assert_eq!(limited.status(), 429);
+    assert!((1..=60).contains(&retry_after(&limited)));
+    let error: serde_json::Value = serde_json::from_slice(limited.body()).unwrap();
+    assert_eq!(error["code"], 429);
+    assert_eq!(other_client.status(), 200);
+    assert_eq!(fake.requests_to(Endpoint::Login).len(), 4);
+}
+
+#[tokio::test]
+async fn email_bucket_limits_one_email_across_clients() {
+    let fake = FakeBackend::start().await;
+    let app = limited_app(
+        &fake,
+        AppConfigT {
+            login_email_burst: 2,
+            login_email_per_minute: 1,
+            ..test_config(&fake)
+        },
+    );
+
+    for peer in ["198.51.100.1:1", "198.51.100.2:1"] {
+        assert_eq!(login(&app, "ani@contoh.id", peer, None).await.status(), 200);
+    }
+    let limited = login(&app, " ANI@contoh.id", "198.51.100.3:1", None).await;
+
+    assert_eq!(limited.status(), 429);
+    assert!(retry_after(&limited) >= 1);
+    assert_eq!(fake.requests_to(Endpoint::Login).len(), 2);
+}
+// -- ENDBLOCK: TOKEN_BUCKETS
+
+// -- BLOCK: LOCKOUT
+#[tokio::test]
+async fn wrong_passwords_lock_the_email_out() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Login, Scripted::Status(401));
+    let app = limited_app(
+        &fake,
+        AppConfigT {
+            login_max_failures: 3,
+            login_lockout_secs: 600,
+            login_email_burst: 100,
+            ..test_config(&fake)
+        },
+    );
+
+    for _ in 0..3 {
+        assert_eq!(
+            login(&app, "ani@contoh.id", CLIENT, None).await.status(),
+            401
+        );
+    }
+    fake.script(Endpoint::Login, Scripted::Success);
+    let locked = login(&app, "ani@contoh.id", CLIENT, None).await;
+    let other_email = login(&app, "budi@contoh.id", CLIENT, None).await;
+
+    assert_eq!(locked.status(), 429);
+    assert!((590..=600).contains(&retry_after(&locked)));
+    assert_eq!(other_email.status(), 200);
+    assert_eq!(fake.requests_to(Endpoint::Login).len(), 4);
+}
+
+#[tokio::test]
+async fn a_success_resets_the_failure_count() {
+    let fake = FakeBackend::start().await;
+    let app = limited_app(
+        &fake,
+        AppConfigT {
+            login_max_failures: 2,
+            login_email_burst: 100,
+            ..test_config(&fake)
+        },
+    );
+
+    for reply in [
+        Scripted::Status(401),
+        Scripted::Success,
+        Scripted::Status(401),
+    ] {
+        fake.script_next(Endpoint::Login, reply);
+        login(&app, "ani@contoh.id", CLIENT, None).await;
+    }
+
+    assert_eq!(
+        login(&app, "ani@contoh.id", CLIENT, None).await.status(),
+        200
+    );
+}
+// -- ENDBLOCK: LOCKOUT
+
+// -- BLOCK: CLIENT_IP
+#[test]
+fn forwarded_for_is_only_believed_from_trusted_proxies() {
+    let proxy = "10.0.0.1".parse().unwrap();
+    let trusted = [proxy, "10.0.0.2".parse().unwrap()];
+    let client = "203.0.113.7".parse().unwrap();
+
+    assert_eq!(
+        client_ip(
+            Some(proxy),
+            Some("1.1.1.1, 203.0.113.7, 10.0.0.2"),
+            &trusted
+        ),
+        Some(client)
+    );
+    assert_eq!(client_ip(Some(proxy), None, &trusted), Some(proxy));
+    assert_eq!(
+        client_ip(Some(proxy), Some("bukan-ip"), &trusted),
+        Some(proxy)
+    );
+    assert_eq!(
+        client_ip(Some(client), Some("1.1.1.1"), &trusted),
+        Some(client)
+    );
+    assert_eq!(client_ip(None, Some("1.1.1.1"), &trusted), None);
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_get_their_own_bucket() {
+    let fake = FakeBackend::start().await;
+    let config = AppConfigT {
+        login_ip_burst: 1,
+        login_ip_per_minute: 1,
+        trusted_proxies: vec!["10.0.0.1".parse().unwrap()],
+        ..test_config(&fake)
+    };
+    let app = limited_app(&fake, config.clone());
+
+    let first = login(&app, "a@contoh.id", PROXY, Some("203.0.113.7")).await;
+    let second = login(&app, "b@contoh.id", PROXY, Some("203.0.113.8")).await;
+    let again = login(&app, "c@contoh.id", PROXY, Some("203.0.113.7")).await;
+    assert_eq!(first.status(), 200);
+    assert_eq!(second.status(), 200);
+    assert_eq!(again.status(), 429);
+
+    // An untrusted peer can not pick its bucket with the header.
+    let untrusted = limited_app(
+        &fake,
+        AppConfigT {
+            trusted_proxies: Vec::new(),
+            ..config
+        },
+    );
+    let first = login(&untrusted, "a@contoh.id", CLIENT, Some("1.1.1.1")).await;
+    let spoofed = login(&untrusted, "b@contoh.id", CLIENT, Some("2.2.2.2")).await;
+    assert_eq!(first.status(), 200);
+    assert_eq!(spoofed.status(), 429);
+}
+// -- ENDBLOCK: CLIENT_IP
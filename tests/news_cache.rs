@@ -0,0 +1,378 @@
+mod support;
+
+use std::sync::Arc;
+
+use support::*;
+use warptest::app::core::backend_client::Backend;
+use warptest::app::core::models::NewsContent;
+use warptest::app::core::news_cache::{
+    canonical_news_url, CachingBackendClient, NewsCache, NewsCacheStats, NewsCacheT,
+};
+use warptest::app::core::routes::monitoring_routes;
+use warptest::app::core::session_store::unix_now;
+
+const NOW: u64 = 1_700_000_000;
+
+fn news(title: &str) -> NewsContent {
+    NewsContent {
+        title: title.into(),
+        ..serde_json::from_value(fake_news_content()).unwrap()
+    }
+}
+
+fn cached_backend(fake: &FakeBackend, cache: &NewsCache) -> Backend {
+    Arc::new(CachingBackendClient::new(test_backend(fake), cache.clone()))
+}
+
+async fn analyze(backend: Backend, news_url: &str) -> String {
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(backend, sessions);
+
+    let query = serde_urlencoded::to_string([("url", news_url)]).unwrap();
+    let res = warp::test::request()
+        .path(&format!("/analyze?{query}"))
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+    body_text(&res)
+}
+
+// -- BLOCK: CANONICAL_URL
+#[test]
+fn canonical_url_drops_tracking_and_normalizes() {
+    let canonical = canonical_news_url(
+        "HTTPS://Berita.Example:443/ekonomi/beras/?utm_source=x&b=2&fbclid=abc&a=1#komentar",
+    );
+
+    assert_eq!(
+        canonical.as_deref(),
+        Some("https://berita.example/ekonomi/beras?a=1&b=2")
+    );
+}
+
+#[test]
+fn canonical_url_variants_share_a_key() {
+    let variants = [
+        "https://berita.example/beras",
+        "https://berita.example/beras/",
+        "https://BERITA.example/beras?utm_medium=social&utm_campaign=lebaran",
+        "https://berita.example/beras#top",
+    ];
+
+    for variant in variants {
+        assert_eq!(
+            canonical_news_url(variant).as_deref(),
+            Some("https://berita.example/beras"),
+            "{variant}"
+        );
+    }
+    assert_eq!(
+        canonical_news_url("https://berita.example/").as_deref(),
+        Some("https://berita.example")
+    );
+}
+
+#[test]
+fn canonical_url_rejects_non_http() {
+    assert_eq!(canonical_news_url("invalid query key!"), None);
+    assert_eq!(canonical_news_url("ftp://berita.example/beras"), None);
+}
+// -- ENDBLOCK: CANONICAL_URL
+
+// -- BLOCK: LRU_TTL
+#[tokio::test]
+async fn least_recently_used_entry_is_evicted() {
+    let cache = NewsCacheT::new(2, 60);
+    cache.insert(FAKE_USER_ID, "a".into(), news("A"), NOW).await;
+    cache.insert(FAKE_USER_ID, "b".into(), news("B"), NOW).await;
+    assert!(cache.get(FAKE_USER_ID, "a", NOW).is_some());
+
+    cache.insert(FAKE_USER_ID, "c".into(), news("C"), NOW).await;
+
+    assert!(cache.get(FAKE_USER_ID, "b", NOW).is_none());
+    assert_eq!(cache.get(FAKE_USER_ID, "a", NOW).unwrap().title, "A");
+    assert_eq!(cache.get(FAKE_USER_ID, "c", NOW).unwrap().title, "C");
+    assert_eq!(cache.stats().evictions, 1);
+    assert_eq!(cache.stats().entries, 2);
+}
+
+#[tokio::test]
+async fn expired_entry_is_a_miss() {
+    let cache = NewsCacheT::new(10, 60);
+    cache.insert(FAKE_USER_ID, "a".into(), news("A"), NOW).await;
+
+    assert!(cache.get(FAKE_USER_ID, "a", NOW + 59).is_some());
+    assert!(cache.get(FAKE_USER_ID, "a", NOW + 60).is_none());
+
+    assert_eq!(
+        cache.stats(),
+        NewsCacheStats {
+            hits: 1,
+            misses: 1,
+            evictions: 0,
+            expirations: 1,
+            entries: 0,
+            capacity: 10,
+        }
+    );
+}
+
+#[tokio::test]
+async fn zero_capacity_caches_nothing() {
+    let cache = NewsCacheT::new(0, 60);
+    cache.insert(FAKE_USER_ID, "a".into(), news("A"), NOW).await;
+
+    assert!(cache.get(FAKE_USER_ID, "a", NOW).is_none());
+}
+
+#[tokio::test]
+async fn persisted_cache_survives_a_restart() {
+    let path = std::env::temp_dir().join(format!("news-cache-{}.jsonl", uuid::Uuid::new_v4()));
+
+    let cache = NewsCacheT::new(2, 60).persisted(&path, NOW).await.unwrap();
+    cache
+        .insert(FAKE_USER_ID, "a".into(), news("A"), NOW - 100)
+        .await;
+    cache
+        .insert(FAKE_USER_ID, "b".into(), news("B lama"), NOW)
+        .await;
+    cache.insert(FAKE_USER_ID, "b".into(), news("B"), NOW).await;
+    cache.insert(FAKE_USER_ID, "c".into(), news("C"), NOW).await;
+    drop(cache);
+
+    let reopened = NewsCacheT::new(2, 60)
+        .persisted(&path, NOW + 1)
+        .await
+        .unwrap();
+
+    assert!(reopened.get(FAKE_USER_ID, "a", NOW + 1).is_none());
+    assert_eq!(reopened.get(FAKE_USER_ID, "b", NOW + 1).unwrap().title, "B");
+    assert_eq!(reopened.get(FAKE_USER_ID, "c", NOW + 1).unwrap().title, "C");
+    std::fs::remove_file(path).unwrap();
+}
+#[tokio::test]
+async fn persisted_log_is_compacted_as_it_grows() {
+    let path = std::env::temp_dir().join(format!("news-cache-{}.jsonl", uuid::Uuid::new_v4()));
+
+    let cache = NewsCacheT::new(1, 60).persisted(&path, NOW).await.unwrap();
+    for title in ["A", "B", "C", "D"] {
+        cache
+            .insert(FAKE_USER_ID, "a".into(), news(title), NOW)
+            .await;
+    }
+    drop(cache);
+
+    assert!(std::fs::read_to_string(&path).unwrap().lines().count() <= 2);
+    let reopened = NewsCacheT::new(1, 60).persisted(&path, NOW).await.unwrap();
+    assert_eq!(reopened.get(FAKE_USER_ID, "a", NOW).unwrap().title, "D");
+    std::fs::remove_file(path).unwrap();
+}
+// -- ENDBLOCK: LRU_TTL
+
+// -- BLOCK: CACHING_BACKEND
+#[tokio::test]
+async fn url_variants_are_parsed_once() {
+    let fake = FakeBackend::start().await;
+    let cache: NewsCache = Arc::new(NewsCacheT::new(10, 60));
+    let backend = cached_backend(&fake, &cache);
+
+    for news_url in [
+        "https://berita.example/beras",
+        "https://Berita.example/beras/?utm_source=wa",
+    ] {
+        assert!(analyze(backend.clone(), news_url)
+            .await
+            .contains(FAKE_TITLE));
+    }
+
+    assert_eq!(fake.requests_to(Endpoint::ParseNewsUrl).len(), 1);
+    assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+}
+
+#[tokio::test]
+async fn users_do_not_share_a_parse() {
+    let fake = FakeBackend::start().await;
+    let cache: NewsCache = Arc::new(NewsCacheT::new(10, 60));
+    let backend = cached_backend(&fake, &cache);
+    let other_user = "0b7e1c55-3d2a-4f7e-9c1d-5e6f7a8b9c0d";
+
+    for user_id in [FAKE_USER_ID, other_user, other_user] {
+        backend
+            .parse_news_url(user_id, "https://berita.example/beras")
+            .await
+            .unwrap();
+    }
+
+    let parsed = fake.requests_to(Endpoint::ParseNewsUrl);
+    assert_eq!(parsed.len(), 2);
+    assert!(parsed[0].path.contains(FAKE_USER_ID));
+    assert!(parsed[1].path.contains(other_user));
+    assert_eq!((cache.stats().hits, cache.stats().misses), (1, 2));
+    assert!(cache
+        .get(other_user, "https://berita.example/beras", unix_now())
+        .is_some());
+}
+
+#[tokio::test]
+async fn failed_parse_is_not_cached() {
+    let fake = FakeBackend::start().await;
+    fake.script_next(Endpoint::ParseNewsUrl, Scripted::Status(500));
+    let cache: NewsCache = Arc::new(NewsCacheT::new(10, 60));
+    let backend = cached_backend(&fake, &cache);
+
+    assert!(!analyze(backend.clone(), "https://berita.example/beras")
+        .await
+        .contains(FAKE_TITLE));
+    assert!(analyze(backend.clone(), "https://berita.example/beras")
+        .await
+        .contains(FAKE_TITLE));
+
+    assert_eq!(fake.requests_to(Endpoint::ParseNewsUrl).len(), 2);
+    assert_eq!(cache.stats().entries, 1);
+}
+
+const OTHER_USER: &str = "0b7e1c55-3d2a-4f7e-9c1d-5e6f7a8b9c0d";
+
+/// `a` and `b` of the fake user and `a` of another user, cached with
+/// content ids `content-a` and `content-b`.
+async fn cache_with_two_contents() -> NewsCache {
+    let cache: NewsCache = Arc::new(NewsCacheT::new(10, 60));
+    for (user_id, url) in [(FAKE_USER_ID, "a"), (FAKE_USER_ID, "b"), (OTHER_USER, "a")] {
+        let content = NewsContent {
+            id: Some(format!("content-{url}")),
+            ..news(url)
+        };
+        cache.insert(user_id, url.into(), content, unix_now()).await;
+    }
+    cache
+}
+
+fn cached_urls(cache: &NewsCache, user_id: &str) -> Vec<&'static str> {
+    ["a", "b"]
+        .into_iter()
+        .filter(|url| cache.get(user_id, url, unix_now()).is_some())
+        .collect()
+}
+
+#[tokio::test]
+async fn deleting_an_entry_evicts_its_parse() {
+    let fake = FakeBackend::start().await;
+    let cache = cache_with_two_contents().await;
+    let backend = cached_backend(&fake, &cache);
+
+    backend
+        .delete_history_entry(FAKE_USER_ID, "content-a")
+        .await
+        .unwrap();
+
+    assert_eq!(cached_urls(&cache, FAKE_USER_ID), ["b"]);
+    assert_eq!(cached_urls(&cache, OTHER_USER), ["a"]);
+}
+
+#[tokio::test]
+async fn clearing_the_history_evicts_all_parses_of_the_user() {
+    let fake = FakeBackend::start().await;
+    let cache = cache_with_two_contents().await;
+    let backend = cached_backend(&fake, &cache);
+
+    backend.clear_history(FAKE_USER_ID).await.unwrap();
+
+    assert!(cached_urls(&cache, FAKE_USER_ID).is_empty());
+    assert_eq!(cached_urls(&cache, OTHER_USER), ["a"]);
+}
+
+#[tokio::test]
+async fn pinning_an_entry_evicts_its_parse() {
+    let fake = FakeBackend::start().await;
+    let cache = cache_with_two_contents().await;
+    let backend = cached_backend(&fake, &cache);
+
+    backend
+        .pin_history_entry(FAKE_USER_ID, "content-a", true)
+        .await
+        .unwrap();
+
+    assert_eq!(cached_urls(&cache, FAKE_USER_ID), ["b"]);
+    assert_eq!(cached_urls(&cache, OTHER_USER), ["a"]);
+}
+
+#[tokio::test]
+async fn noting_an_entry_evicts_its_parse() {
+    let fake = FakeBackend::start().await;
+    let cache = cache_with_two_contents().await;
+    let backend = cached_backend(&fake, &cache);
+
+    backend
+        .set_history_note(FAKE_USER_ID, "content-b", Some("penting"))
+        .await
+        .unwrap();
+
+    assert_eq!(cached_urls(&cache, FAKE_USER_ID), ["a"]);
+    assert_eq!(cached_urls(&cache, OTHER_USER), ["a"]);
+}
+
+#[tokio::test]
+async fn failed_edit_keeps_the_parse() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::DeleteHistoryEntry, Scripted::Status(404));
+    let cache = cache_with_two_contents().await;
+    let backend = cached_backend(&fake, &cache);
+
+    assert!(backend
+        .delete_history_entry(FAKE_USER_ID, "content-a")
+        .await
+        .is_err());
+
+    assert_eq!(cached_urls(&cache, FAKE_USER_ID), ["a", "b"]);
+}
+
+#[tokio::test]
+async fn deleted_entry_is_parsed_again_after_a_restart() {
+    let path = std::env::temp_dir().join(format!("news-cache-{}.jsonl", uuid::Uuid::new_v4()));
+    let fake = FakeBackend::start().await;
+    let cache: NewsCache = Arc::new(
+        NewsCacheT::new(10, 60)
+            .persisted(&path, unix_now())
+            .await
+            .unwrap(),
+    );
+    let backend = cached_backend(&fake, &cache);
+    analyze(backend.clone(), "https://berita.example/beras").await;
+
+    backend
+        .delete_history_entry(FAKE_USER_ID, FAKE_CONTENT_ID)
+        .await
+        .unwrap();
+    drop(backend);
+    drop(cache);
+    let reopened = NewsCacheT::new(10, 60)
+        .persisted(&path, unix_now())
+        .await
+        .unwrap();
+
+    assert_eq!(reopened.stats().entries, 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn metrics_route_reports_the_counters() {
+    let fake = FakeBackend::start().await;
+    let cache: NewsCache = Arc::new(NewsCacheT::new(10, 60));
+    let backend = cached_backend(&fake, &cache);
+    analyze(backend.clone(), "https://berita.example/beras").await;
+    analyze(backend.clone(), "https://berita.example/beras").await;
+
+    let res = warp::test::request()
+        .path("/metrics/news-cache")
+        .reply(&monitoring_routes(cache.clone()))
+        .await;
+
+    assert_eq!(res.status(), 200);
+    let stats: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(stats["hits"], 1);
+    assert_eq!(stats["misses"], 1);
+    assert_eq!(stats["entries"], 1);
+    assert_eq!(stats["capacity"], 10);
+}
+// -- ENDBLOCK: CACHING_BACKEND
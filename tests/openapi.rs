@@ -0,0 +1,199 @@
+mod support;
+
+use std::collections::BTreeSet;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use support::*;
+use warptest::app::core::error::ErrorMessage;
+use warptest::app::core::models::{
+    ApiTokenInfo, CreatedApiToken, NewsContent, PublicUserCred, PublicUserWithId,
+};
+use warptest::app::core::openapi::schema_generator;
+
+fn keys(value: &Value) -> BTreeSet<String> {
+    value.as_object().unwrap().keys().cloned().collect()
+}
+
+/// The schema of `T` lists exactly the fields serde writes for `sample`.
+fn assert_schema_matches<T: JsonSchema + Serialize>(sample: &T) {
+    let written = serde_json::to_value(sample).unwrap();
+    let schema = schema_generator().into_root_schema_for::<T>().to_value();
+    let name = T::schema_name();
+    assert_eq!(keys(&schema["properties"]), keys(&written), "{name}");
+    let required = schema["required"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|name| name.as_str().unwrap().to_string())
+        .collect::<BTreeSet<_>>();
+    assert!(required.is_subset(&keys(&written)), "{name}");
+}
+
+/// `/v1/openapi.json` as served.
+async fn served_document() -> Value {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+    let res = warp::test::request()
+        .path("/v1/openapi.json")
+        .reply(&app)
+        .await;
+    assert_eq!(res.status(), 200);
+    serde_json::from_slice(res.body()).unwrap()
+}
+
+// -- BLOCK: DOCUMENT
+#[tokio::test]
+async fn openapi_document_is_served_without_auth() {
+    let doc = served_document().await;
+
+    assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+    assert_eq!(
+        doc["paths"]["/v1/analyze"]["get"]["responses"]["200"]["content"]["application/json"]
+            ["schema"]["$ref"],
+        "#/components/schemas/NewsContent"
+    );
+    assert_eq!(
+        doc["paths"]["/v1/me/history/{id}/pin"]["put"]["parameters"][0]["name"],
+        "id"
+    );
+    let url = &doc["paths"]["/v1/analyze"]["get"]["parameters"][0];
+    assert_eq!(url["name"], "url");
+    assert_eq!(url["in"], "query");
+    assert_eq!(url["required"], true);
+    assert_eq!(
+        doc["components"]["schemas"]["NewApiToken"]["properties"]["name"]["maxLength"],
+        64
+    );
+}
+
+#[tokio::test]
+async fn every_schema_reference_resolves() {
+    let doc = served_document().await;
+    let schemas = doc["components"]["schemas"].as_object().unwrap();
+
+    fn refs(value: &Value, found: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    found.push(target.clone());
+                }
+                map.values().for_each(|v| refs(v, found));
+            }
+            Value::Array(items) => items.iter().for_each(|v| refs(v, found)),
+            _ => {}
+        }
+    }
+    let mut found = Vec::new();
+    refs(&doc, &mut found);
+
+    assert!(!found.is_empty());
+    for target in found {
+        let name = target.strip_prefix("#/components/schemas/").unwrap();
+        assert!(schemas.contains_key(name), "{target}");
+    }
+}
+
+#[test]
+fn schemas_match_what_serde_writes() {
+    let news = NewsContent {
+        id: Some(FAKE_CONTENT_ID.into()),
+        title: FAKE_TITLE.into(),
+        content: None,
+        authors: "Redaksi".into(),
+        publication_date: None,
+        url: "https://berita.example/beras".into(),
+        summary: None,
+        pinned: false,
+        note: None,
+    };
+    let info = ApiTokenInfo {
+        id: "t1".into(),
+        name: "cron".into(),
+        created_at: 1,
+        last_used: None,
+    };
+
+    assert_schema_matches(&news);
+    assert_schema_matches(&PublicUserCred {
+        email: "ani@contoh.id".into(),
+        password: "rahasia123".into(),
+    });
+    assert_schema_matches(&PublicUserWithId::default());
+    assert_schema_matches(&info.clone());
+    assert_schema_matches(&CreatedApiToken {
+        info,
+        token: "sgk_x".into(),
+    });
+    assert_schema_matches(&ErrorMessage {
+        code: 404,
+        message: "Not Found",
+        detail: "Halaman atau data yang dicari tidak ditemukan.",
+        instructions: vec![],
+    });
+}
+// -- ENDBLOCK: DOCUMENT
+
+// -- BLOCK: ROUTES
+/// Every documented operation reaches a route, an unknown one would be a
+/// JSON 404 or 405.
+#[tokio::test]
+async fn every_documented_operation_is_routed() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let doc = served_document().await;
+    let operations = doc["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| {
+            let item = item.as_object().unwrap();
+            item.iter()
+                .map(move |(method, operation)| (path, method, operation))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(operations.len(), 13);
+
+    for (path, method, operation) in operations {
+        let mut path = path.replace("{id}", FAKE_CONTENT_ID);
+        let query = operation["parameters"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|parameter| parameter["in"] == "query")
+            .map(|parameter| format!("{}=x", parameter["name"].as_str().unwrap()))
+            .collect::<Vec<_>>();
+        if !query.is_empty() {
+            path = format!("{path}?{}", query.join("&"));
+        }
+        let res = warp::test::request()
+            .method(&method.to_uppercase())
+            .path(&path)
+            .reply(&app)
+            .await;
+        let status = res.status().as_u16();
+        assert!(
+            status != 404 && status != 405,
+            "{method} {path} -> {status}"
+        );
+        if operation.get("security").is_some() {
+            assert_eq!(status, 401, "{method} {path}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn docs_page_renders_without_external_assets() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request().path("/v1/docs").reply(&app).await;
+
+    assert_eq!(res.status(), 200);
+    let page = body_text(&res);
+    assert!(page.contains("/v1/openapi.json"));
+    assert!(!page.contains("http://") && !page.contains("https://"));
+}
+// -- ENDBLOCK: ROUTES
@@ -0,0 +1,102 @@
+mod support;
+
+use std::convert::Infallible;
+
+use support::*;
+use warp::http::{Request, Response, Version};
+use warp::hyper::body::to_bytes;
+use warp::hyper::service::{service_fn, Service};
+use warp::hyper::Body;
+use warp::Filter;
+use warptest::app::core::request_context::{current_request_id, in_request_scope, RequestContext};
+
+// -- BLOCK: REQUEST_ID
+#[tokio::test]
+async fn request_id_reaches_the_backend_and_the_reply() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let req = Request::get("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .header("x-request-id", "req-123.abc")
+        .body(Default::default())
+        .unwrap();
+    let res = reply_in_context(app, req).await;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["x-request-id"], "req-123.abc");
+    let parsed = fake.requests_to(Endpoint::ParseNewsUrl);
+    assert_eq!(parsed[0].request_id.as_deref(), Some("req-123.abc"));
+}
+
+#[tokio::test]
+async fn unusable_request_id_is_replaced() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let body = r#"{"email":"ani@contoh.id","password":"rahasia123"}"#;
+    let req = Request::post("/v1/auth/login")
+        .header("x-request-id", "bukan id yang sah!")
+        .header("content-type", "application/json")
+        .header("content-length", body.len())
+        .body(body.into())
+        .unwrap();
+    let res = reply_in_context(app, req).await;
+
+    assert_eq!(res.status(), 200);
+    let minted = res.headers()["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(minted).is_ok(), "{minted}");
+    let logged_in = fake.requests_to(Endpoint::Login);
+    assert_eq!(logged_in[0].request_id.as_deref(), Some(minted));
+    assert!(logged_in[0].body.contains("ani@contoh.id"));
+}
+
+#[tokio::test]
+async fn spawned_tasks_keep_the_request_id() {
+    let route = warp::any().then(|| async {
+        tokio::spawn(in_request_scope(async { current_request_id() }))
+            .await
+            .unwrap()
+            .unwrap_or_default()
+    });
+    let req = Request::get("/")
+        .header("x-request-id", "tugas-1")
+        .body(Default::default())
+        .unwrap();
+    let res = reply_in_context(route, req).await;
+
+    assert_eq!(body_text(&res), "tugas-1");
+    assert_eq!(current_request_id(), None);
+}
+
+#[tokio::test]
+async fn request_reaches_the_service_as_sent() {
+    let inner = service_fn(|req: Request<Body>| async move {
+        let seen = format!(
+            "{:?} {:?} {:?}",
+            req.version(),
+            req.extensions().get::<Marker>(),
+            current_request_id()
+        );
+        Ok::<_, Infallible>(Response::new(Body::from(seen)))
+    });
+    let mut service = RequestContext::new(inner, None);
+    let mut req = Request::get("/")
+        .version(Version::HTTP_2)
+        .header("x-request-id", "tugas-2")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut().insert(Marker);
+
+    let res = service.call(req).await.unwrap();
+
+    assert_eq!(res.headers()["x-request-id"], "tugas-2");
+    let body = to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body, r#"HTTP/2.0 Some(Marker) Some("tugas-2")"#);
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Marker;
+// -- ENDBLOCK: REQUEST_ID
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use warp::Filter;
+use warptest::app::core::authenticator::{
+    add_user_to_sessions, renew_session_cookie, sign_session_id, with_cookies_session_auth,
+    with_sessions, UserSessions,
+};
+use warptest::app::core::error::redirect_on_reject;
+use warptest::app::core::session_store::{
+    unix_now, FileSessionStore, MemorySessionStore, Session, SessionPolicy, SessionStore,
+};
+
+const PARALLEL_REQUESTS: usize = 500;
+
+/// Mimics the home page: htmx loads history and submits an analysis at the
+/// same time, while other users keep logging in.
+async fn hammer(sessions: UserSessions) {
+    sessions
+        .insert(
+            "browser".into(),
+            Session::new("user".into(), unix_now() - 120),
+        )
+        .await
+        .unwrap();
+
+    let authenticated = warp::path("history")
+        .and(with_cookies_session_auth(sessions.clone()))
+        .map(|user_id: String| user_id);
+    let login = warp::path("login")
+        .and(with_sessions(sessions.clone()))
+        .and_then(|sessions: UserSessions| async move {
+            add_user_to_sessions("other".into(), sessions).await
+        });
+    let routes = renew_session_cookie(authenticated.or(login).unify(), sessions.clone())
+        .recover(redirect_on_reject);
+
+    let cookie = format!("session_id={}", sign_session_id("browser"));
+    let tasks = (0..PARALLEL_REQUESTS).map(|i| {
+        let routes = routes.clone();
+        let cookie = cookie.clone();
+        tokio::spawn(async move {
+            let path = if i % 5 == 0 { "/login" } else { "/history" };
+            warp::test::request()
+                .path(path)
+                .header("cookie", cookie)
+                .reply(&routes)
+                .await
+        })
+    });
+
+    for task in tasks.collect::<Vec<_>>() {
+        let res = task.await.unwrap();
+        assert_eq!(
+            res.status(),
+            200,
+            "request was redirected to {:?}",
+            res.headers().get("HX-Location")
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn memory_store_serves_parallel_requests_without_errors() {
+    hammer(Arc::new(MemorySessionStore::default())).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn file_store_serves_parallel_requests_without_errors() {
+    let path = std::env::temp_dir().join(format!("sessions-{}.jsonl", uuid::Uuid::new_v4()));
+    let store = FileSessionStore::open(&path, POLICY, unix_now())
+        .await
+        .unwrap();
+    hammer(Arc::new(store)).await;
+    std::fs::remove_file(path).unwrap();
+}
+
+const POLICY: SessionPolicy = SessionPolicy {
+    idle_timeout_secs: 60 * 60,
+    max_lifetime_secs: 24 * 60 * 60,
+};
+
+#[tokio::test]
+async fn file_store_drops_expired_sessions_on_open() {
+    let path = std::env::temp_dir().join(format!("sessions-{}.jsonl", uuid::Uuid::new_v4()));
+    let now = unix_now();
+    let store = FileSessionStore::open(&path, POLICY, now).await.unwrap();
+    store
+        .insert("fresh".into(), Session::new("user".into(), now))
+        .await
+        .unwrap();
+    store
+        .insert(
+            "stale".into(),
+            Session::new("user".into(), now - 2 * 60 * 60),
+        )
+        .await
+        .unwrap();
+    drop(store);
+
+    let reopened = FileSessionStore::open(&path, POLICY, now).await.unwrap();
+
+    assert!(reopened.get("fresh").await.unwrap().is_some());
+    assert!(reopened.get("stale").await.unwrap().is_none());
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert!(log.contains("fresh"));
+    assert!(!log.contains("stale"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn file_store_compacts_a_log_of_touches() {
+    let path = std::env::temp_dir().join(format!("sessions-{}.jsonl", uuid::Uuid::new_v4()));
+    let now = unix_now();
+    let store = FileSessionStore::open(&path, POLICY, now).await.unwrap();
+    store
+        .insert("browser".into(), Session::new("user".into(), now))
+        .await
+        .unwrap();
+    assert!(!store.compact().await.unwrap());
+
+    for seconds in 1..=5 {
+        store.touch("browser", now + seconds).await.unwrap();
+    }
+    assert!(store.compact().await.unwrap());
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+    store.touch("browser", now + 6).await.unwrap();
+    drop(store);
+    let reopened = FileSessionStore::open(&path, POLICY, now).await.unwrap();
+    assert_eq!(
+        reopened.get("browser").await.unwrap().unwrap().last_seen,
+        now + 6
+    );
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use warp::Filter;
+use warptest::app::core::app_config::{load_config, AppConfigT, SameSite};
+use warptest::app::core::authenticator::{
+    cookie_with_attributes_of, renew_session_cookie, session_cookie, sign_session_id, sign_value,
+    spawn_session_sweeper, with_cookies_session_auth, with_session_id, UserSessions,
+};
+use warptest::app::core::error::redirect_on_reject;
+use warptest::app::core::session_store::{unix_now, MemorySessionStore, Session, SessionPolicy};
+
+/// Short enough for the sweeper to see sessions expire within a test.
+const SHORT_POLICY: SessionPolicy = SessionPolicy {
+    idle_timeout_secs: 2,
+    max_lifetime_secs: 60,
+};
+
+/// The policy requests are checked against.
+fn configured_policy() -> SessionPolicy {
+    SessionPolicy::from_config(&load_config())
+}
+
+async fn sessions_with(session: Session) -> UserSessions {
+    let sessions: UserSessions = Arc::new(MemorySessionStore::default());
+    sessions.insert("browser".into(), session).await.unwrap();
+    sessions
+}
+
+/// `GET /history` of a session authenticated page, with sliding renewal.
+async fn request_history(
+    sessions: &UserSessions,
+) -> warp::http::Response<warp::hyper::body::Bytes> {
+    request_history_with(sessions, &sign_session_id("browser")).await
+}
+
+async fn request_history_with(
+    sessions: &UserSessions,
+    cookie_value: &str,
+) -> warp::http::Response<warp::hyper::body::Bytes> {
+    let authenticated = warp::path("history")
+        .and(with_cookies_session_auth(sessions.clone()))
+        .map(|user_id: String| user_id);
+    let routes = renew_session_cookie(authenticated, sessions.clone()).recover(redirect_on_reject);
+
+    warp::test::request()
+        .path("/history")
+        .header("cookie", format!("session_id={cookie_value}"))
+        .reply(&routes)
+        .await
+}
+
+fn session_cookie_max_age(res: &warp::http::Response<warp::hyper::body::Bytes>) -> Option<u64> {
+    res.headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with("session_id="))?
+        .split("; ")
+        .find_map(|attribute| attribute.strip_prefix("Max-Age="))?
+        .parse()
+        .ok()
+}
+
+// -- BLOCK: EXPIRY
+#[tokio::test]
+async fn idle_session_is_rejected_and_removed() {
+    let policy = configured_policy();
+    let now = unix_now();
+    let idle = Session {
+        user_id: "user".into(),
+        created_at: now - policy.idle_timeout_secs - 10,
+        last_seen: now - policy.idle_timeout_secs - 1,
+    };
+    let sessions = sessions_with(idle).await;
+
+    let res = request_history(&sessions).await;
+
+    assert_ne!(res.status(), 200);
+    assert_eq!(session_cookie_max_age(&res), None);
+    assert!(sessions.get("browser").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn session_past_its_max_lifetime_is_rejected_though_active() {
+    let policy = configured_policy();
+    let now = unix_now();
+    let old = Session {
+        user_id: "user".into(),
+        created_at: now - policy.max_lifetime_secs - 1,
+        last_seen: now,
+    };
+    let sessions = sessions_with(old).await;
+
+    let res = request_history(&sessions).await;
+
+    assert_ne!(res.status(), 200);
+    assert!(sessions.get("browser").await.unwrap().is_none());
+}
+// -- ENDBLOCK: EXPIRY
+
+// -- BLOCK: RENEWAL
+#[tokio::test]
+async fn renewal_slides_the_cookie_to_a_full_idle_timeout() {
+    let policy = configured_policy();
+    let now = unix_now();
+    let seen_earlier = Session {
+        user_id: "user".into(),
+        created_at: now - 1_000,
+        last_seen: now - 500,
+    };
+    let sessions = sessions_with(seen_earlier).await;
+
+    let res = request_history(&sessions).await;
+
+    assert_eq!(res.status(), 200);
+    let max_age = session_cookie_max_age(&res).expect("session cookie is renewed");
+    assert!(
+        max_age > policy.idle_timeout_secs - 500,
+        "cookie did not slide, Max-Age={max_age}"
+    );
+    assert!(max_age <= policy.idle_timeout_secs);
+    let touched = sessions.get("browser").await.unwrap().unwrap();
+    assert!(touched.last_seen >= now);
+}
+
+#[tokio::test]
+async fn renewal_never_outlives_the_max_lifetime() {
+    let policy = configured_policy();
+    let now = unix_now();
+    let almost_over = Session {
+        user_id: "user".into(),
+        created_at: now - policy.max_lifetime_secs + 100,
+        last_seen: now,
+    };
+    let sessions = sessions_with(almost_over).await;
+
+    let res = request_history(&sessions).await;
+
+    assert_eq!(res.status(), 200);
+    let max_age = session_cookie_max_age(&res).expect("session cookie is renewed");
+    assert!((99..=100).contains(&max_age), "Max-Age={max_age}");
+}
+// -- ENDBLOCK: RENEWAL
+
+// -- BLOCK: SWEEPER
+#[tokio::test]
+async fn sweeper_removes_expired_sessions_only() {
+    let now = unix_now();
+    let sessions: UserSessions = Arc::new(MemorySessionStore::default());
+    sessions
+        .insert("fresh".into(), Session::new("user".into(), now))
+        .await
+        .unwrap();
+    let idle = Session {
+        user_id: "user".into(),
+        created_at: now - 10,
+        last_seen: now - SHORT_POLICY.idle_timeout_secs - 1,
+    };
+    sessions.insert("idle".into(), idle).await.unwrap();
+    let old = Session {
+        user_id: "user".into(),
+        created_at: now - SHORT_POLICY.max_lifetime_secs - 1,
+        last_seen: now,
+    };
+    sessions.insert("old".into(), old).await.unwrap();
+
+    let sweeper = spawn_session_sweeper(sessions.clone(), SHORT_POLICY, Duration::from_millis(20));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(sessions.get("idle").await.unwrap().is_none());
+    assert!(sessions.get("old").await.unwrap().is_none());
+    assert!(sessions.get("fresh").await.unwrap().is_some());
+
+    // Past the idle timeout the fresh session goes too.
+    tokio::time::sleep(Duration::from_secs(SHORT_POLICY.idle_timeout_secs + 2)).await;
+    assert!(sessions.get("fresh").await.unwrap().is_none());
+    sweeper.abort();
+}
+// -- ENDBLOCK: SWEEPER
+
+// -- BLOCK: COOKIE
+/// Cookie values naming the session `browser` without our signature for it.
+fn forged_cookie_values() -> Vec<String> {
+    let signed = sign_session_id("browser");
+    let (_, signature) = signed.rsplit_once('.').unwrap();
+    vec![
+        "browser".into(),
+        format!("browser.{}", &signature[1..]),
+        format!("browser.{signature}x"),
+        sign_session_id("another").replace("another", "browser"),
+        // Signed for another cookie.
+        sign_value("csrf", "browser"),
+    ]
+}
+
+#[tokio::test]
+async fn unsigned_or_tampered_session_cookie_counts_as_missing() {
+    for forged in forged_cookie_values() {
+        let session_id = warp::test::request()
+            .header("cookie", format!("session_id={forged}"))
+            .filter(&with_session_id())
+            .await
+            .unwrap();
+        assert_eq!(session_id, None, "{forged} was accepted");
+    }
+
+    let signed = warp::test::request()
+        .header(
+            "cookie",
+            format!("session_id={}", sign_session_id("browser")),
+        )
+        .filter(&with_session_id())
+        .await
+        .unwrap();
+    assert_eq!(signed.as_deref(), Some("browser"));
+}
+
+#[tokio::test]
+async fn tampered_session_cookie_does_not_authenticate() {
+    let sessions = sessions_with(Session::new("user".into(), unix_now())).await;
+
+    for forged in forged_cookie_values() {
+        let res = request_history_with(&sessions, &forged).await;
+        assert_ne!(res.status(), 200, "{forged} was accepted");
+        assert_eq!(session_cookie_max_age(&res), None);
+    }
+    assert!(sessions.get("browser").await.unwrap().is_some());
+}
+
+#[test]
+fn session_cookie_is_http_only_same_site_and_expiring() {
+    let cookie = session_cookie("browser", 60);
+
+    assert!(cookie.starts_with(&format!("session_id={}; ", sign_session_id("browser"))));
+    let attributes = cookie.split("; ").skip(1).collect::<Vec<_>>();
+    assert!(attributes.contains(&"Path=/"));
+    assert!(attributes.contains(&"Max-Age=60"));
+    assert!(attributes.contains(&"HttpOnly"));
+    assert!(attributes.contains(&"SameSite=Lax"));
+    assert!(!attributes.contains(&"Secure"));
+}
+
+#[test]
+fn cookie_is_secure_and_strict_when_configured() {
+    let config = AppConfigT {
+        cookie_secure: true,
+        cookie_same_site: SameSite::Strict,
+        ..AppConfigT::default()
+    };
+
+    let cookie = cookie_with_attributes_of(&config, "session_id", "value", 60);
+
+    let attributes = cookie.split("; ").skip(1).collect::<Vec<_>>();
+    assert!(attributes.contains(&"Secure"));
+    assert!(attributes.contains(&"SameSite=Strict"));
+    assert!(attributes.contains(&"HttpOnly"));
+    assert!(attributes.contains(&"Max-Age=60"));
+}
+// -- ENDBLOCK: COOKIE
@@ -0,0 +1,388 @@
+mod support;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use support::*;
+use tokio::sync::oneshot;
+use tokio_stream::{Stream, StreamExt};
+use warp::hyper::body::Bytes;
+use warptest::app::core::backend_client::BackendError;
+use warptest::app::core::summary_store::{
+    FileSummaryStore, MemorySummaryStore, Summaries, SummaryStore,
+};
+use warptest::app::core::summary_stream::{proxy_summary_stream, SseDecoder, SummaryEvent};
+
+fn stream_path() -> String {
+    format!("/analyze/{FAKE_CONTENT_ID}/summary/stream")
+}
+
+// -- BLOCK: DECODER
+#[test]
+fn decoder_handles_events_split_across_chunks() {
+    let mut decoder = SseDecoder::default();
+
+    assert!(decoder.push(b"data: Harga ").is_empty());
+    assert!(decoder.push(b"beras\r\n").is_empty());
+    let events = decoder.push(b"\r\n: keep-alive\n\nevent: done\ndata: \n\n");
+
+    assert_eq!(
+        events,
+        vec![
+            SummaryEvent {
+                event: None,
+                data: "Harga beras".into()
+            },
+            SummaryEvent {
+                event: Some("done".into()),
+                data: "".into()
+            },
+        ]
+    );
+}
+
+#[test]
+fn decoder_joins_multi_line_data() {
+    let mut decoder = SseDecoder::default();
+
+    let events = decoder.push(b"event: message\ndata: baris satu\ndata: baris dua\n\n");
+
+    assert_eq!(events.len(), 1);
+    assert!(events[0].is_message());
+    assert_eq!(events[0].data, "baris satu\nbaris dua");
+}
+// -- ENDBLOCK: DECODER
+
+// -- BLOCK: PROXY_ROUTE
+#[tokio::test]
+async fn stream_relays_messages_and_done() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path(&stream_path())
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "text/event-stream");
+    let body = body_text(&res);
+    for chunk in FAKE_SUMMARY_CHUNKS {
+        assert!(body.contains(&format!("data:{chunk}\n")), "{body}");
+    }
+    assert!(body.contains("event:done\n"));
+    assert!(!body.contains("event:error"));
+
+    let summarized = fake.requests_to(Endpoint::Summarize);
+    assert!(summarized[0].path.contains(FAKE_USER_ID));
+    assert!(summarized[0].query.contains(FAKE_CONTENT_ID));
+}
+
+#[tokio::test]
+async fn stream_requires_a_session() {
+    let fake = FakeBackend::start().await;
+    let app = test_app(test_backend(&fake), test_sessions());
+
+    let res = warp::test::request().path(&stream_path()).reply(&app).await;
+
+    assert_eq!(location(&res).as_deref(), Some("/auth"));
+    assert!(fake.requests().is_empty());
+}
+
+#[tokio::test]
+async fn stream_reports_a_backend_error_as_bad_gateway() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Summarize, Scripted::Status(404));
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path(&stream_path())
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 502);
+}
+
+#[tokio::test]
+async fn stream_ending_without_done_sends_an_error_event() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Summarize, Scripted::MalformedJson);
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path(&stream_path())
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    assert_eq!(res.status(), 200);
+    assert!(body_text(&res).contains("event:error\n"));
+}
+
+#[tokio::test]
+async fn analyze_page_points_at_the_proxy_not_the_backend() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app(test_backend(&fake), sessions);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    let body = body_text(&res);
+    assert!(body.contains(&stream_path()));
+    assert!(!body.contains(&fake.base_url()));
+}
+// -- ENDBLOCK: PROXY_ROUTE
+
+// -- BLOCK: CLIENT_DISCONNECT
+/// A backend stream that never yields and reports when it is dropped.
+struct HangingBackend {
+    dropped: Option<oneshot::Sender<()>>,
+}
+
+impl Stream for HangingBackend {
+    type Item = Result<Bytes, BackendError>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Pending
+    }
+}
+
+impl Drop for HangingBackend {
+    fn drop(&mut self) {
+        if let Some(dropped) = self.dropped.take() {
+            let _ = dropped.send(());
+        }
+    }
+}
+
+#[tokio::test]
+async fn browser_disconnect_drops_the_backend_stream() {
+    let (dropped_tx, dropped_rx) = oneshot::channel();
+    let backend = HangingBackend {
+        dropped: Some(dropped_tx),
+    };
+
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    let mut browser = Box::pin(proxy_summary_stream(
+        Box::pin(backend),
+        FAKE_CONTENT_ID.into(),
+        summaries.clone(),
+    ));
+    let nothing_yet = tokio::time::timeout(Duration::from_millis(50), browser.next()).await;
+    assert!(nothing_yet.is_err());
+    drop(browser);
+
+    tokio::time::timeout(Duration::from_secs(1), dropped_rx)
+        .await
+        .expect("backend stream is dropped once the browser leaves")
+        .unwrap();
+    assert_eq!(summaries.get(FAKE_CONTENT_ID).await.unwrap(), None);
+}
+// -- ENDBLOCK: CLIENT_DISCONNECT
+
+// -- BLOCK: REPLAY
+async fn stream_body(
+    app: &(impl warp::Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible>
+          + Clone
+          + 'static),
+    cookie: &str,
+) -> String {
+    let res = warp::test::request()
+        .path(&stream_path())
+        .header("cookie", cookie)
+        .reply(app)
+        .await;
+    assert_eq!(res.status(), 200);
+    body_text(&res)
+}
+
+#[tokio::test]
+async fn finished_summary_is_stored_and_replayed_without_the_backend() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app_with(test_backend(&fake), sessions, summaries.clone());
+
+    stream_body(&app, &cookie).await;
+    let replayed = stream_body(&app, &cookie).await;
+
+    let summary = FAKE_SUMMARY_CHUNKS.concat();
+    assert_eq!(
+        summaries.get(FAKE_CONTENT_ID).await.unwrap(),
+        Some(summary.clone())
+    );
+    assert!(replayed.contains(&format!("data:{summary}\n")));
+    assert!(replayed.contains("event:done\n"));
+    assert_eq!(fake.requests_to(Endpoint::Summarize).len(), 1);
+}
+
+#[tokio::test]
+async fn unfinished_summary_is_not_stored() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::Summarize, Scripted::MalformedJson);
+    let sessions = test_sessions();
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app_with(test_backend(&fake), sessions, summaries.clone());
+
+    stream_body(&app, &cookie).await;
+
+    assert_eq!(summaries.get(FAKE_CONTENT_ID).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn empty_summary_is_not_stored() {
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    let backend = tokio_stream::iter([Ok(Bytes::from_static(b"event: done\ndata: \n\n"))]);
+
+    let events = proxy_summary_stream(Box::pin(backend), FAKE_CONTENT_ID.into(), summaries.clone())
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(summaries.get(FAKE_CONTENT_ID).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn stored_empty_summary_is_summarized_again() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    summaries
+        .put(FAKE_CONTENT_ID.into(), " ".into())
+        .await
+        .unwrap();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app_with(test_backend(&fake), sessions, summaries);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", &cookie)
+        .reply(&app)
+        .await;
+    assert!(body_text(&res).contains("new EventSource"));
+
+    stream_body(&app, &cookie).await;
+    assert_eq!(fake.requests_to(Endpoint::Summarize).len(), 1);
+}
+
+#[tokio::test]
+async fn stored_summary_is_only_replayed_from_the_own_history() {
+    let fake = FakeBackend::start().await;
+    fake.script(Endpoint::History, Scripted::Json(serde_json::json!([])));
+    let sessions = test_sessions();
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    summaries
+        .put(FAKE_CONTENT_ID.into(), "Ringkasan orang lain.".into())
+        .await
+        .unwrap();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app_with(test_backend(&fake), sessions, summaries);
+
+    let body = stream_body(&app, &cookie).await;
+
+    assert!(!body.contains("Ringkasan orang lain."));
+    assert_eq!(fake.requests_to(Endpoint::Summarize).len(), 1);
+}
+
+#[tokio::test]
+async fn analyze_renders_a_stored_summary_instead_of_streaming() {
+    let fake = FakeBackend::start().await;
+    let sessions = test_sessions();
+    let summaries: Summaries = Arc::new(MemorySummaryStore::default());
+    summaries
+        .put(FAKE_CONTENT_ID.into(), "Ringkasan tersimpan.".into())
+        .await
+        .unwrap();
+    let cookie = logged_in_cookie(&sessions).await;
+    let app = test_app_with(test_backend(&fake), sessions, summaries);
+
+    let res = warp::test::request()
+        .path("/analyze?url=https%3A%2F%2Fberita.example%2Fberas")
+        .header("cookie", cookie)
+        .reply(&app)
+        .await;
+
+    let body = body_text(&res);
+    assert!(body.contains("Ringkasan tersimpan."));
+    assert!(!body.contains("new EventSource"));
+}
+
+#[tokio::test]
+async fn file_summary_store_survives_a_restart() {
+    let path = std::env::temp_dir().join(format!("summaries-{}.jsonl", uuid::Uuid::new_v4()));
+
+    let store = FileSummaryStore::open(&path, 10).await.unwrap();
+    store
+        .put(FAKE_CONTENT_ID.into(), "Ringkasan lama.".into())
+        .await
+        .unwrap();
+    store
+        .put(FAKE_CONTENT_ID.into(), "Ringkasan baru.".into())
+        .await
+        .unwrap();
+    drop(store);
+
+    let reopened = FileSummaryStore::open(&path, 10).await.unwrap();
+    assert_eq!(
+        reopened.get(FAKE_CONTENT_ID).await.unwrap().as_deref(),
+        Some("Ringkasan baru.")
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn memory_summary_store_forgets_the_first_stored_beyond_its_capacity() {
+    let store = MemorySummaryStore::new(2);
+    for content_id in ["a", "b", "a", "c"] {
+        store
+            .put(content_id.into(), format!("Ringkasan {content_id}."))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(store.get("a").await.unwrap(), None);
+    assert!(store.get("b").await.unwrap().is_some());
+    assert!(store.get("c").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn file_summary_store_keeps_its_capacity_across_a_restart() {
+    let path = std::env::temp_dir().join(format!("summaries-{}.jsonl", uuid::Uuid::new_v4()));
+
+    let store = FileSummaryStore::open(&path, 2).await.unwrap();
+    for content_id in ["a", "b", "c", "d", "e"] {
+        store
+            .put(content_id.into(), format!("Ringkasan {content_id}."))
+            .await
+            .unwrap();
+    }
+    drop(store);
+
+    assert!(std::fs::read_to_string(&path).unwrap().lines().count() <= 4);
+    let reopened = FileSummaryStore::open(&path, 2).await.unwrap();
+    assert_eq!(reopened.get("c").await.unwrap(), None);
+    assert_eq!(
+        reopened.get("e").await.unwrap().as_deref(),
+        Some("Ringkasan e.")
+    );
+    assert!(reopened.get("d").await.unwrap().is_some());
+    std::fs::remove_file(path).unwrap();
+}
+// -- ENDBLOCK: REPLAY
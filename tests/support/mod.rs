@@ -0,0 +1,433 @@
+//! Test support: an in-process stand-in for the Python analyzer backend and
+//! the app wired to it, so routes can be exercised without localhost:8000.
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use handlebars::Handlebars;
+use serde_json::json;
+use warp::http::{HeaderMap, Request, Response, StatusCode};
+use warp::hyper::body::Bytes;
+use warp::hyper::service::Service;
+use warp::Filter;
+use warptest::app::api::routes::api_routes;
+use warptest::app::core::api_token_store::{ApiTokens, MemoryApiTokenStore};
+use warptest::app::core::app_config::{load_config, AppConfigT};
+use warptest::app::core::authenticator::{renew_session_cookie, sign_session_id, UserSessions};
+use warptest::app::core::backend_client::{Backend, ReqwestBackendClient};
+use warptest::app::core::error::{json_on_reject, recover_negotiated};
+use warptest::app::core::rate_limiter::LoginLimiterT;
+use warptest::app::core::renderer::Renderer;
+use warptest::app::core::request_context::RequestContext;
+use warptest::app::core::routes::error_routes;
+use warptest::app::core::session_store::{unix_now, MemorySessionStore, Session};
+use warptest::app::core::summary_store::{MemorySummaryStore, Summaries};
+use warptest::app::routes::app_routes;
+use warptest::register_templates_in;
+
+pub const FAKE_USER_ID: &str = "fa160d0b-2922-496e-a7b0-abc133c48ca7";
+pub const FAKE_CONTENT_ID: &str = "6f1c8a2e-1111-4c3b-9d7e-0a2b3c4d5e6f";
+pub const FAKE_TITLE: &str = "Harga beras naik menjelang lebaran";
+pub const FAKE_SUMMARY_CHUNKS: [&str; 2] = ["Harga beras ", "naik 10 persen."];
+
+/// Backend endpoints the app calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Register,
+    Login,
+    ParseNewsUrl,
+    History,
+    DeleteHistoryEntry,
+    ClearHistory,
+    UpdateHistoryEntry,
+    Summarize,
+}
+
+/// What the fake backend answers on an endpoint.
+#[derive(Debug, Clone)]
+pub enum Scripted {
+    /// The canned success body of the endpoint.
+    Success,
+    /// An empty JSON error with this status.
+    Status(u16),
+    /// `200 OK` with a body that is not JSON.
+    MalformedJson,
+    /// The success body, after waiting this long.
+    Slow(Duration),
+    /// `200 OK` with this JSON body.
+    Json(serde_json::Value),
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub endpoint: Endpoint,
+    pub path: String,
+    pub query: String,
+    pub request_id: Option<String>,
+    pub body: String,
+}
+
+#[derive(Default)]
+struct FakeState {
+    scripts: HashMap<Endpoint, Scripted>,
+    queued: HashMap<Endpoint, VecDeque<Scripted>>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// Local warp server emulating the analyzer backend under `/api/v0`, every
+/// endpoint answers [`Scripted::Success`] until scripted otherwise.
+#[derive(Clone)]
+pub struct FakeBackend {
+    addr: SocketAddr,
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeBackend {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(FakeState::default()));
+
+        let with_state = {
+            let state = state.clone();
+            warp::any().map(move || state.clone())
+        };
+        let routes = warp::path("api")
+            .and(warp::path("v0"))
+            .and(warp::method())
+            .and(warp::path::full())
+            .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .and(with_state)
+            .then(fake_reply);
+
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        FakeBackend { addr, state }
+    }
+
+    /// Value for `local_backend_api`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/api/v0", self.addr)
+    }
+
+    pub fn script(&self, endpoint: Endpoint, reply: Scripted) {
+        self.state.lock().unwrap().scripts.insert(endpoint, reply);
+    }
+
+    /// Answers the next call to `endpoint` with `reply`, before falling back
+    /// to the [`script`](Self::script)ed reply. Queued replies are used in order.
+    pub fn script_next(&self, endpoint: Endpoint, reply: Scripted) {
+        self.state
+            .lock()
+            .unwrap()
+            .queued
+            .entry(endpoint)
+            .or_default()
+            .push_back(reply);
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn requests_to(&self, endpoint: Endpoint) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|req| req.endpoint == endpoint)
+            .collect()
+    }
+}
+
+fn endpoint_of(method: &warp::http::Method, path: &str) -> Option<Endpoint> {
+    let path = path.strip_prefix("/api/v0")?;
+    let is_get = method == warp::http::Method::GET;
+    let is_post = method == warp::http::Method::POST;
+    let is_delete = method == warp::http::Method::DELETE;
+    let is_patch = method == warp::http::Method::PATCH;
+
+    match path {
+        "/users" if is_post => Some(Endpoint::Register),
+        "/users/login" if is_post => Some(Endpoint::Login),
+        _ if is_post && path.ends_with("/news-contents/parse-news-url") => {
+            Some(Endpoint::ParseNewsUrl)
+        }
+        _ if is_get && path.starts_with("/users/") && path.ends_with("/history") => {
+            Some(Endpoint::History)
+        }
+        _ if is_delete && path.starts_with("/users/") && path.ends_with("/history") => {
+            Some(Endpoint::ClearHistory)
+        }
+        _ if is_delete && path.starts_with("/users/") && path.contains("/history/") => {
+            Some(Endpoint::DeleteHistoryEntry)
+        }
+        _ if is_patch && path.starts_with("/users/") && path.contains("/history/") => {
+            Some(Endpoint::UpdateHistoryEntry)
+        }
+        _ if is_get && path.ends_with("/news_contents/summarize-news-content-stream") => {
+            Some(Endpoint::Summarize)
+        }
+        _ => None,
+    }
+}
+
+async fn fake_reply(
+    method: warp::http::Method,
+    path: warp::path::FullPath,
+    query: String,
+    headers: HeaderMap,
+    body: Bytes,
+    state: Arc<Mutex<FakeState>>,
+) -> warp::reply::Response {
+    use warp::Reply;
+
+    let Some(endpoint) = endpoint_of(&method, path.as_str()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let scripted = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(RecordedRequest {
+            endpoint,
+            path: path.as_str().to_string(),
+            query,
+            request_id: headers
+                .get("x-request-id")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            body: String::from_utf8_lossy(&body).into_owned(),
+        });
+        match state
+            .queued
+            .get_mut(&endpoint)
+            .and_then(VecDeque::pop_front)
+        {
+            Some(queued) => queued,
+            None => state
+                .scripts
+                .get(&endpoint)
+                .cloned()
+                .unwrap_or(Scripted::Success),
+        }
+    };
+
+    match scripted {
+        Scripted::Success => success_reply(endpoint, &body),
+        Scripted::Slow(delay) => {
+            tokio::time::sleep(delay).await;
+            success_reply(endpoint, &body)
+        }
+        Scripted::Status(code) => warp::reply::with_status(
+            warp::reply::json(&json!({ "detail": "scripted failure" })),
+            StatusCode::from_u16(code).expect("scripted status is valid"),
+        )
+        .into_response(),
+        Scripted::Json(value) => warp::reply::json(&value).into_response(),
+        Scripted::MalformedJson => {
+            warp::reply::with_header("{\"id\": \"oops\", ", "content-type", "application/json")
+                .into_response()
+        }
+    }
+}
+
+pub fn fake_news_content() -> serde_json::Value {
+    json!({
+        "id": FAKE_CONTENT_ID,
+        "title": FAKE_TITLE,
+        "content": "Harga beras di pasar induk naik sepuluh persen.",
+        "authors": "Redaksi",
+        "publication_date": "2025-03-01",
+        "url": "https://berita.example/beras",
+        "summary": null
+    })
+}
+
+fn success_reply(endpoint: Endpoint, body: &[u8]) -> warp::reply::Response {
+    use warp::Reply;
+
+    match endpoint {
+        Endpoint::Register | Endpoint::Login => {
+            let email = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|cred| cred["email"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            warp::reply::json(&json!({
+                "id": FAKE_USER_ID,
+                "email": email,
+                "password": null
+            }))
+            .into_response()
+        }
+        Endpoint::ParseNewsUrl => warp::reply::json(&fake_news_content()).into_response(),
+        Endpoint::History => warp::reply::json(&json!([fake_news_content()])).into_response(),
+        Endpoint::DeleteHistoryEntry | Endpoint::ClearHistory | Endpoint::UpdateHistoryEntry => {
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Endpoint::Summarize => {
+            let mut events = FAKE_SUMMARY_CHUNKS
+                .iter()
+                .map(|chunk| format!("data: {chunk}\n\n"))
+                .collect::<String>();
+            events.push_str("event: done\ndata: \n\n");
+            warp::reply::with_header(events, "content-type", "text/event-stream").into_response()
+        }
+    }
+}
+
+// -- BLOCK: TEST_APP
+pub fn test_renderer() -> Renderer {
+    let mut hb = Handlebars::new();
+    register_templates_in(&mut hb, concat!(env!("CARGO_MANIFEST_DIR"), "/src"));
+    Arc::new(hb)
+}
+
+/// Default config pointed at `fake`, with retries that do not slow tests down.
+pub fn test_config(fake: &FakeBackend) -> AppConfigT {
+    AppConfigT {
+        local_backend_api: fake.base_url(),
+        backend_retry_base_delay_ms: 1,
+        ..AppConfigT::default()
+    }
+}
+
+pub fn test_backend(fake: &FakeBackend) -> Backend {
+    test_backend_with(&test_config(fake))
+}
+
+pub fn test_backend_with(config: &AppConfigT) -> Backend {
+    Arc::new(ReqwestBackendClient::new(config).unwrap())
+}
+
+pub fn test_sessions() -> UserSessions {
+    Arc::new(MemorySessionStore::default())
+}
+
+/// The app as composed in `main`, minus assets.
+pub fn test_app(
+    backend: Backend,
+    sessions: UserSessions,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Infallible> + Clone {
+    test_app_with(backend, sessions, Arc::new(MemorySummaryStore::default()))
+}
+
+pub fn test_app_with(
+    backend: Backend,
+    sessions: UserSessions,
+    summaries: Summaries,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Infallible> + Clone {
+    let renderer = test_renderer();
+    let login_limiter = Arc::new(LoginLimiterT::from_config(&load_config()));
+    let tokens: ApiTokens = Arc::new(MemoryApiTokenStore::default());
+    let api_route = warp::path("v1").and(
+        renew_session_cookie(
+            api_routes(
+                backend.clone(),
+                sessions.clone(),
+                tokens.clone(),
+                summaries.clone(),
+                login_limiter.clone(),
+            ),
+            sessions.clone(),
+        )
+        .recover(json_on_reject),
+    );
+
+    let routes = api_route
+        .or(renew_session_cookie(
+            app_routes(
+                renderer.clone(),
+                backend,
+                sessions.clone(),
+                tokens,
+                summaries,
+                login_limiter,
+            ),
+            sessions,
+        ))
+        .or(error_routes(renderer.clone()))
+        .boxed();
+    recover_negotiated(routes, renderer)
+}
+
+/// Sends `req` to `app` served as in `main`, inside a [`RequestContext`].
+pub async fn reply_in_context<F, R>(app: F, req: Request<hyper::Body>) -> Response<Bytes>
+where
+    F: Filter<Extract = (R,), Error = Infallible> + Clone + Send + Sync + 'static,
+    R: warp::Reply,
+{
+    let mut service = RequestContext::new(warp::service(app), None);
+    let res = match service.call(req).await {
+        Ok(res) => res,
+        Err(infallible) => match infallible {},
+    };
+    let (parts, body) = res.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap();
+    Response::from_parts(parts, body)
+}
+
+/// Stores a live session for [`FAKE_USER_ID`] and returns its `Cookie` header.
+pub async fn logged_in_cookie(sessions: &UserSessions) -> String {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    sessions
+        .insert(
+            session_id.clone(),
+            Session::new(FAKE_USER_ID.into(), unix_now()),
+        )
+        .await
+        .unwrap();
+    format!("session_id={}", sign_session_id(&session_id))
+}
+
+/// Opens `/home` with `session_cookie` and returns the `Cookie` header
+/// carrying both cookies, and the token for the `X-CSRF-Token` header.
+pub async fn with_csrf<F>(app: &F, session_cookie: &str) -> (String, String)
+where
+    F: Filter + 'static,
+    F::Extract: warp::Reply + Send,
+{
+    let res = warp::test::request()
+        .path("/home")
+        .header("cookie", session_cookie)
+        .reply(app)
+        .await;
+    let csrf_cookie = set_cookie(&res, "csrf_token").expect("home page sets the csrf cookie");
+    let token = csrf_cookie.trim_start_matches("csrf_token=").to_string();
+    (format!("{session_cookie}; {csrf_cookie}"), token)
+}
+// -- ENDBLOCK: TEST_APP
+
+// -- BLOCK: RESPONSE_HELPERS
+/// `name=value` of the `Set-Cookie` header setting `name`.
+pub fn set_cookie<B>(res: &warp::http::Response<B>, name: &str) -> Option<String> {
+    res.headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find(|v| v.starts_with(&format!("{name}=")))
+        .and_then(|v| v.split(';').next())
+        .map(str::to_string)
+}
+
+pub fn location<B>(res: &warp::http::Response<B>) -> Option<String> {
+    res.headers()
+        .get("location")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The error page of a redirect, without its `request_id` query.
+pub fn error_page<B>(res: &warp::http::Response<B>) -> Option<String> {
+    location(res).map(|location| match location.split_once('?') {
+        Some((page, _)) => page.to_string(),
+        None => location,
+    })
+}
+
+pub fn body_text(res: &warp::http::Response<Bytes>) -> String {
+    String::from_utf8_lossy(res.body()).into_owned()
+}
+// -- ENDBLOCK: RESPONSE_HELPERS